@@ -1,24 +1,183 @@
-use account::{Account, Error as AccError};
-use storage::{AccountStorage, Error as StorageError, TransactionAction, TransactionStorage};
-use transactions::Transaction;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+use account::{
+    currency_tag, Account, AccountSort, Error as AccError, RestoreFailure, TransferOptions,
+};
+use fee::{FeeLedger, FeePolicy, FlatFee};
+use snapshot::{BankSnapshot, BankState};
+use storage::{
+    set_currency_balance, AccountStorage, AccountTransfer, ActionKind, Error as StorageError,
+    ExternalDirection, TransactionAction, TransactionId, TransactionStorage, TransactionTransfer,
+    DEFAULT_CURRENCY,
+};
+use transactions::{
+    Aggregates, BalanceDiscrepancy, IntegrityReport, NetTransaction, StatementLine, Transaction,
+};
 
 pub mod account;
+pub mod fee;
 pub mod implements;
+pub mod snapshot;
 pub mod storage;
 pub mod transactions;
 
+// max account name length in bytes (shared by the bank and protocol boundaries)
+pub const MAX_ACCOUNT_NAME_LEN: usize = 64;
+
+// checks a name can be used for a new account
+// (not empty or whitespace only, not too long, not the reserved fee account name)
+// errors: InvalidAccountName
+pub fn validate_account_name(account_name: &str, fee_acc_name: &str) -> Result<(), AccError> {
+    if account_name.trim().is_empty()
+        || account_name.len() > MAX_ACCOUNT_NAME_LEN
+        || account_name == fee_acc_name
+    {
+        return Err(AccError::InvalidAccountName);
+    }
+    Ok(())
+}
+
+// balance * rate_bps / 10000 rounded down
+// errors: Storage (balance overflow)
+fn interest_on(balance: usize, rate_bps: u32) -> Result<usize, AccError> {
+    usize::try_from(balance as u128 * rate_bps as u128 / 10000)
+        .map_err(|_| AccError::Storage("balance overflow".to_string()))
+}
+
+// adds value to a running total
+// errors: Storage (total overflow)
+fn add_to_total(total: &mut usize, value: usize) -> Result<(), AccError> {
+    *total = total
+        .checked_add(value)
+        .ok_or_else(|| AccError::Storage("total overflow".to_string()))?;
+    Ok(())
+}
+
+// action column of csv exports
+fn csv_action_name(action: &TransactionAction) -> &'static str {
+    match action {
+        TransactionAction::Registration => "registration",
+        TransactionAction::Increment(_) => "increment",
+        TransactionAction::Decrement(_) => "decrement",
+        TransactionAction::TransferOut { .. } => "transfer_out",
+        TransactionAction::TransferIn { .. } => "transfer_in",
+        TransactionAction::External {
+            direction: ExternalDirection::Deposit,
+            ..
+        } => "external_deposit",
+        TransactionAction::External {
+            direction: ExternalDirection::Withdrawal,
+            ..
+        } => "external_withdrawal",
+        TransactionAction::Hold(_) => "hold",
+        TransactionAction::Release { .. } => "release",
+        TransactionAction::Reversal { .. } => "reversal",
+        TransactionAction::Renamed { .. } => "renamed",
+        TransactionAction::Merged { .. } => "merged",
+        TransactionAction::Closed => "closed",
+        TransactionAction::Fee { .. } => "fee",
+    }
+}
+
+// amount column of csv exports (transfer value without the fee)
+fn csv_amount(action: &TransactionAction) -> usize {
+    match action {
+        TransactionAction::Registration
+        | TransactionAction::Reversal { .. }
+        | TransactionAction::Renamed { .. }
+        | TransactionAction::Merged { .. }
+        | TransactionAction::Closed => 0,
+        TransactionAction::Increment(amount)
+        | TransactionAction::Decrement(amount)
+        | TransactionAction::External { amount, .. }
+        | TransactionAction::Hold(amount)
+        | TransactionAction::Release { amount, .. }
+        | TransactionAction::Fee { amount, .. } => *amount,
+        TransactionAction::TransferOut { value, .. }
+        | TransactionAction::TransferIn { value, .. } => *value,
+    }
+}
+
+// fee column of csv exports (empty for actions without a fee)
+fn csv_fee(action: &TransactionAction) -> String {
+    match action {
+        TransactionAction::TransferOut { fee, .. } => fee.to_string(),
+        _ => String::new(),
+    }
+}
+
+// quotes a csv field containing a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
 pub struct Bank<A: AccountStorage, T: TransactionStorage> {
     acc_storage: A,
     tr_storage: T,
-    tr_fee: usize,
+    fee_policy: Box<dyn FeePolicy>,
+    // idempotency key -> transfer made with it
+    idempotency_keys: HashMap<String, IdempotentTransfer>,
+    // largest single deposit accepted (None - no limit)
+    max_deposit: Option<usize>,
+}
+
+// transfer remembered by its idempotency key
+struct IdempotentTransfer {
+    from: String,
+    to: String,
+    value: usize,
+    currency: String,
+    tr_id: TransactionId,
 }
 
 impl<A: AccountStorage, T: TransactionStorage> Bank<A, T> {
-    pub fn new(acc_storage: A, tr_storage: T, tr_fee: Option<usize>) -> Self {
+    // fee policy defaults to FlatFee(0)
+    pub fn new(acc_storage: A, tr_storage: T, fee_policy: Option<Box<dyn FeePolicy>>) -> Self {
         Bank {
             acc_storage,
             tr_storage,
-            tr_fee: tr_fee.unwrap_or(0),
+            fee_policy: fee_policy.unwrap_or_else(|| Box::new(FlatFee(0))),
+            idempotency_keys: HashMap::new(),
+            max_deposit: None,
+        }
+    }
+
+    // gives the storages back (e.g. to inspect or reuse them after the bank is done)
+    pub fn into_storages(self) -> (A, T) {
+        (self.acc_storage, self.tr_storage)
+    }
+
+    // replaces the fee policy; only transfers made after the call are affected
+    pub fn set_fee_policy(&mut self, fee_policy: Box<dyn FeePolicy>) {
+        self.fee_policy = fee_policy;
+    }
+
+    // switches to a flat fee per transfer
+    pub fn set_fee(&mut self, fee: usize) {
+        self.set_fee_policy(Box::new(FlatFee(fee)));
+    }
+
+    // fee the current policy charges for a transfer of `amount`
+    pub fn fee(&self, amount: usize) -> usize {
+        self.fee_policy.fee(amount)
+    }
+
+    // limits a single deposit (increment or external deposit, interest is not a deposit),
+    // None removes the limit
+    pub fn set_max_deposit(&mut self, max_deposit: Option<usize>) {
+        self.max_deposit = max_deposit;
+    }
+
+    // errors: AmountTooLarge
+    fn check_deposit(&self, value: usize) -> Result<(), AccError> {
+        match self.max_deposit {
+            Some(max) if value > max => Err(AccError::AmountTooLarge),
+            _ => Ok(()),
         }
     }
     pub fn accounts(&self) -> Result<Vec<Account>, AccError> {
@@ -31,90 +190,6035 @@ impl<A: AccountStorage, T: TransactionStorage> Bank<A, T> {
         Ok(accs)
     }
 
+    // a sorted page of accounts, the fee account is listed only if include_fee is set
+    // errors: Storage
+    pub fn accounts_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+        sort_by: AccountSort,
+        include_fee: bool,
+    ) -> Result<Vec<Account>, AccError> {
+        let fee_acc_name = self.acc_storage.fee_account()?.name;
+        let mut accs = self
+            .acc_storage
+            .accounts()?
+            .into_iter()
+            .filter(|acc| include_fee || acc.name != fee_acc_name)
+            .map(Account::from)
+            .collect::<Vec<Account>>();
+        match sort_by {
+            AccountSort::NameAsc => accs.sort_by(|a, b| a.name.cmp(&b.name)),
+            AccountSort::BalanceDesc => {
+                accs.sort_by(|a, b| b.balance().cmp(&a.balance()).then(a.name.cmp(&b.name)))
+            }
+            AccountSort::BalanceAsc => {
+                accs.sort_by(|a, b| a.balance().cmp(&b.balance()).then(a.name.cmp(&b.name)))
+            }
+        }
+        Ok(accs.into_iter().skip(offset).take(limit).collect())
+    }
+
+    // errors: InvalidAccountName (incl. the name of a closed account),
+    // AccountAlreadyExists, Storage
     pub fn create_account(&mut self, account_name: String) -> Result<Account, AccError> {
+        validate_account_name(&account_name, &self.acc_storage.fee_account()?.name)?;
+        if !self.account_exists(account_name.clone())? {
+            self.check_no_history(&account_name)?;
+        }
         Account::new(account_name, &mut self.acc_storage, &mut self.tr_storage)
     }
 
-    pub fn inc_acc_balance(&mut self, acc: &mut Account, value: usize) -> Result<usize, AccError> {
-        acc.inc_balance(value, &mut self.acc_storage, &mut self.tr_storage)
+    // a closed account leaves its history under its name, a new account with
+    // that name would take it over
+    // errors: InvalidAccountName, Storage
+    fn check_no_history(&self, account_name: &str) -> Result<(), AccError> {
+        if self
+            .tr_storage
+            .account_transaction_count(account_name.to_string())?
+            > 0
+        {
+            return Err(AccError::InvalidAccountName);
+        }
+        Ok(())
+    }
+
+    // create-or-get: returns the existing account instead of AccountAlreadyExists;
+    // the flag is true if the account was created by this call
+    // errors: InvalidAccountName, Storage
+    pub fn get_or_create_account(
+        &mut self,
+        account_name: String,
+    ) -> Result<(Account, bool), AccError> {
+        match self.create_account(account_name.clone()) {
+            Ok(acc) => Ok((acc, true)),
+            Err(AccError::AccountAlreadyExists) => Ok((self.account(account_name)?, false)),
+            Err(err) => Err(err),
+        }
+    }
+
+    // creates accounts one by one, a failed name doesn't abort the batch
+    // returns a result per name in the input order
+    pub fn create_accounts(&mut self, names: Vec<String>) -> Vec<Result<Account, AccError>> {
+        names
+            .into_iter()
+            .map(|name| self.create_account(name))
+            .collect()
+    }
+
+    // creates an account with an initial deposit recorded as an increment
+    // (so replaying the log gives the same balance)
+    // errors: AmountTooLarge, InvalidAccountName, AccountAlreadyExists, Storage
+    pub fn create_account_with_balance(
+        &mut self,
+        account_name: String,
+        balance: usize,
+    ) -> Result<Account, AccError> {
+        self.check_deposit(balance)?;
+        let mut acc = self.create_account(account_name)?;
+        if balance > 0 {
+            self.inc_acc_balance(&mut acc, balance)?;
+        }
+        Ok(acc)
+    }
+
+    // total fees collected so far in the default currency (balance of the fee account)
+    // errors: Storage
+    pub fn fee_balance(&self) -> Result<usize, AccError> {
+        self.fee_balance_in(DEFAULT_CURRENCY.to_string())
+    }
+
+    // total fees collected so far in `currency`
+    // errors: Storage
+    pub fn fee_balance_in(&self, currency: String) -> Result<usize, AccError> {
+        Ok(self.acc_storage.fee_account()?.balance_in(&currency))
+    }
+
+    // accounts whose name starts with prefix sorted by name, at most limit
+    // (the fee account is never returned)
+    // errors: Storage
+    pub fn find_accounts_by_prefix(
+        &self,
+        prefix: String,
+        limit: usize,
+    ) -> Result<Vec<Account>, AccError> {
+        let fee_acc_name = self.acc_storage.fee_account()?.name;
+        let mut found = Vec::new();
+        self.acc_storage.for_each_account(&mut |acc| {
+            if acc.name.starts_with(&prefix) && acc.name != fee_acc_name {
+                found.push(acc.clone());
+            }
+            Ok(())
+        })?;
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(found.into_iter().take(limit).map(Account::from).collect())
+    }
+
+    // accounts with default currency balance > threshold, richest first (ties by name)
+    // (the fee account is never returned)
+    // errors: Storage
+    pub fn accounts_with_balance_over(&self, threshold: usize) -> Result<Vec<Account>, AccError> {
+        let fee_acc_name = self.acc_storage.fee_account()?.name;
+        let mut found = Vec::new();
+        self.acc_storage.for_each_account(&mut |acc| {
+            if acc.balance() > threshold && acc.name != fee_acc_name {
+                found.push(acc.clone());
+            }
+            Ok(())
+        })?;
+        found.sort_by(|a, b| {
+            b.balance()
+                .cmp(&a.balance())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        Ok(found.into_iter().map(Account::from).collect())
+    }
+
+    // number of accounts, optionally counting the fee account
+    // errors: Storage
+    pub fn accounts_count(&self, include_fee: bool) -> Result<usize, AccError> {
+        let count = self.acc_storage.count()?;
+        if include_fee {
+            Ok(count)
+        } else {
+            Ok(count.saturating_sub(1))
+        }
+    }
+
+    // FNV-1a hash of (name, balance) of every account (fee account included) sorted
+    // by name, followed by its other currency balances sorted by currency; equal
+    // balances give equal hashes for any storage or build
+    // errors: Storage
+    pub fn state_hash(&self) -> Result<u64, AccError> {
+        let mut accs = self.acc_storage.accounts()?;
+        accs.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        for acc in accs {
+            feed(acc.name.as_bytes());
+            // never part of utf-8 text, separates the name from the balance
+            feed(&[0xff]);
+            feed(&(acc.balance() as u64).to_le_bytes());
+            let mut others = acc
+                .balances
+                .iter()
+                .filter(|(currency, _)| *currency != DEFAULT_CURRENCY)
+                .collect::<Vec<_>>();
+            others.sort();
+            for (currency, balance) in others {
+                feed(&[0xff]);
+                feed(currency.as_bytes());
+                feed(&[0xff]);
+                feed(&(*balance as u64).to_le_bytes());
+            }
+        }
+        Ok(hash)
+    }
+
+    // checks an account exists without creating it
+    // errors: Storage
+    pub fn account_exists(&self, account_name: String) -> Result<bool, AccError> {
+        match self.acc_storage.get_account(account_name) {
+            Ok(_) => Ok(true),
+            Err(StorageError::AccountNotExists) => Ok(false),
+            Err(err) => Err(AccError::from(err)),
+        }
+    }
+
+    // errors: AccountNotExists, Storage
+    pub fn account(&self, account_name: String) -> Result<Account, AccError> {
+        Ok(Account::from(self.acc_storage.get_account(account_name)?))
+    }
+
+    // renames an account and rewrites its transactions and transfer
+    // counterparties so the history stays consistent; a Renamed marker is logged
+    // so replay_onto can rename the account of a replica
+    // errors: InvalidAccountName (incl. the fee account and the name of a closed
+    // account), AccountAlreadyExists, AccountNotExists, Storage
+    pub fn rename_account(&mut self, old: String, new: String) -> Result<(), AccError> {
+        let fee_acc_name = self.acc_storage.fee_account()?.name;
+        validate_account_name(&new, &fee_acc_name)?;
+        if old == fee_acc_name {
+            return Err(AccError::InvalidAccountName);
+        }
+        if !self.account_exists(new.clone())? {
+            self.check_no_history(&new)?;
+        }
+        self.acc_storage.rename_account(old.clone(), new.clone())?;
+        self.tr_storage
+            .rename_account_references(old.clone(), new.clone())?;
+        self.rename_idempotency_references(&old, &new);
+        self.tr_storage
+            .create_transaction(new, TransactionAction::Renamed { from: old })?;
+        Ok(())
+    }
+
+    // blocks all debits and credits of the account (reads still work)
+    // errors: InvalidAccountName (fee account), AccountNotExists, Storage
+    pub fn freeze_account(&mut self, account_name: String) -> Result<(), AccError> {
+        self.set_frozen(account_name, true)
+    }
+
+    // errors: InvalidAccountName (fee account), AccountNotExists, Storage
+    pub fn unfreeze_account(&mut self, account_name: String) -> Result<(), AccError> {
+        self.set_frozen(account_name, false)
+    }
+
+    fn set_frozen(&mut self, account_name: String, frozen: bool) -> Result<(), AccError> {
+        if account_name == self.acc_storage.fee_account()?.name {
+            return Err(AccError::InvalidAccountName);
+        }
+        let mut acc = self.acc_storage.get_account(account_name)?;
+        acc.frozen = frozen;
+        self.acc_storage.update_account(acc)?;
+        Ok(())
+    }
+
+    // caps the total value the account can send by transfers (None removes the cap)
+    // errors: AccountNotExists, Storage
+    pub fn set_transfer_limit(
+        &mut self,
+        account_name: String,
+        limit: Option<usize>,
+    ) -> Result<(), AccError> {
+        let mut acc = self.acc_storage.get_account(account_name)?;
+        acc.transfer_limit = limit;
+        self.acc_storage.update_account(acc)?;
+        Ok(())
+    }
+
+    // starts counting transfers towards the limit from zero
+    // errors: AccountNotExists, Storage
+    pub fn reset_transfer_usage(&mut self, account_name: String) -> Result<(), AccError> {
+        let mut acc = self.acc_storage.get_account(account_name)?;
+        acc.transferred = 0;
+        self.acc_storage.update_account(acc)?;
+        Ok(())
+    }
+
+    // points remembered idempotent transfers of `old` to `new`
+    fn rename_idempotency_references(&mut self, old: &str, new: &str) {
+        for transfer in self.idempotency_keys.values_mut() {
+            if transfer.from == old {
+                transfer.from = new.to_string();
+            }
+            if transfer.to == old {
+                transfer.to = new.to_string();
+            }
+        }
+    }
+
+    // moves the source balances to the target by fee free transfers, then moves
+    // the source history to the target (rewriting transfer counterparties) and
+    // removes the source account; a Merged marker is logged on the target; as in
+    // close_to the source minimum balance and transfer limit don't apply
+    // errors: InvalidAccountName (fee account or source == target),
+    // HoldsOpen (settle or release the source holds first), AccountNotExists,
+    // AccountFrozen, Storage
+    pub fn merge_account(&mut self, source: String, target: String) -> Result<(), AccError> {
+        self.sweep(source.clone(), target.clone())?;
+        self.tr_storage
+            .rename_account_references(source.clone(), target.clone())?;
+        self.acc_storage.delete_account(source.clone())?;
+        self.rename_idempotency_references(&source, &target);
+        self.tr_storage
+            .create_transaction(target, TransactionAction::Merged { from: source })?;
+        Ok(())
+    }
+
+    // moves the whole balance to the destination by a fee free transfer per currency
+    // and removes the account (its history stays in the log and ends with a Closed
+    // marker); returns the moved default currency amount; the sweep isn't limited by the minimum
+    // balance or the transfer limit of the account and isn't counted in its usage
+    // errors: InvalidAccountName (fee account or account == destination),
+    // HoldsOpen (settle or release the holds first), AccountNotExists, AccountFrozen,
+    // Storage
+    pub fn close_to(
+        &mut self,
+        account_name: String,
+        destination: String,
+    ) -> Result<usize, AccError> {
+        let moved = self.sweep(account_name.clone(), destination)?;
+        self.tr_storage
+            .create_transaction(account_name.clone(), TransactionAction::Closed)?;
+        self.acc_storage.delete_account(account_name)?;
+        Ok(moved)
+    }
+
+    // transfers as much as the sender can pay for (value + policy fee within the
+    // available balance above its minimum), returns the transferred value
+    // errors: NotEnoughMoney (nothing left after the fee), AccountNotExists,
+    // AccountFrozen, TransferLimitExceeded, Storage
+    pub fn transfer_all(&mut self, from: String, to: String) -> Result<usize, AccError> {
+        let mut acc_from = self.account(from)?;
+        let mut acc_to = self.account(to)?;
+
+        // value + fee grows with the value, find the largest one that fits
+        let spendable = acc_from
+            .available_balance()
+            .saturating_sub(acc_from.min_balance);
+        let fits = |value: usize| {
+            self.fee_policy
+                .fee(value)
+                .checked_add(value)
+                .is_some_and(|total| total <= spendable)
+        };
+        let (mut low, mut high) = (0, spendable);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            if fits(mid) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        if low == 0 {
+            return Err(AccError::NotEnoughMoney);
+        }
+
+        self.make_transaction(&mut acc_from, &mut acc_to, low, None, None)?;
+        Ok(low)
+    }
+
+    // transfers the whole source balance in every currency to the target without a fee,
+    // returns the default currency amount
+    // errors: see close_to
+    fn sweep(&mut self, source: String, target: String) -> Result<usize, AccError> {
+        let fee_acc_name = self.acc_storage.fee_account()?.name;
+        if source == target || source == fee_acc_name || target == fee_acc_name {
+            return Err(AccError::InvalidAccountName);
+        }
+        let mut source_acc = self.account(source)?;
+        let mut target_acc = self.account(target)?;
+        if source_acc.frozen || target_acc.frozen {
+            return Err(AccError::AccountFrozen);
+        }
+        // held money belongs to pending settlements, it can't be swept
+        if !source_acc.held.is_empty() {
+            return Err(AccError::HoldsOpen);
+        }
+
+        let moved = source_acc.balance();
+        let mut balances = source_acc
+            .balances
+            .iter()
+            .filter(|(_, balance)| **balance > 0)
+            .map(|(currency, balance)| (currency.clone(), *balance))
+            .collect::<Vec<_>>();
+        balances.sort();
+        for (currency, balance) in balances {
+            source_acc.bank_transfer(
+                balance,
+                &currency,
+                &mut target_acc,
+                &mut self.acc_storage,
+                &mut self.tr_storage,
+            )?;
+        }
+        Ok(moved)
+    }
+
+    // sets the balance the account can't go below by withdrawals and transfers
+    // (the fee account is exempt)
+    // errors: InvalidAccountName (fee account), AccountNotExists, Storage
+    pub fn set_min_balance(&mut self, account_name: String, min: usize) -> Result<(), AccError> {
+        if account_name == self.acc_storage.fee_account()?.name {
+            return Err(AccError::InvalidAccountName);
+        }
+        let mut acc = self.acc_storage.get_account(account_name)?;
+        acc.min_balance = min;
+        self.acc_storage.update_account(acc)?;
+        Ok(())
+    }
+
+    // errors: AmountTooLarge, EmptyTransaction, AccountFrozen, Storage (incl. balance
+    // overflow), AccountNotExists
+    pub fn inc_acc_balance(
+        &mut self,
+        acc: &mut Account,
+        value: usize,
+    ) -> Result<TransactionId, AccError> {
+        self.inc_acc_balance_with_memo(acc, value, None)
+    }
+
+    // same as inc_acc_balance, the memo (at most MAX_MEMO_LEN chars) is stored with the increment
+    // errors: as inc_acc_balance, Storage (memo too long)
+    pub fn inc_acc_balance_with_memo(
+        &mut self,
+        acc: &mut Account,
+        value: usize,
+        memo: Option<String>,
+    ) -> Result<TransactionId, AccError> {
+        self.inc_acc_balance_in(acc, value, DEFAULT_CURRENCY.to_string(), memo)
+    }
+
+    // same as inc_acc_balance_with_memo, the value is credited in `currency`
+    // errors: as inc_acc_balance_with_memo, InvalidCurrency
+    pub fn inc_acc_balance_in(
+        &mut self,
+        acc: &mut Account,
+        value: usize,
+        currency: String,
+        memo: Option<String>,
+    ) -> Result<TransactionId, AccError> {
+        self.check_deposit(value)?;
+        acc.inc_balance_in(
+            value,
+            &currency,
+            memo,
+            &mut self.acc_storage,
+            &mut self.tr_storage,
+        )
+    }
+
+    pub fn decr_acc_balance(
+        &mut self,
+        acc: &mut Account,
+        value: usize,
+    ) -> Result<TransactionId, AccError> {
+        self.decr_acc_balance_with_memo(acc, value, None)
+    }
+
+    // same as decr_acc_balance, the memo (at most MAX_MEMO_LEN chars) is stored with the decrement
+    // errors: as decr_acc_balance, Storage (memo too long)
+    pub fn decr_acc_balance_with_memo(
+        &mut self,
+        acc: &mut Account,
+        value: usize,
+        memo: Option<String>,
+    ) -> Result<TransactionId, AccError> {
+        self.decr_acc_balance_in(acc, value, DEFAULT_CURRENCY.to_string(), memo)
+    }
+
+    // same as decr_acc_balance_with_memo, the value is debited in `currency` (the
+    // minimum balance applies only to the default currency)
+    // errors: as decr_acc_balance_with_memo, InvalidCurrency
+    pub fn decr_acc_balance_in(
+        &mut self,
+        acc: &mut Account,
+        value: usize,
+        currency: String,
+        memo: Option<String>,
+    ) -> Result<TransactionId, AccError> {
+        acc.decr_balance_in(
+            value,
+            &currency,
+            memo,
+            &mut self.acc_storage,
+            &mut self.tr_storage,
+        )
+    }
+
+    // withdraws everything available above the account minimum balance by a single decrement,
+    // returns the transaction id
+    // errors: EmptyTransaction (nothing to withdraw), AccountFrozen, AccountNotExists, Storage
+    pub fn withdraw_all(&mut self, account_name: String) -> Result<TransactionId, AccError> {
+        let mut acc = self.account(account_name)?;
+        let amount = acc.available_balance().saturating_sub(acc.min_balance);
+        if amount == 0 {
+            return Err(AccError::EmptyTransaction);
+        }
+        self.decr_acc_balance(&mut acc, amount)
+    }
+
+    // credits balance * rate_bps / 10000 as an increment (not limited by max_deposit),
+    // returns the transaction id
+    // errors: EmptyTransaction (interest rounds to 0), AccountFrozen, AccountNotExists,
+    // Storage (incl. balance overflow)
+    pub fn apply_interest(
+        &mut self,
+        account_name: String,
+        rate_bps: u32,
+    ) -> Result<TransactionId, AccError> {
+        let mut acc = self.account(account_name)?;
+        let interest = interest_on(acc.balance(), rate_bps)?;
+        acc.inc_balance(interest, &mut self.acc_storage, &mut self.tr_storage)
     }
 
-    pub fn decr_acc_balance(&mut self, acc: &mut Account, value: usize) -> Result<usize, AccError> {
-        acc.decr_balance(value, &mut self.acc_storage, &mut self.tr_storage)
+    // applies interest to every account except the fee account,
+    // accounts where interest rounds to 0 and frozen accounts are skipped;
+    // every account is checked before any is credited, so an error credits nothing
+    // returns created transaction ids
+    // errors: Storage (incl. balance overflow)
+    pub fn apply_interest_all(&mut self, rate_bps: u32) -> Result<Vec<TransactionId>, AccError> {
+        let fee_acc_name = self.acc_storage.fee_account()?.name;
+        let mut names = self
+            .acc_storage
+            .accounts()?
+            .into_iter()
+            .map(|acc| acc.name)
+            .filter(|name| *name != fee_acc_name)
+            .collect::<Vec<String>>();
+        names.sort();
+
+        let mut credits = Vec::new();
+        for name in names {
+            let acc = self.account(name)?;
+            let interest = interest_on(acc.balance(), rate_bps)?;
+            if interest == 0 || acc.frozen {
+                continue;
+            }
+            acc.balance()
+                .checked_add(interest)
+                .ok_or_else(|| AccError::Storage("balance overflow".to_string()))?;
+            credits.push((acc, interest));
+        }
+
+        let mut ids = Vec::new();
+        for (mut acc, interest) in credits {
+            ids.push(acc.inc_balance(interest, &mut self.acc_storage, &mut self.tr_storage)?);
+        }
+        Ok(ids)
     }
 
+    // a repeated idempotency key returns the original transaction id without
+    // moving money again; reusing a key for a different transfer is an error
+    // fee_override replaces the fee computed by the bank fee policy
+    // errors: EmptyTransaction, NotEnoughMoney, AccountNotExists,
+    // Storage ("idempotency key conflict")
     pub fn make_transaction(
         &mut self,
         acc_from: &mut Account,
         acc_to: &mut Account,
         value: usize,
-    ) -> Result<usize, AccError> {
-        acc_from.make_transaction(
+        idempotency_key: Option<String>,
+        fee_override: Option<usize>,
+    ) -> Result<TransactionId, AccError> {
+        self.make_transaction_with_memo(
+            acc_from,
+            acc_to,
+            value,
+            idempotency_key,
+            fee_override,
+            None,
+        )
+    }
+
+    // make_transaction with a note stored on both sides of the transfer
+    // (at most MAX_MEMO_LEN chars)
+    // errors: as make_transaction, Storage (memo too long)
+    pub fn make_transaction_with_memo(
+        &mut self,
+        acc_from: &mut Account,
+        acc_to: &mut Account,
+        value: usize,
+        idempotency_key: Option<String>,
+        fee_override: Option<usize>,
+        memo: Option<String>,
+    ) -> Result<TransactionId, AccError> {
+        let options = TransferOptions {
+            fee: fee_override,
+            memo,
+            currency: None,
+        };
+        self.make_transaction_with_options(acc_from, acc_to, value, idempotency_key, options)
+    }
+
+    // make_transaction with the fee override (None - the fee policy fee), memo and
+    // currency of options; in another currency both sides and the fee (which the fee
+    // account collects per currency) are in it; a repeated idempotency key must come
+    // with the same currency
+    // errors: as make_transaction_with_memo, InvalidCurrency
+    pub fn make_transaction_with_options(
+        &mut self,
+        acc_from: &mut Account,
+        acc_to: &mut Account,
+        value: usize,
+        idempotency_key: Option<String>,
+        options: TransferOptions,
+    ) -> Result<TransactionId, AccError> {
+        let currency = options
+            .currency
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+        if let Some(seen) = idempotency_key
+            .as_ref()
+            .and_then(|key| self.idempotency_keys.get(key))
+        {
+            if seen.from != acc_from.name
+                || seen.to != acc_to.name
+                || seen.value != value
+                || seen.currency != currency
+            {
+                return Err(AccError::Storage("idempotency key conflict".to_string()));
+            }
+            return Ok(seen.tr_id);
+        }
+
+        let options = TransferOptions {
+            fee: Some(options.fee.unwrap_or_else(|| self.fee_policy.fee(value))),
+            ..options
+        };
+        let tr_id = acc_from.make_transaction_with_options(
             value,
             acc_to,
-            Some(self.tr_fee),
+            options,
             &mut self.acc_storage,
             &mut self.tr_storage,
-        )
+        )?;
+        if let Some(key) = idempotency_key {
+            self.idempotency_keys.insert(
+                key,
+                IdempotentTransfer {
+                    from: acc_from.name.clone(),
+                    to: acc_to.name.clone(),
+                    value,
+                    currency,
+                    tr_id,
+                },
+            );
+        }
+        Ok(tr_id)
     }
 
-    pub fn restore_account_from_transactions(
+    // credits an account with money coming from an external system
+    // errors: EmptyTransaction, AccountNotExists, Storage
+    pub fn external_deposit(
         &mut self,
         account_name: String,
-    ) -> Result<Account, AccError> {
-        Account::restore_account_from_transactions(
-            account_name,
+        value: usize,
+        reference: String,
+    ) -> Result<TransactionId, AccError> {
+        self.external_deposit_in(account_name, value, DEFAULT_CURRENCY.to_string(), reference)
+    }
+
+    // same as external_deposit, the value is credited in `currency`
+    // errors: as external_deposit, InvalidCurrency
+    pub fn external_deposit_in(
+        &mut self,
+        account_name: String,
+        value: usize,
+        currency: String,
+        reference: String,
+    ) -> Result<TransactionId, AccError> {
+        self.check_deposit(value)?;
+        self.account(account_name)?.external_transfer(
+            ExternalDirection::Deposit,
+            value,
+            &currency,
+            reference,
             &mut self.acc_storage,
-            &self.tr_storage,
+            &mut self.tr_storage,
         )
     }
 
-    pub fn create_transaction(
+    // debits an account with money leaving to an external system
+    // errors: EmptyTransaction, NotEnoughMoney, AccountNotExists, Storage
+    pub fn external_withdraw(
         &mut self,
         account_name: String,
-        action: TransactionAction,
-    ) -> Result<Transaction, StorageError> {
-        Ok(Transaction::from(
-            self.tr_storage.create_transaction(account_name, action)?,
-        ))
+        value: usize,
+        reference: String,
+    ) -> Result<TransactionId, AccError> {
+        self.external_withdraw_in(account_name, value, DEFAULT_CURRENCY.to_string(), reference)
     }
 
-    pub fn transactions(&self) -> Result<Vec<Transaction>, StorageError> {
-        Ok(self
-            .tr_storage
-            .transactions()?
-            .into_iter()
-            .map(Transaction::from)
-            .collect())
+    // same as external_withdraw, the value is debited in `currency`
+    // errors: as external_withdraw, InvalidCurrency
+    pub fn external_withdraw_in(
+        &mut self,
+        account_name: String,
+        value: usize,
+        currency: String,
+        reference: String,
+    ) -> Result<TransactionId, AccError> {
+        self.account(account_name)?.external_transfer(
+            ExternalDirection::Withdrawal,
+            value,
+            &currency,
+            reference,
+            &mut self.acc_storage,
+            &mut self.tr_storage,
+        )
     }
 
-    pub fn account_transactions(
-        &self,
+    // reserves amount of the account balance for a later settle or release; held money
+    // stays in the balance but can't be withdrawn or transferred; returns the hold id
+    // errors: EmptyTransaction, NotEnoughMoney, BelowMinimumBalance, AccountFrozen,
+    // AccountNotExists, Storage
+    pub fn hold(&mut self, account_name: String, amount: usize) -> Result<TransactionId, AccError> {
+        self.hold_in(account_name, amount, DEFAULT_CURRENCY.to_string())
+    }
+
+    // same as hold, the amount is reserved in `currency` (and settled in it)
+    // errors: as hold, InvalidCurrency
+    pub fn hold_in(
+        &mut self,
         account_name: String,
-    ) -> Result<Vec<Transaction>, StorageError> {
-        Ok(self
-            .tr_storage
-            .account_transactions(account_name)?
-            .into_iter()
-            .map(Transaction::from)
-            .collect())
+        amount: usize,
+        currency: String,
+    ) -> Result<TransactionId, AccError> {
+        let mut acc = self.account(account_name)?;
+        acc.hold_in(
+            amount,
+            &currency,
+            &mut self.acc_storage,
+            &mut self.tr_storage,
+        )
     }
 
-    pub fn transaction_by_id(&self, id: usize) -> Result<Transaction, StorageError> {
-        Ok(Transaction::from(self.tr_storage.transaction_by_id(id)?))
+    // completes a hold by transferring the held amount (plus the policy fee from the
+    // available balance) to `to`, returns the transfer id
+    // errors: TransactionNotExists (not a hold), Storage (hold already closed), and the
+    // errors of make_transaction
+    pub fn settle(
+        &mut self,
+        hold_id: impl Into<TransactionId>,
+        to: String,
+    ) -> Result<TransactionId, AccError> {
+        let (hold_id, account_name, amount, currency) = self.open_hold(hold_id.into())?;
+        let mut acc_from = self.account(account_name.clone())?;
+        let mut acc_to = self.account(to)?;
+
+        // the held money pays for this transfer, so it's freed in storage first and
+        // reserved again if the transfer fails
+        self.adjust_held(&account_name, &currency, |held| held.saturating_sub(amount))?;
+        let options = TransferOptions {
+            currency: Some(currency.clone()),
+            ..TransferOptions::default()
+        };
+        let tr_id = match self.make_transaction_with_options(
+            &mut acc_from,
+            &mut acc_to,
+            amount,
+            None,
+            options,
+        ) {
+            Ok(tr_id) => tr_id,
+            Err(err) => {
+                self.adjust_held(&account_name, &currency, |held| held.saturating_add(amount))?;
+                return Err(err);
+            }
+        };
+        self.tr_storage.create_transaction_in(
+            account_name,
+            TransactionAction::Release { hold_id, amount },
+            None,
+            currency_tag(&currency),
+        )?;
+        Ok(tr_id)
     }
 
-    pub fn restore_accounts_from_bank_transactions(
+    // cancels a hold making its amount available again, returns the release id
+    // errors: TransactionNotExists (not a hold), Storage (hold already closed),
+    // AccountNotExists
+    pub fn release(
         &mut self,
-        bank: &Bank<A, T>,
+        hold_id: impl Into<TransactionId>,
+    ) -> Result<TransactionId, AccError> {
+        let (hold_id, account_name, amount, currency) = self.open_hold(hold_id.into())?;
+        let mut acc = self.account(account_name)?;
+        acc.release_hold(
+            hold_id,
+            amount,
+            &currency,
+            &mut self.acc_storage,
+            &mut self.tr_storage,
+        )
+    }
+
+    // sets the stored held amount of an account in `currency` without logging a transaction
+    // errors: AccountNotExists, Storage
+    fn adjust_held(
+        &mut self,
+        name: &str,
+        currency: &str,
+        f: impl Fn(usize) -> usize,
     ) -> Result<(), AccError> {
-        for acc in bank.accounts().unwrap() {
-            Account::restore_account_from_transactions(
-                acc.name.clone(),
+        let mut raw = self.acc_storage.get_account(name.to_string())?;
+        let held = f(raw.held_in(currency));
+        raw.set_held_in(currency, held);
+        self.acc_storage.update_account(raw)?;
+        Ok(())
+    }
+
+    // sequence number, account, amount and currency of a hold that is neither settled
+    // nor released
+    // errors: TransactionNotExists, Storage (hold already closed)
+    fn open_hold(
+        &self,
+        hold_id: TransactionId,
+    ) -> Result<(usize, String, usize, String), AccError> {
+        let tr = self.tr_storage.transaction_by_id(hold_id)?;
+        let TransactionAction::Hold(amount) = tr.action else {
+            return Err(AccError::TransactionNotExists);
+        };
+        let hold_id = tr.id;
+        let mut closed = false;
+        self.tr_storage
+            .for_each_account_transaction(tr.account_name.clone(), &mut |x| {
+                closed |= matches!(x.action, TransactionAction::Release { hold_id: id, .. } if id == hold_id);
+                Ok(())
+            })?;
+        if closed {
+            return Err(AccError::Storage("hold already closed".to_string()));
+        }
+        let currency = tr.currency().to_string();
+        Ok((hold_id, tr.account_name, amount, currency))
+    }
+
+    // deposits `amount` into an account and forwards `forward_amount` (plus the fee)
+    // from it, returns the deposit and transfer ids; both legs are checked (the transfer
+    // against the balance after the deposit) before anything is stored, so a rejected
+    // call leaves no trace in the log; only a storage failure between the two writes
+    // can leave the deposit stored without the transfer (it can be reversed then)
+    // errors: AmountTooLarge, EmptyTransaction, NotEnoughMoney, BelowMinimumBalance,
+    // AccountFrozen, TransferLimitExceeded, AccountNotExists, Storage (incl. balance
+    // overflow)
+    pub fn deposit_and_transfer(
+        &mut self,
+        into: String,
+        amount: usize,
+        forward_to: String,
+        forward_amount: usize,
+    ) -> Result<(TransactionId, TransactionId), AccError> {
+        let mut acc_into = self.account(into)?;
+        let mut acc_to = self.account(forward_to)?;
+
+        if amount == 0 {
+            return Err(AccError::EmptyTransaction);
+        }
+        self.check_deposit(amount)?;
+        let balance = acc_into
+            .balance()
+            .checked_add(amount)
+            .ok_or_else(|| AccError::Storage("balance overflow".to_string()))?;
+        set_currency_balance(&mut acc_into.balances, DEFAULT_CURRENCY, balance);
+        let fee = self.fee_policy.fee(forward_amount);
+        acc_into.check_transfer(forward_amount, &acc_to, fee)?;
+        self.fee_balance()?
+            .checked_add(fee)
+            .ok_or_else(|| AccError::Storage("balance overflow".to_string()))?;
+
+        // the handle is reloaded from storage by the deposit
+        let deposit_id = self.inc_acc_balance(&mut acc_into, amount)?;
+        let tr_id =
+            self.make_transaction(&mut acc_into, &mut acc_to, forward_amount, None, None)?;
+        Ok((deposit_id, tr_id))
+    }
+
+    // creates a compensating transaction in the currency of the reversed one and
+    // returns its id: increment <-> decrement, external deposit <-> withdrawal, and a transfer
+    // moves its value back from the recipient (fees are not refunded; the refund
+    // ignores frozen flags and transfer limits and doesn't use up the recipient's limit);
+    // a Reversal marker is logged on the account so it's reversed only once
+    // errors: TransactionNotExists, NotEnoughMoney, AccountNotExists,
+    // Storage (registration, recipient side or fee of a transfer, already reversed)
+    pub fn reverse_transaction(
+        &mut self,
+        tr_id: impl Into<TransactionId>,
+    ) -> Result<TransactionId, AccError> {
+        let tr = self.tr_storage.transaction_by_id(tr_id)?;
+        // markers refer to the sequence number
+        let tr_id = tr.id;
+        let mut reversed = false;
+        self.tr_storage
+            .for_each_account_transaction(tr.account_name.clone(), &mut |x| {
+                reversed |= x.action == TransactionAction::Reversal { tr_id };
+                Ok(())
+            })?;
+        if reversed {
+            return Err(AccError::Storage(
+                "transaction already reversed".to_string(),
+            ));
+        }
+
+        let mut acc = self.account(tr.account_name.clone())?;
+        let currency = tr.currency().to_string();
+        let id = match tr.action {
+            TransactionAction::Registration => {
+                return Err(AccError::Storage("cannot reverse registration".to_string()))
+            }
+            TransactionAction::Reversal { .. } => {
+                return Err(AccError::Storage("cannot reverse a reversal".to_string()))
+            }
+            TransactionAction::Renamed { .. }
+            | TransactionAction::Merged { .. }
+            | TransactionAction::Closed => {
+                return Err(AccError::Storage(
+                    "cannot reverse an account change".to_string(),
+                ))
+            }
+            // the transfer keeps its fee, so the fee can't be reversed alone
+            TransactionAction::TransferIn { .. } | TransactionAction::Fee { .. } => {
+                return Err(AccError::Storage(
+                    "only the sender side of a transfer can be reversed".to_string(),
+                ))
+            }
+            TransactionAction::Hold(_) | TransactionAction::Release { .. } => {
+                return Err(AccError::Storage(
+                    "holds are settled or released, not reversed".to_string(),
+                ))
+            }
+            TransactionAction::Increment(value) => acc.decr_balance_in(
+                value,
+                &currency,
+                None,
                 &mut self.acc_storage,
-                &bank.tr_storage,
-            )?;
+                &mut self.tr_storage,
+            )?,
+            TransactionAction::Decrement(value) => acc.inc_balance_in(
+                value,
+                &currency,
+                None,
+                &mut self.acc_storage,
+                &mut self.tr_storage,
+            )?,
+            TransactionAction::TransferOut { to, value, .. } => {
+                let mut to_acc = self.account(to)?;
+                to_acc.bank_transfer(
+                    value,
+                    &currency,
+                    &mut acc,
+                    &mut self.acc_storage,
+                    &mut self.tr_storage,
+                )?
+            }
+            TransactionAction::External {
+                direction, amount, ..
+            } => {
+                let direction = match direction {
+                    ExternalDirection::Deposit => ExternalDirection::Withdrawal,
+                    ExternalDirection::Withdrawal => ExternalDirection::Deposit,
+                };
+                acc.external_transfer(
+                    direction,
+                    amount,
+                    &currency,
+                    format!("reversal of {}", tr.transaction_id()),
+                    &mut self.acc_storage,
+                    &mut self.tr_storage,
+                )?
+            }
+        };
+        self.tr_storage
+            .create_transaction(tr.account_name, TransactionAction::Reversal { tr_id })?;
+        Ok(id)
+    }
+
+    // applies transactions of another bank in id order keeping their ids (a registration
+    // creates the account, renamed/merged/closed markers rename, fold or remove it,
+    // other actions change its balance by their effect);
+    // ids already stored are skipped, so replaying an overlapping slice is idempotent
+    // errors: AccountNotExists (account not registered), Storage
+    pub fn replay_onto(&mut self, trs: &[Transaction]) -> Result<(), AccError> {
+        let mut trs = trs.iter().collect::<Vec<_>>();
+        trs.sort_by_key(|tr| tr.id);
+        for tr in trs {
+            match self.tr_storage.transaction_by_id(tr.id) {
+                Ok(_) => continue,
+                Err(StorageError::TransactionNotExists) => (),
+                Err(err) => return Err(AccError::from(err)),
+            }
+
+            let tr = TransactionTransfer::from(tr.clone());
+            self.replay_account_change(&tr)?;
+            let mut acc = match self.acc_storage.get_account(tr.account_name.clone()) {
+                Err(StorageError::AccountNotExists)
+                    if tr.action == TransactionAction::Registration =>
+                {
+                    self.acc_storage
+                        .create_account(AccountTransfer::new(tr.account_name.clone(), None))?
+                }
+                acc => acc?,
+            };
+            let balance = acc.balance_in(tr.currency()) as i128 + tr.balance_delta();
+            let held = acc.held_in(tr.currency()) as i128 + tr.action.held_delta();
+            let replayed = |value: i128| {
+                usize::try_from(value).map_err(|_| {
+                    AccError::Storage(if value < 0 {
+                        "negative balance in log".to_string()
+                    } else {
+                        "balance overflow".to_string()
+                    })
+                })
+            };
+            acc.set_balance_in(tr.currency(), replayed(balance)?);
+            acc.set_held_in(tr.currency(), replayed(held)?);
+            self.acc_storage.update_account(acc)?;
+            if tr.action == TransactionAction::Closed {
+                self.acc_storage.delete_account(tr.account_name.clone())?;
+            }
+            self.tr_storage.restore_transaction(tr)?;
+        }
+        Ok(())
+    }
+
+    // renames or folds a replica account before a Renamed/Merged marker is replayed;
+    // nothing to do if the old account is unknown (its history already came under
+    // the new name)
+    // errors: Storage
+    fn replay_account_change(&mut self, tr: &TransactionTransfer) -> Result<(), AccError> {
+        let name = tr.account_name.clone();
+        match &tr.action {
+            TransactionAction::Renamed { from } if self.account_exists(from.clone())? => {
+                self.acc_storage
+                    .rename_account(from.clone(), name.clone())?;
+                self.tr_storage
+                    .rename_account_references(from.clone(), name)?;
+            }
+            // the swept transfer became a self transfer of the target, so the source
+            // balance is moved here
+            TransactionAction::Merged { from } if self.account_exists(from.clone())? => {
+                let source = self.acc_storage.delete_account(from.clone())?;
+                let mut target = self.acc_storage.get_account(name.clone())?;
+                for (currency, balance) in &source.balances {
+                    let merged = target
+                        .balance_in(currency)
+                        .checked_add(*balance)
+                        .ok_or_else(|| AccError::Storage("balance overflow".to_string()))?;
+                    target.set_balance_in(currency, merged);
+                }
+                for (currency, held) in &source.held {
+                    let merged = target.held_in(currency).saturating_add(*held);
+                    target.set_held_in(currency, merged);
+                }
+                self.acc_storage.update_account(target)?;
+                self.tr_storage
+                    .rename_account_references(from.clone(), name)?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    pub fn restore_account_from_transactions(
+        &mut self,
+        account_name: String,
+    ) -> Result<Account, AccError> {
+        Account::restore_account_from_transactions(
+            account_name,
+            &mut self.acc_storage,
+            &self.tr_storage,
+        )
+    }
+
+    // recomputes the stored balance of an existing account from its transaction log
+    // (incoming transfers are logged on the receiver, so they are included)
+    // errors: AccountNotExists, Storage
+    pub fn rebuild_account(&mut self, account_name: String) -> Result<Account, AccError> {
+        self.acc_storage.get_account(account_name.clone())?;
+        self.restore_account_from_transactions(account_name)
+    }
+
+    pub fn create_transaction(
+        &mut self,
+        account_name: String,
+        action: TransactionAction,
+    ) -> Result<Transaction, StorageError> {
+        Ok(Transaction::from(
+            self.tr_storage.create_transaction(account_name, action)?,
+        ))
+    }
+
+    // all transactions sorted by id (storages may return them in any order)
+    pub fn transactions(&self) -> Result<Vec<Transaction>, StorageError> {
+        let mut trs = self.tr_storage.transactions()?;
+        trs.sort_by_key(|x| x.id);
+        Ok(trs.into_iter().map(Transaction::from).collect())
+    }
+
+    // transactions with from_id <= id <= to_id in id order
+    // (ids missing from the storage are skipped)
+    // errors: StorageError (from_id > to_id)
+    pub fn transactions_between(
+        &self,
+        from_id: usize,
+        to_id: usize,
+    ) -> Result<Vec<Transaction>, StorageError> {
+        if from_id > to_id {
+            return Err(StorageError::StorageError("invalid id range".to_string()));
+        }
+        Ok(self
+            .tr_storage
+            .transactions_between(from_id, to_id)?
+            .into_iter()
+            .map(Transaction::from)
+            .collect())
+    }
+
+    // transactions with id > after_id in id order (for polling with an id cursor)
+    pub fn transactions_since(&self, after_id: usize) -> Result<Vec<Transaction>, StorageError> {
+        match after_id.checked_add(1) {
+            Some(from_id) => self.transactions_between(from_id, usize::MAX),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn transactions_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, StorageError> {
+        Ok(self
+            .tr_storage
+            .transactions_paged(offset, limit)?
+            .into_iter()
+            .map(Transaction::from)
+            .collect())
+    }
+
+    // account transactions with the given action kind (all if filter is None)
+    // errors: AccountNotExists, StorageError
+    pub fn account_transactions_filtered(
+        &self,
+        account_name: String,
+        action_filter: Option<ActionKind>,
+    ) -> Result<Vec<Transaction>, StorageError> {
+        Ok(self
+            .logged_account_transactions(account_name)?
+            .into_iter()
+            .filter(|x| action_filter.is_none_or(|kind| x.action.kind() == kind))
+            .map(Transaction::from)
+            .collect())
+    }
+
+    // sum of all default currency account balances (including the fee account)
+    // errors: Storage (incl. total overflow)
+    pub fn total_assets(&self) -> Result<usize, AccError> {
+        self.total_assets_in(DEFAULT_CURRENCY.to_string())
+    }
+
+    // sum of all account balances in `currency` (including the fee account)
+    // errors: Storage (incl. total overflow)
+    pub fn total_assets_in(&self, currency: String) -> Result<usize, AccError> {
+        let mut total: usize = 0;
+        self.acc_storage.for_each_account(&mut |acc| {
+            total = total
+                .checked_add(acc.balance_in(&currency))
+                .ok_or_else(|| StorageError::StorageError("total overflow".to_string()))?;
+            Ok(())
+        })?;
+        Ok(total)
+    }
+
+    // read-only check that every stored balance (in every currency) matches its
+    // replayed log and the fee account holds exactly the recorded transfer fees in
+    // every currency
+    // errors: Storage (incl. total overflow)
+    pub fn audit_integrity(&self) -> Result<IntegrityReport, AccError> {
+        let mut computed: HashMap<(String, String), i128> = HashMap::new();
+        let mut recorded_fees: BTreeMap<String, usize> = BTreeMap::new();
+        for tr in self.tr_storage.transactions()? {
+            *computed
+                .entry((tr.account_name.clone(), tr.currency().to_string()))
+                .or_default() += tr.balance_delta();
+            if let TransactionAction::TransferOut { fee, .. } = tr.action {
+                if fee > 0 {
+                    let total = recorded_fees.entry(tr.currency().to_string()).or_default();
+                    add_to_total(total, fee)?;
+                }
+            }
+        }
+
+        let mut discrepancies = Vec::new();
+        self.acc_storage.for_each_account(&mut |acc| {
+            let mut currencies = computed
+                .keys()
+                .filter(|(name, _)| *name == acc.name)
+                .map(|(_, currency)| currency)
+                .chain(acc.balances.keys())
+                .collect::<Vec<_>>();
+            currencies.sort();
+            currencies.dedup();
+            for currency in currencies {
+                let balance = computed
+                    .get(&(acc.name.clone(), currency.clone()))
+                    .copied()
+                    .unwrap_or_default();
+                if balance != acc.balance_in(currency) as i128 {
+                    discrepancies.push(BalanceDiscrepancy {
+                        account_name: acc.name.clone(),
+                        currency: currency.clone(),
+                        stored: acc.balance_in(currency),
+                        computed: balance,
+                    });
+                }
+            }
+            Ok(())
+        })?;
+        discrepancies
+            .sort_by(|a, b| (&a.account_name, &a.currency).cmp(&(&b.account_name, &b.currency)));
+
+        Ok(IntegrityReport {
+            discrepancies,
+            recorded_fees,
+            fee_balances: self
+                .acc_storage
+                .fee_account()?
+                .balances
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    // default currency fees charged so far in total and per sender, from the recorded
+    // transfers
+    // errors: Storage (fee overflow)
+    pub fn fee_report(&self) -> Result<FeeLedger, AccError> {
+        self.fee_report_in(DEFAULT_CURRENCY.to_string())
+    }
+
+    // same as fee_report for the fees charged in `currency`
+    // errors: Storage (fee overflow)
+    pub fn fee_report_in(&self, currency: String) -> Result<FeeLedger, AccError> {
+        let mut ledger = FeeLedger::default();
+        for tr in self.tr_storage.transactions()? {
+            if tr.currency() != currency {
+                continue;
+            }
+            if let TransactionAction::TransferOut { fee, .. } = tr.action {
+                if fee > 0 && !ledger.record(&tr.account_name, fee) {
+                    return Err(AccError::Storage("fee overflow".to_string()));
+                }
+            }
+        }
+        Ok(ledger)
+    }
+
+    // deposited, withdrawn, transferred and fee totals over all default currency
+    // transactions; without external leakage total_assets == total_added - total_withdrawn
+    // errors: Storage (incl. total overflow)
+    pub fn aggregates(&self) -> Result<Aggregates, AccError> {
+        self.aggregates_in(DEFAULT_CURRENCY.to_string())
+    }
+
+    // same as aggregates over the transactions in `currency` (compare with
+    // total_assets_in)
+    // errors: Storage (incl. total overflow)
+    pub fn aggregates_in(&self, currency: String) -> Result<Aggregates, AccError> {
+        let mut aggs = Aggregates::default();
+        for tr in self.tr_storage.transactions()? {
+            if tr.currency() != currency {
+                continue;
+            }
+            match tr.action {
+                TransactionAction::Registration => (),
+                TransactionAction::Increment(amount) => {
+                    add_to_total(&mut aggs.total_added, amount)?
+                }
+                TransactionAction::Decrement(amount) => {
+                    add_to_total(&mut aggs.total_withdrawn, amount)?
+                }
+                TransactionAction::TransferOut { value, fee, .. } => {
+                    add_to_total(&mut aggs.total_transferred, value)?;
+                    add_to_total(&mut aggs.total_fees, fee)?;
+                }
+                // counted by the sender side (the fee leg too)
+                TransactionAction::TransferIn { .. } | TransactionAction::Fee { .. } => (),
+                TransactionAction::External {
+                    direction: ExternalDirection::Deposit,
+                    amount,
+                    ..
+                } => add_to_total(&mut aggs.total_added, amount)?,
+                TransactionAction::External {
+                    direction: ExternalDirection::Withdrawal,
+                    amount,
+                    ..
+                } => add_to_total(&mut aggs.total_withdrawn, amount)?,
+                // no money moves
+                TransactionAction::Hold(_)
+                | TransactionAction::Release { .. }
+                | TransactionAction::Reversal { .. }
+                | TransactionAction::Renamed { .. }
+                | TransactionAction::Merged { .. }
+                | TransactionAction::Closed => (),
+            }
+        }
+        Ok(aggs)
+    }
+
+    // transaction that registered the account
+    // errors: AccountNotExists, TransactionNotExists (e.g. fee account), Storage
+    pub fn registration_transaction(&self, account_name: String) -> Result<Transaction, AccError> {
+        self.acc_storage.get_account(account_name.clone())?;
+        let tr = match self.tr_storage.account_transactions(account_name) {
+            Ok(trs) => trs
+                .into_iter()
+                .filter(|x| x.action == TransactionAction::Registration)
+                .min_by_key(|x| x.id),
+            Err(StorageError::AccountNotExists) => None,
+            Err(err) => return Err(AccError::from(err)),
+        };
+        tr.map(Transaction::from)
+            .ok_or(AccError::TransactionNotExists)
+    }
+
+    // earliest transaction (None for an empty bank)
+    pub fn first_transaction(&self) -> Result<Option<Transaction>, StorageError> {
+        Ok(self.tr_storage.first_transaction()?.map(Transaction::from))
+    }
+
+    // latest transaction (None for an empty bank)
+    pub fn last_transaction(&self) -> Result<Option<Transaction>, StorageError> {
+        Ok(self.tr_storage.last_transaction()?.map(Transaction::from))
+    }
+
+    pub fn account_transactions(
+        &self,
+        account_name: String,
+    ) -> Result<Vec<Transaction>, StorageError> {
+        Ok(self
+            .tr_storage
+            .account_transactions(account_name)?
+            .into_iter()
+            .map(Transaction::from)
+            .collect())
+    }
+
+    // one row per distinct event (transaction id) with its net effect on the account;
+    // rows that don't change the balance (e.g. registration) are omitted
+    // errors: AccountNotExists, StorageError
+    pub fn net_account_transactions(
+        &self,
+        account_name: String,
+    ) -> Result<Vec<NetTransaction>, StorageError> {
+        let mut events = BTreeMap::new();
+        for tr in self.logged_account_transactions(account_name)? {
+            *events.entry(tr.id).or_insert(0) += tr.balance_delta();
+        }
+        Ok(events
+            .into_iter()
+            .filter(|(_, delta)| *delta != 0)
+            .map(|(id, delta)| NetTransaction { id, delta })
+            .collect())
+    }
+
+    // transactions of an existing account (empty if it has none logged, e.g. the fee
+    // account before the first fee)
+    // errors: AccountNotExists, StorageError
+    fn logged_account_transactions(
+        &self,
+        account_name: String,
+    ) -> Result<Vec<TransactionTransfer>, StorageError> {
+        self.acc_storage.get_account(account_name.clone())?;
+        match self.tr_storage.account_transactions(account_name) {
+            Err(StorageError::AccountNotExists) => Ok(Vec::new()),
+            res => res,
+        }
+    }
+
+    // account transactions in id order with the running balance (in the currency of
+    // the transaction) after each one
+    // errors: AccountNotExists, Storage (log leads to a negative balance)
+    pub fn statement(&self, account_name: String) -> Result<Vec<StatementLine>, AccError> {
+        let mut trs = self.logged_account_transactions(account_name)?;
+        trs.sort_by_key(|x| x.id);
+
+        let mut balances: HashMap<String, i128> = HashMap::new();
+        let mut lines = Vec::with_capacity(trs.len());
+        for tr in trs {
+            let currency = tr.currency().to_string();
+            let balance = balances.entry(currency.clone()).or_default();
+            *balance += tr.balance_delta();
+            let balance_after = usize::try_from(*balance)
+                .map_err(|_| AccError::Storage("negative balance in log".to_string()))?;
+            lines.push(StatementLine {
+                id: tr.id,
+                action: tr.action,
+                currency,
+                balance_after,
+            });
+        }
+        Ok(lines)
+    }
+
+    // all accounts as csv (name,currency,balance) sorted by name, including the fee
+    // account; one row per currency of an account: the default currency always, then
+    // the other ones sorted by code
+    // errors: Storage
+    pub fn export_accounts_csv(&self) -> Result<String, AccError> {
+        let mut accs = self.acc_storage.accounts()?;
+        accs.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut csv = String::from("name,currency,balance\n");
+        for acc in accs {
+            let mut others = acc
+                .balances
+                .iter()
+                .filter(|(currency, _)| *currency != DEFAULT_CURRENCY)
+                .collect::<Vec<_>>();
+            others.sort();
+            let name = csv_field(&acc.name);
+            csv.push_str(&format!("{name},{DEFAULT_CURRENCY},{}\n", acc.balance()));
+            for (currency, balance) in others {
+                csv.push_str(&format!("{name},{},{balance}\n", csv_field(currency)));
+            }
+        }
+        Ok(csv)
+    }
+
+    // all transactions as csv (id,account_name,action,amount,to,fee,currency) in id
+    // order; `to` and `fee` are set only for the sender side of a transfer
+    // errors: Storage
+    pub fn export_transactions_csv(&self) -> Result<String, AccError> {
+        let mut csv = String::from("id,account_name,action,amount,to,fee,currency\n");
+        for tr in self.transactions()? {
+            let to = match &tr.action {
+                TransactionAction::TransferOut { to, .. } => csv_field(to),
+                _ => String::new(),
+            };
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                tr.id,
+                csv_field(&tr.account_name),
+                csv_action_name(&tr.action),
+                csv_amount(&tr.action),
+                to,
+                csv_fee(&tr.action),
+                csv_field(tr.currency.as_deref().unwrap_or(DEFAULT_CURRENCY))
+            ));
+        }
+        Ok(csv)
+    }
+
+    // writes an account statement as csv (id,action,amount,fee,balance,currency) row by
+    // row with a running balance in the currency of the row; amount and fee are split as
+    // in export_transactions_csv;
+    // the history is never collected in memory
+    // errors: AccountNotExists, Storage
+    pub fn stream_account_csv<W: Write>(
+        &self,
+        account_name: String,
+        w: &mut W,
+    ) -> Result<(), AccError> {
+        let io_err = |err: std::io::Error| StorageError::StorageError(err.to_string());
+
+        self.acc_storage.get_account(account_name.clone())?;
+        writeln!(w, "id,action,amount,fee,balance,currency").map_err(io_err)?;
+        let mut balances: HashMap<String, i128> = HashMap::new();
+        let res = self
+            .tr_storage
+            .for_each_account_transaction(account_name, &mut |tr| {
+                let balance = balances.entry(tr.currency().to_string()).or_default();
+                *balance += tr.balance_delta();
+                writeln!(
+                    w,
+                    "{},{},{},{},{},{}",
+                    tr.id,
+                    csv_action_name(&tr.action),
+                    csv_amount(&tr.action),
+                    csv_fee(&tr.action),
+                    balance,
+                    csv_field(tr.currency())
+                )
+                .map_err(io_err)
+            });
+        match res {
+            // an existing account without a log gets just the header
+            Ok(()) | Err(StorageError::AccountNotExists) => {}
+            Err(err) => return Err(AccError::from(err)),
+        }
+        w.flush().map_err(io_err)?;
+        Ok(())
+    }
+
+    // accounts (including the fee account) ordered by name and transactions ordered by id
+    fn state(&self) -> Result<BankState, StorageError> {
+        let mut accounts = self.acc_storage.accounts()?;
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut transactions = self.tr_storage.transactions()?;
+        transactions.sort_by_key(|x| x.id);
+        Ok(BankState {
+            accounts,
+            transactions,
+        })
+    }
+
+    // fills the given storages with a saved state keeping balances and transaction ids
+    // errors: Storage
+    fn from_state(
+        state: BankState,
+        acc_storage: A,
+        tr_storage: T,
+        fee_policy: Option<Box<dyn FeePolicy>>,
+    ) -> Result<Self, AccError> {
+        let mut bank = Bank::new(acc_storage, tr_storage, fee_policy);
+        for acc in state.accounts {
+            // the fee account may be already created by the storage
+            match bank.acc_storage.update_account(acc.clone()) {
+                Ok(_) => (),
+                Err(StorageError::AccountNotExists) => {
+                    bank.acc_storage.create_account(acc)?;
+                }
+                Err(err) => return Err(AccError::from(err)),
+            }
         }
+        for tr in state.transactions {
+            bank.tr_storage.restore_transaction(tr)?;
+        }
+        Ok(bank)
+    }
+
+    // writes a compact binary snapshot of all accounts and transactions
+    // errors: Storage
+    pub fn write_snapshot<W: Write>(&self, w: &mut W) -> Result<(), AccError> {
+        self.state()?.write_binary(w)?;
+        Ok(())
+    }
+
+    // builds a bank from a snapshot written by write_snapshot on top of empty storages
+    // errors: Storage (bad header, unsupported version, malformed data)
+    pub fn read_snapshot<R: Read>(
+        r: &mut R,
+        acc_storage: A,
+        tr_storage: T,
+        fee_policy: Option<Box<dyn FeePolicy>>,
+    ) -> Result<Self, AccError> {
+        let state = BankState::read_binary(r)?;
+        Self::from_state(state, acc_storage, tr_storage, fee_policy)
+    }
+
+    // all accounts, transactions and the collected fees read at once
+    // (the bank is borrowed immutably, so nothing can change in between)
+    // errors: Storage
+    pub fn snapshot(&self) -> Result<BankSnapshot, AccError> {
+        let fee_acc_name = self.acc_storage.fee_account()?.name;
+        let state = self.state()?;
+        let fee_balance = state
+            .accounts
+            .iter()
+            .find(|acc| acc.name == fee_acc_name)
+            .map_or(0, |acc| acc.balance());
+        Ok(BankSnapshot {
+            accounts: state.accounts.into_iter().map(Account::from).collect(),
+            transactions: state
+                .transactions
+                .into_iter()
+                .map(Transaction::from)
+                .collect(),
+            fee_balance,
+        })
+    }
+
+    // flushes both storages (call before shutting down)
+    // errors: Storage
+    pub fn flush(&mut self) -> Result<(), AccError> {
+        self.acc_storage.flush()?;
+        self.tr_storage.flush()?;
         Ok(())
     }
+
+    // exports all accounts and transactions as a json document
+    // errors: Storage
+    pub fn export_state(&self) -> Result<String, AccError> {
+        Ok(self.state()?.to_json()?)
+    }
+
+    // builds a bank from a document written by export_state on top of empty storages
+    // (unlike restore_accounts_from_bank_transactions keeps exact balances and ids)
+    // errors: Storage (malformed data)
+    pub fn import_state(
+        json: &str,
+        acc_storage: A,
+        tr_storage: T,
+        fee_policy: Option<Box<dyn FeePolicy>>,
+    ) -> Result<Self, AccError> {
+        let state = BankState::from_json(json)?;
+        Self::from_state(state, acc_storage, tr_storage, fee_policy)
+    }
+
+    pub fn transaction_count(&self) -> Result<usize, StorageError> {
+        self.tr_storage.transaction_count()
+    }
+
+    // errors: AccountNotExists, StorageError
+    pub fn account_transaction_count(&self, account_name: String) -> Result<usize, StorageError> {
+        self.acc_storage.get_account(account_name.clone())?;
+        self.tr_storage.account_transaction_count(account_name)
+    }
+
+    // ids of the account transactions (incoming transfers included) in log order
+    // errors: AccountNotExists, StorageError
+    pub fn transaction_ids_for_account(
+        &self,
+        account_name: String,
+    ) -> Result<Vec<TransactionId>, StorageError> {
+        self.acc_storage.get_account(account_name.clone())?;
+        let mut ids = Vec::new();
+        let res = self
+            .tr_storage
+            .for_each_account_transaction(account_name, &mut |tr| {
+                ids.push((tr.id, tr.transaction_id()));
+                Ok(())
+            });
+        match res {
+            // an existing account without a log (the fee account before the first fee)
+            Ok(()) | Err(StorageError::AccountNotExists) => {}
+            Err(err) => return Err(err),
+        }
+        ids.sort_unstable_by_key(|(seq, _)| *seq);
+        Ok(ids.into_iter().map(|(_, id)| id).collect())
+    }
+
+    // looks a transaction up by its sequence number or uuid
+    pub fn transaction_by_id(
+        &self,
+        id: impl Into<TransactionId>,
+    ) -> Result<Transaction, StorageError> {
+        Ok(Transaction::from(self.tr_storage.transaction_by_id(id)?))
+    }
+
+    // restores every account of `bank` from its transaction log; an account whose
+    // log is missing or invalid doesn't abort the restore and is returned as a failure
+    // errors: Storage (reading the accounts of `bank`, writing to this bank)
+    pub fn restore_accounts_from_bank_transactions(
+        &mut self,
+        bank: &Bank<A, T>,
+    ) -> Result<Vec<RestoreFailure>, AccError> {
+        let fee_acc_name = bank.acc_storage.fee_account()?.name;
+        let mut failures = Vec::new();
+        for acc in bank.accounts()? {
+            let (balances, held) =
+                match Account::replayed_balances(acc.name.clone(), &bank.tr_storage) {
+                    Ok(balances) => balances,
+                    // no fees were charged yet
+                    Err(AccError::AccountNotExists) if acc.name == fee_acc_name => {
+                        (HashMap::new(), HashMap::new())
+                    }
+                    Err(error) => {
+                        failures.push(RestoreFailure {
+                            account_name: acc.name,
+                            error,
+                        });
+                        continue;
+                    }
+                };
+            Account::store_restored_balance(acc.name, balances, held, &mut self.acc_storage)?;
+        }
+        Ok(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::bank::fee::PercentFee;
+    use crate::bank::implements::memory::storage::{MemAccountStorage, MemTransactionStorage};
+    use crate::bank::storage::{
+        AccountTransfer, TransactionIdMode, TransactionTransfer, FEE_ACCOUNT_NAME, MAX_MEMO_LEN,
+    };
+
+    // options of a transfer in `currency` with the policy fee
+    fn in_currency(currency: &str) -> TransferOptions {
+        TransferOptions {
+            currency: Some(currency.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    // sequence number of an id returned in the sequential id mode
+    fn seq(id: TransactionId) -> usize {
+        match id {
+            TransactionId::Seq(id) => id,
+            TransactionId::Uuid(uuid) => panic!("unexpected uuid {uuid}"),
+        }
+    }
+
+    fn new_bank(tr_fee: Option<usize>) -> Bank<MemAccountStorage, MemTransactionStorage> {
+        Bank::new(
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            tr_fee.map(|fee| Box::new(FlatFee(fee)) as Box<dyn FeePolicy>),
+        )
+    }
+
+    // splits written data into lines and tracks the largest single write
+    #[derive(Default)]
+    struct CountingWriter {
+        lines: Vec<String>,
+        line: Vec<u8>,
+        bytes: usize,
+        max_write: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.bytes += buf.len();
+            self.max_write = self.max_write.max(buf.len());
+            for b in buf {
+                if *b == b'\n' {
+                    self.lines
+                        .push(String::from_utf8(self.line.clone()).unwrap());
+                    self.line.clear();
+                } else {
+                    self.line.push(*b);
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // memory account storage that remembers whether it was flushed
+    struct FlushRecorder {
+        inner: MemAccountStorage,
+        flushed: bool,
+    }
+
+    impl AccountStorage for FlushRecorder {
+        fn create_account(
+            &mut self,
+            raw_data: AccountTransfer,
+        ) -> Result<AccountTransfer, StorageError> {
+            self.inner.create_account(raw_data)
+        }
+
+        fn get_account(&self, name: String) -> Result<AccountTransfer, StorageError> {
+            self.inner.get_account(name)
+        }
+
+        fn update_account(
+            &mut self,
+            transfer_data: AccountTransfer,
+        ) -> Result<AccountTransfer, StorageError> {
+            self.inner.update_account(transfer_data)
+        }
+
+        fn fee_account(&self) -> Result<AccountTransfer, StorageError> {
+            self.inner.fee_account()
+        }
+
+        fn accounts(&self) -> Result<Vec<AccountTransfer>, StorageError> {
+            self.inner.accounts()
+        }
+
+        fn for_each_account(
+            &self,
+            f: &mut dyn FnMut(&AccountTransfer) -> Result<(), StorageError>,
+        ) -> Result<(), StorageError> {
+            self.inner.for_each_account(f)
+        }
+
+        fn count(&self) -> Result<usize, StorageError> {
+            self.inner.count()
+        }
+
+        fn rename_account(
+            &mut self,
+            old: String,
+            new: String,
+        ) -> Result<AccountTransfer, StorageError> {
+            self.inner.rename_account(old, new)
+        }
+
+        fn delete_account(&mut self, name: String) -> Result<AccountTransfer, StorageError> {
+            self.inner.delete_account(name)
+        }
+
+        fn flush(&mut self) -> Result<(), StorageError> {
+            self.flushed = true;
+            Ok(())
+        }
+    }
+
+    // memory account storage whose reads fail while `broken` is set
+    struct BrokenReads {
+        inner: MemAccountStorage,
+        broken: bool,
+    }
+
+    impl AccountStorage for BrokenReads {
+        fn create_account(
+            &mut self,
+            raw_data: AccountTransfer,
+        ) -> Result<AccountTransfer, StorageError> {
+            self.inner.create_account(raw_data)
+        }
+
+        fn get_account(&self, name: String) -> Result<AccountTransfer, StorageError> {
+            if self.broken {
+                return Err(StorageError::StorageError("read failed".to_string()));
+            }
+            self.inner.get_account(name)
+        }
+
+        fn update_account(
+            &mut self,
+            transfer_data: AccountTransfer,
+        ) -> Result<AccountTransfer, StorageError> {
+            self.inner.update_account(transfer_data)
+        }
+
+        fn fee_account(&self) -> Result<AccountTransfer, StorageError> {
+            self.inner.fee_account()
+        }
+
+        fn accounts(&self) -> Result<Vec<AccountTransfer>, StorageError> {
+            self.inner.accounts()
+        }
+
+        fn for_each_account(
+            &self,
+            f: &mut dyn FnMut(&AccountTransfer) -> Result<(), StorageError>,
+        ) -> Result<(), StorageError> {
+            self.inner.for_each_account(f)
+        }
+
+        fn count(&self) -> Result<usize, StorageError> {
+            self.inner.count()
+        }
+
+        fn rename_account(
+            &mut self,
+            old: String,
+            new: String,
+        ) -> Result<AccountTransfer, StorageError> {
+            self.inner.rename_account(old, new)
+        }
+
+        fn delete_account(&mut self, name: String) -> Result<AccountTransfer, StorageError> {
+            self.inner.delete_account(name)
+        }
+    }
+
+    // memory account storage whose updates fail while `broken` is set
+    struct BrokenWrites {
+        inner: MemAccountStorage,
+        broken: bool,
+    }
+
+    impl AccountStorage for BrokenWrites {
+        fn create_account(
+            &mut self,
+            raw_data: AccountTransfer,
+        ) -> Result<AccountTransfer, StorageError> {
+            self.inner.create_account(raw_data)
+        }
+
+        fn get_account(&self, name: String) -> Result<AccountTransfer, StorageError> {
+            self.inner.get_account(name)
+        }
+
+        fn update_account(
+            &mut self,
+            transfer_data: AccountTransfer,
+        ) -> Result<AccountTransfer, StorageError> {
+            if self.broken {
+                return Err(StorageError::StorageError("write failed".to_string()));
+            }
+            self.inner.update_account(transfer_data)
+        }
+
+        fn fee_account(&self) -> Result<AccountTransfer, StorageError> {
+            self.inner.fee_account()
+        }
+
+        fn accounts(&self) -> Result<Vec<AccountTransfer>, StorageError> {
+            self.inner.accounts()
+        }
+
+        fn for_each_account(
+            &self,
+            f: &mut dyn FnMut(&AccountTransfer) -> Result<(), StorageError>,
+        ) -> Result<(), StorageError> {
+            self.inner.for_each_account(f)
+        }
+
+        fn count(&self) -> Result<usize, StorageError> {
+            self.inner.count()
+        }
+
+        fn rename_account(
+            &mut self,
+            old: String,
+            new: String,
+        ) -> Result<AccountTransfer, StorageError> {
+            self.inner.rename_account(old, new)
+        }
+
+        fn delete_account(&mut self, name: String) -> Result<AccountTransfer, StorageError> {
+            self.inner.delete_account(name)
+        }
+    }
+
+    #[test]
+    fn test_bank_create_account_invalid_names() {
+        let mut bank = new_bank(None);
+        for name in ["", "  ", "\t\n", FEE_ACCOUNT_NAME] {
+            assert_eq!(
+                bank.create_account(name.to_owned()).err().unwrap(),
+                AccError::InvalidAccountName
+            );
+        }
+        assert_eq!(FEE_ACCOUNT_NAME, "fee_acc");
+        assert_eq!(bank.accounts().unwrap().len(), 1);
+        assert!(bank.create_account(" test 1 ".to_owned()).is_ok());
+        assert_eq!(
+            bank.rename_account(" test 1 ".to_owned(), " ".to_owned())
+                .err()
+                .unwrap(),
+            AccError::InvalidAccountName
+        );
+    }
+
+    #[test]
+    fn test_bank_create_account_name_length() {
+        let mut bank = new_bank(None);
+        let at_limit = "a".repeat(MAX_ACCOUNT_NAME_LEN);
+        assert!(bank.create_account(at_limit.clone()).is_ok());
+        assert!(bank.account(at_limit).is_ok());
+
+        let over_limit = "a".repeat(MAX_ACCOUNT_NAME_LEN + 1);
+        assert!(validate_account_name(&"a".repeat(MAX_ACCOUNT_NAME_LEN), FEE_ACCOUNT_NAME).is_ok());
+        assert_eq!(
+            validate_account_name(&over_limit, FEE_ACCOUNT_NAME)
+                .err()
+                .unwrap(),
+            AccError::InvalidAccountName
+        );
+        assert_eq!(
+            bank.create_account(over_limit.clone()).err().unwrap(),
+            AccError::InvalidAccountName
+        );
+        assert_eq!(
+            bank.account(over_limit).err().unwrap(),
+            AccError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_bank_external_transfers() {
+        let mut bank = new_bank(None);
+        let mut acc = bank.create_account("test".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 10).unwrap();
+
+        let dep_id = bank
+            .external_deposit("test".to_owned(), 100, "wire-1".to_owned())
+            .unwrap();
+        assert_eq!(bank.account("test".to_owned()).unwrap().balance(), 110);
+
+        let wd_id = bank
+            .external_withdraw("test".to_owned(), 30, "card-7".to_owned())
+            .unwrap();
+        assert_eq!(bank.account("test".to_owned()).unwrap().balance(), 80);
+
+        // external transactions are distinguishable from internal ones
+        assert_eq!(
+            bank.transaction_by_id(dep_id).unwrap().action,
+            TransactionAction::External {
+                direction: ExternalDirection::Deposit,
+                amount: 100,
+                reference: "wire-1".to_owned(),
+            }
+        );
+        assert_eq!(
+            bank.transaction_by_id(wd_id).unwrap().action,
+            TransactionAction::External {
+                direction: ExternalDirection::Withdrawal,
+                amount: 30,
+                reference: "card-7".to_owned(),
+            }
+        );
+        let external = bank
+            .account_transactions_filtered("test".to_owned(), Some(ActionKind::External))
+            .unwrap();
+        assert_eq!(
+            external.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![seq(dep_id), seq(wd_id)]
+        );
+
+        // errors
+        assert_eq!(
+            bank.external_withdraw("test".to_owned(), 81, "card-7".to_owned())
+                .err()
+                .unwrap(),
+            AccError::NotEnoughMoney
+        );
+        assert_eq!(
+            bank.external_deposit("test".to_owned(), 0, "wire-2".to_owned())
+                .err()
+                .unwrap(),
+            AccError::EmptyTransaction
+        );
+        assert_eq!(
+            bank.external_deposit("not_exists".to_owned(), 1, "wire-3".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        assert_eq!(bank.account("test".to_owned()).unwrap().balance(), 80);
+
+        // restore replays external transactions
+        let acc = bank
+            .restore_account_from_transactions("test".to_owned())
+            .unwrap();
+        assert_eq!(acc.balance(), 80);
+    }
+
+    #[test]
+    fn test_bank_account_transactions_filtered() {
+        let mut bank = new_bank(None);
+        let mut acc = bank.create_account("test".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 10).unwrap();
+        bank.decr_acc_balance(&mut acc, 3).unwrap();
+        bank.inc_acc_balance(&mut acc, 5).unwrap();
+
+        let all = bank
+            .account_transactions_filtered("test".to_owned(), None)
+            .unwrap();
+        assert_eq!(all.len(), 4);
+
+        let incs = bank
+            .account_transactions_filtered("test".to_owned(), Some(ActionKind::Increment))
+            .unwrap();
+        assert_eq!(
+            incs.iter().map(|x| x.action.clone()).collect::<Vec<_>>(),
+            vec![
+                TransactionAction::Increment(10),
+                TransactionAction::Increment(5)
+            ]
+        );
+
+        let decrs = bank
+            .account_transactions_filtered("test".to_owned(), Some(ActionKind::Decrement))
+            .unwrap();
+        assert_eq!(decrs.len(), 1);
+        assert_eq!(decrs[0].action, TransactionAction::Decrement(3));
+
+        // unknown account is an error, not an empty list
+        assert_eq!(
+            bank.account_transactions_filtered("not_exists".to_owned(), None)
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+
+        // the fee account has no log before the first fee
+        let fee_name = bank.acc_storage.fee_account().unwrap().name;
+        assert!(bank
+            .account_transactions_filtered(fee_name, None)
+            .unwrap()
+            .is_empty());
+
+        // a closed account keeps its log but is gone
+        bank.create_account("dest".to_owned()).unwrap();
+        bank.close_to("test".to_owned(), "dest".to_owned()).unwrap();
+        assert_eq!(
+            bank.account_transactions_filtered("test".to_owned(), None)
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_bank_net_account_transactions() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 20).unwrap();
+        let tr_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+
+        // sender: deposit and a single transfer row including the fee
+        let rows = bank.net_account_transactions("test_1".to_owned()).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                NetTransaction { id: 3, delta: 20 },
+                NetTransaction {
+                    id: seq(tr_id),
+                    delta: -11
+                },
+            ]
+        );
+
+        // recipient: a single incoming row, registration omitted
+        let rows = bank.net_account_transactions("test_2".to_owned()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].delta, 10);
+
+        assert_eq!(
+            bank.net_account_transactions("not_exists".to_owned())
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+
+        // a closed account is gone, as for statement
+        bank.close_to("test_2".to_owned(), "test_1".to_owned())
+            .unwrap();
+        assert_eq!(
+            bank.net_account_transactions("test_2".to_owned())
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+        assert_eq!(
+            bank.statement("test_2".to_owned()).err().unwrap(),
+            AccError::AccountNotExists
+        );
+
+        // the fee account has no rows before the first fee
+        let bank = new_bank(None);
+        let fee_name = bank.acc_storage.fee_account().unwrap().name;
+        assert!(bank.net_account_transactions(fee_name).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bank_apply_interest() {
+        let mut bank = new_bank(None);
+        let mut acc = bank.create_account("test".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 1000).unwrap();
+
+        // 2.5%
+        let tr_id = bank.apply_interest("test".to_owned(), 250).unwrap();
+        assert_eq!(bank.account("test".to_owned()).unwrap().balance(), 1025);
+        assert_eq!(
+            bank.transaction_by_id(tr_id).unwrap().action,
+            TransactionAction::Increment(25)
+        );
+
+        // rounding: 1 * 100 / 10000 == 0
+        let mut small = bank.create_account("small".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut small, 1).unwrap();
+        assert_eq!(
+            bank.apply_interest("small".to_owned(), 100).err().unwrap(),
+            AccError::EmptyTransaction
+        );
+        assert_eq!(bank.account("small".to_owned()).unwrap().balance(), 1);
+
+        assert_eq!(
+            bank.apply_interest("not_exists".to_owned(), 100)
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+
+        // interest that doesn't fit usize is refused instead of truncated
+        let mut rich = bank.create_account("rich".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut rich, usize::MAX).unwrap();
+        let overflow = AccError::Storage("balance overflow".to_string());
+        assert_eq!(
+            bank.apply_interest("rich".to_owned(), 20000).unwrap_err(),
+            overflow
+        );
+        assert_eq!(
+            bank.apply_interest("rich".to_owned(), 100).unwrap_err(),
+            overflow
+        );
+        assert_eq!(
+            bank.account("rich".to_owned()).unwrap().balance(),
+            usize::MAX
+        );
+    }
+
+    #[test]
+    fn test_bank_apply_interest_all() {
+        let mut bank = new_bank(Some(100));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        let mut small = bank.create_account("small".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 1100).unwrap();
+        bank.inc_acc_balance(&mut small, 1).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 500, None, None)
+            .unwrap();
+
+        let ids = bank.apply_interest_all(1000).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 550);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 550);
+        assert_eq!(bank.account("small".to_owned()).unwrap().balance(), 1);
+
+        // fee account is skipped
+        let fee_acc = bank.acc_storage.fee_account().unwrap();
+        assert_eq!(fee_acc.balance(), 100);
+    }
+
+    #[test]
+    fn test_bank_apply_interest_all_checked_first() {
+        let mut bank = new_bank(None);
+        for name in ["a", "b", "c"] {
+            bank.create_account_with_balance(name.to_owned(), 100)
+                .unwrap();
+        }
+        let balances = |bank: &Bank<_, _>| {
+            ["a", "b", "c"].map(|name| bank.account(name.to_owned()).unwrap().balance())
+        };
+
+        // a frozen account in the middle of the batch is skipped
+        bank.freeze_account("b".to_owned()).unwrap();
+        assert_eq!(bank.apply_interest_all(1000).unwrap().len(), 2);
+        assert_eq!(balances(&bank), [110, 100, 110]);
+
+        // a failing account stops the batch before anything is credited
+        bank.create_account_with_balance("z".to_owned(), usize::MAX)
+            .unwrap();
+        assert_eq!(
+            bank.apply_interest_all(1000).unwrap_err(),
+            AccError::Storage("balance overflow".to_string())
+        );
+        assert_eq!(balances(&bank), [110, 100, 110]);
+    }
+
+    #[test]
+    fn test_bank_fee_policy() {
+        let run = |policy: Option<Box<dyn FeePolicy>>| {
+            let mut bank = Bank::new(
+                MemAccountStorage::new().unwrap(),
+                MemTransactionStorage::new(),
+                policy,
+            );
+            let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+            let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+            bank.inc_acc_balance(&mut acc_f, 10000).unwrap();
+            bank.make_transaction(&mut acc_f, &mut acc_s, 2000, None, None)
+                .unwrap();
+
+            let fee_acc = bank.acc_storage.fee_account().unwrap();
+            // the fee account has no transactions until the first fee
+            let fee_trs = bank
+                .account_transactions(fee_acc.name.clone())
+                .unwrap_or_default();
+            (
+                acc_f.balance(),
+                acc_s.balance(),
+                fee_acc.balance(),
+                fee_trs.into_iter().map(|x| x.action).collect::<Vec<_>>(),
+            )
+        };
+
+        // default is no fee
+        assert_eq!(run(None), (8000, 2000, 0, vec![]));
+        assert_eq!(
+            run(Some(Box::new(FlatFee(5)))),
+            (
+                7995,
+                2000,
+                5,
+                vec![TransactionAction::Fee {
+                    transfer_id: 4,
+                    amount: 5
+                }]
+            )
+        );
+        // 2.5% of 2000
+        assert_eq!(
+            run(Some(Box::new(PercentFee { bps: 250 }))),
+            (
+                7950,
+                2000,
+                50,
+                vec![TransactionAction::Fee {
+                    transfer_id: 4,
+                    amount: 50
+                }]
+            )
+        );
+    }
+
+    fn sorted_accounts(bank: &Bank<MemAccountStorage, MemTransactionStorage>) -> Vec<Account> {
+        let mut accs = bank.accounts().unwrap();
+        accs.sort_by(|a, b| a.name.cmp(&b.name));
+        accs
+    }
+
+    fn sorted_transactions(
+        bank: &Bank<MemAccountStorage, MemTransactionStorage>,
+    ) -> Vec<Transaction> {
+        let mut trs = bank.transactions().unwrap();
+        trs.sort_by_key(|x| x.id);
+        trs
+    }
+
+    #[test]
+    fn test_bank_snapshot_round_trip() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.external_withdraw("test_2".to_owned(), 5, "card".to_owned())
+            .unwrap();
+
+        let mut buf = Vec::new();
+        bank.write_snapshot(&mut buf).unwrap();
+        assert_eq!(&buf[..4], snapshot::SNAPSHOT_MAGIC);
+
+        let mut restored = Bank::read_snapshot(
+            &mut buf.as_slice(),
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            Some(Box::new(FlatFee(1))),
+        )
+        .unwrap();
+        assert_eq!(sorted_accounts(&restored), sorted_accounts(&bank));
+        assert_eq!(sorted_transactions(&restored), sorted_transactions(&bank));
+        assert_eq!(
+            restored.acc_storage.fee_account().unwrap().balance(),
+            bank.acc_storage.fee_account().unwrap().balance()
+        );
+
+        // new transactions continue after the restored ids
+        let mut acc = restored.account("test_1".to_owned()).unwrap();
+        let tr_id = restored.inc_acc_balance(&mut acc, 1).unwrap();
+        assert_eq!(seq(tr_id), bank.last_transaction().unwrap().unwrap().id + 1);
+    }
+
+    #[test]
+    fn test_bank_export_import_state() {
+        let mut bank = new_bank(Some(2));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_f, 3, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 20, None, None)
+            .unwrap();
+        bank.set_min_balance("test_1".to_owned(), 5).unwrap();
+
+        let json = bank.export_state().unwrap();
+        let restored = Bank::import_state(
+            &json,
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            Some(Box::new(FlatFee(2))),
+        )
+        .unwrap();
+        assert_eq!(sorted_accounts(&restored), sorted_accounts(&bank));
+        assert_eq!(sorted_transactions(&restored), sorted_transactions(&bank));
+        assert_eq!(restored.acc_storage.fee_account().unwrap().balance(), 6);
+        assert_eq!(
+            restored.account("test_1".to_owned()).unwrap().min_balance,
+            5
+        );
+        assert_eq!(restored.export_state().unwrap(), json);
+
+        assert!(matches!(
+            Bank::import_state(
+                "{not json",
+                MemAccountStorage::new().unwrap(),
+                MemTransactionStorage::new(),
+                None,
+            )
+            .err()
+            .unwrap(),
+            AccError::Storage(_)
+        ));
+    }
+
+    #[test]
+    fn test_bank_snapshot_bad_header() {
+        let read = |data: &[u8]| {
+            Bank::read_snapshot(
+                &mut &data[..],
+                MemAccountStorage::new().unwrap(),
+                MemTransactionStorage::new(),
+                None,
+            )
+            .err()
+            .unwrap()
+        };
+
+        assert_eq!(
+            read(b"XXXX\x01rest"),
+            AccError::Storage("invalid snapshot header".to_owned())
+        );
+        assert_eq!(
+            read(b"RBSN\x03rest"),
+            AccError::Storage("unsupported snapshot version 3".to_owned())
+        );
+        assert!(matches!(read(b"RB"), AccError::Storage(_)));
+    }
+
+    #[test]
+    fn test_bank_make_transaction_idempotency_key() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+
+        let key = Some("key-1".to_owned());
+        let tr_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, key.clone(), None)
+            .unwrap();
+        let trs_count = bank.transactions().unwrap().len();
+
+        // retry returns the same id and moves nothing
+        let retry_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, key.clone(), None)
+            .unwrap();
+        assert_eq!(retry_id, tr_id);
+        assert_eq!(acc_f.balance(), 89);
+        assert_eq!(acc_s.balance(), 10);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 89);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 10);
+        assert_eq!(bank.acc_storage.fee_account().unwrap().balance(), 1);
+        assert_eq!(bank.transactions().unwrap().len(), trs_count);
+
+        // same key for another transfer
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 20, key, None)
+                .err()
+                .unwrap(),
+            AccError::Storage("idempotency key conflict".to_owned())
+        );
+        assert_eq!(acc_f.balance(), 89);
+
+        // without a key every call is a new transfer
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        assert_eq!(acc_s.balance(), 30);
+    }
+
+    #[test]
+    fn test_bank_registration_transaction() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 10).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 5, None, None)
+            .unwrap();
+
+        let tr = bank.registration_transaction("test_2".to_owned()).unwrap();
+        assert_eq!(tr.id, 3);
+        assert_eq!(tr.action, TransactionAction::Registration);
+        assert_eq!(tr.account_name, "test_2");
+        let min_id = bank
+            .account_transactions("test_2".to_owned())
+            .unwrap()
+            .iter()
+            .map(|x| x.id)
+            .min()
+            .unwrap();
+        assert_eq!(tr.id, min_id);
+
+        assert_eq!(
+            bank.registration_transaction("not_exists".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        // the fee account is created by the storage without a registration
+        let fee_acc_name = bank.acc_storage.fee_account().unwrap().name;
+        assert_eq!(
+            bank.registration_transaction(fee_acc_name).err().unwrap(),
+            AccError::TransactionNotExists
+        );
+    }
+
+    #[test]
+    fn test_bank_statement() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.decr_acc_balance(&mut acc_f, 50).unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_f, 4, None, None)
+            .unwrap();
+
+        let lines = bank.statement("test_1".to_owned()).unwrap();
+        assert_eq!(
+            lines
+                .iter()
+                .map(|x| (x.action.clone(), x.balance_after))
+                .collect::<Vec<_>>(),
+            vec![
+                (TransactionAction::Registration, 0),
+                (TransactionAction::Increment(100), 100),
+                (
+                    TransactionAction::TransferOut {
+                        to: "test_2".to_owned(),
+                        value: 10,
+                        fee: 1,
+                        memo: None,
+                    },
+                    89
+                ),
+                (TransactionAction::Decrement(50), 39),
+                (
+                    TransactionAction::TransferIn {
+                        from: "test_2".to_owned(),
+                        value: 4,
+                        memo: None,
+                    },
+                    43
+                ),
+            ]
+        );
+        assert!(lines.windows(2).all(|w| w[0].id < w[1].id));
+        assert_eq!(lines.last().unwrap().balance_after, acc_f.balance());
+
+        // recipient side of the first transfer is an incoming credit
+        let lines = bank.statement("test_2".to_owned()).unwrap();
+        assert_eq!(
+            lines[1].action,
+            TransactionAction::TransferIn {
+                from: "test_1".to_owned(),
+                value: 10,
+                memo: None,
+            }
+        );
+        assert_eq!(lines[1].action.kind(), ActionKind::TransferIn);
+        assert_eq!(lines[1].balance_after, 10);
+        assert_eq!(lines.last().unwrap().balance_after, acc_s.balance());
+
+        assert_eq!(
+            bank.statement("not_exists".to_owned()).err().unwrap(),
+            AccError::AccountNotExists
+        );
+
+        // the fee account has an empty statement until the first fee is charged
+        let mut bank = new_bank(None);
+        let fee_name = bank.acc_storage.fee_account().unwrap().name;
+        assert!(bank.statement(fee_name.clone()).unwrap().is_empty());
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(2))
+            .unwrap();
+        let lines = bank.statement(fee_name).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].balance_after, 2);
+    }
+
+    #[test]
+    fn test_bank_accounts_paged() {
+        let mut bank = new_bank(Some(1));
+        for (name, balance) in [("carol", 30), ("alice", 10), ("dave", 10), ("bob", 50)] {
+            let mut acc = bank.create_account(name.to_owned()).unwrap();
+            bank.inc_acc_balance(&mut acc, balance).unwrap();
+        }
+        let names = |accs: Vec<Account>| accs.into_iter().map(|x| x.name).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(
+                bank.accounts_paged(0, 10, AccountSort::NameAsc, false)
+                    .unwrap()
+            ),
+            vec!["alice", "bob", "carol", "dave"]
+        );
+        // equal balances are ordered by name
+        assert_eq!(
+            names(
+                bank.accounts_paged(0, 10, AccountSort::BalanceDesc, false)
+                    .unwrap()
+            ),
+            vec!["bob", "carol", "alice", "dave"]
+        );
+        assert_eq!(
+            names(
+                bank.accounts_paged(0, 10, AccountSort::BalanceAsc, false)
+                    .unwrap()
+            ),
+            vec!["alice", "dave", "carol", "bob"]
+        );
+
+        // pages
+        assert_eq!(
+            names(
+                bank.accounts_paged(1, 2, AccountSort::NameAsc, false)
+                    .unwrap()
+            ),
+            vec!["bob", "carol"]
+        );
+        assert!(bank
+            .accounts_paged(4, 2, AccountSort::NameAsc, false)
+            .unwrap()
+            .is_empty());
+
+        // stable across calls
+        for _ in 0..10 {
+            assert_eq!(
+                names(
+                    bank.accounts_paged(0, 10, AccountSort::BalanceDesc, false)
+                        .unwrap()
+                ),
+                vec!["bob", "carol", "alice", "dave"]
+            );
+        }
+
+        // fee account
+        let fee_acc_name = bank.acc_storage.fee_account().unwrap().name;
+        let all = names(
+            bank.accounts_paged(0, 10, AccountSort::NameAsc, true)
+                .unwrap(),
+        );
+        assert_eq!(all.len(), 5);
+        assert!(all.contains(&fee_acc_name));
+    }
+
+    #[test]
+    fn test_bank_total_assets_and_aggregates() {
+        let mut bank = new_bank(Some(2));
+        assert_eq!(bank.total_assets().unwrap(), 0);
+        assert_eq!(bank.aggregates().unwrap(), Aggregates::default());
+
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.inc_acc_balance(&mut acc_s, 50).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_f, 10, None, None)
+            .unwrap();
+        bank.decr_acc_balance(&mut acc_s, 5).unwrap();
+        bank.external_deposit("test_1".to_owned(), 20, "wire".to_owned())
+            .unwrap();
+        bank.external_withdraw("test_2".to_owned(), 7, "card".to_owned())
+            .unwrap();
+
+        let aggs = bank.aggregates().unwrap();
+        assert_eq!(
+            aggs,
+            Aggregates {
+                total_added: 170,
+                total_withdrawn: 12,
+                total_transferred: 40,
+                total_fees: 4,
+            }
+        );
+        assert_eq!(bank.acc_storage.fee_account().unwrap().balance(), 4);
+
+        // transfers and fees only move money inside the bank
+        assert_eq!(bank.total_assets().unwrap(), 158);
+        assert_eq!(
+            bank.total_assets().unwrap(),
+            aggs.total_added - aggs.total_withdrawn
+        );
+
+        // a deposit into the fee account is not a fee
+        let mut fee_acc = bank.account("fee_acc".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut fee_acc, 6).unwrap();
+        let aggs = bank.aggregates().unwrap();
+        assert_eq!((aggs.total_added, aggs.total_fees), (176, 4));
+        assert_eq!(
+            bank.total_assets().unwrap(),
+            aggs.total_added - aggs.total_withdrawn
+        );
+    }
+
+    #[test]
+    fn test_bank_total_assets_overflow() {
+        let mut bank = new_bank(None);
+        bank.create_account_with_balance("test_1".to_owned(), usize::MAX)
+            .unwrap();
+        bank.create_account_with_balance("test_2".to_owned(), 1)
+            .unwrap();
+
+        let overflow = AccError::Storage("total overflow".to_string());
+        assert_eq!(bank.total_assets().unwrap_err(), overflow);
+        assert_eq!(bank.aggregates().unwrap_err(), overflow);
+    }
+
+    #[test]
+    fn test_bank_reverse_transaction() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        let balance =
+            |bank: &Bank<_, _>, name: &str| bank.account(name.to_owned()).unwrap().balance();
+
+        // increment
+        let inc_id = bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        let rev_id = bank.reverse_transaction(inc_id).unwrap();
+        assert_eq!(balance(&bank, "test_1"), 0);
+        assert_eq!(
+            bank.transaction_by_id(rev_id).unwrap().action,
+            TransactionAction::Decrement(100)
+        );
+
+        // decrement
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        let decr_id = bank.decr_acc_balance(&mut acc_f, 40).unwrap();
+        let rev_id = bank.reverse_transaction(decr_id).unwrap();
+        assert_eq!(balance(&bank, "test_1"), 100);
+        assert_eq!(
+            bank.transaction_by_id(rev_id).unwrap().action,
+            TransactionAction::Increment(40)
+        );
+
+        // transfer: value goes back, fee stays in the fee account
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        let tr_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        assert_eq!(balance(&bank, "test_1"), 69);
+        assert_eq!(balance(&bank, "test_2"), 30);
+        let rev_id = bank.reverse_transaction(tr_id).unwrap();
+        assert_eq!(balance(&bank, "test_1"), 99);
+        assert_eq!(balance(&bank, "test_2"), 0);
+        assert_eq!(bank.acc_storage.fee_account().unwrap().balance(), 1);
+        let rev = bank.transaction_by_id(rev_id).unwrap();
+        assert_eq!(rev.account_name, "test_2");
+        assert_eq!(
+            rev.action,
+            TransactionAction::TransferOut {
+                to: "test_1".to_owned(),
+                value: 30,
+                fee: 0,
+                memo: None,
+            }
+        );
+        // the recipient side can't be reversed on its own
+        assert_eq!(
+            bank.reverse_transaction(seq(tr_id) + 1).err().unwrap(),
+            AccError::Storage("only the sender side of a transfer can be reversed".to_owned())
+        );
+        // nor can its fee
+        assert_eq!(
+            bank.transaction_by_id(seq(tr_id) + 2).unwrap().action,
+            TransactionAction::Fee {
+                transfer_id: seq(tr_id),
+                amount: 1
+            }
+        );
+        assert_eq!(
+            bank.reverse_transaction(seq(tr_id) + 2).err().unwrap(),
+            AccError::Storage("only the sender side of a transfer can be reversed".to_owned())
+        );
+        assert_eq!(bank.acc_storage.fee_account().unwrap().balance(), 1);
+        // while a plain deposit into the fee account is reversible
+        let mut fee_acc = bank.account("fee_acc".to_owned()).unwrap();
+        let fee_dep_id = bank.inc_acc_balance(&mut fee_acc, 1).unwrap();
+        bank.reverse_transaction(fee_dep_id).unwrap();
+        assert_eq!(bank.acc_storage.fee_account().unwrap().balance(), 1);
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+
+        // external
+        let ext_id = bank
+            .external_deposit("test_2".to_owned(), 10, "wire".to_owned())
+            .unwrap();
+        bank.reverse_transaction(ext_id).unwrap();
+        assert_eq!(balance(&bank, "test_2"), 0);
+
+        // double reverse
+        assert_eq!(
+            bank.reverse_transaction(inc_id).err().unwrap(),
+            AccError::Storage("transaction already reversed".to_owned())
+        );
+        assert_eq!(
+            bank.reverse_transaction(tr_id).err().unwrap(),
+            AccError::Storage("transaction already reversed".to_owned())
+        );
+        assert_eq!(balance(&bank, "test_1"), 99);
+
+        // registration and unknown ids
+        assert_eq!(
+            bank.reverse_transaction(1).err().unwrap(),
+            AccError::Storage("cannot reverse registration".to_owned())
+        );
+        assert_eq!(
+            bank.reverse_transaction(999).err().unwrap(),
+            AccError::TransactionNotExists
+        );
+
+        // reversing a deposit that was already spent
+        let mut acc_s = bank.account("test_2".to_owned()).unwrap();
+        let inc_id = bank.inc_acc_balance(&mut acc_s, 10).unwrap();
+        bank.decr_acc_balance(&mut acc_s, 5).unwrap();
+        assert_eq!(
+            bank.reverse_transaction(inc_id).err().unwrap(),
+            AccError::NotEnoughMoney
+        );
+        // a failed reversal can be retried later
+        bank.inc_acc_balance(&mut acc_s, 5).unwrap();
+        assert!(bank.reverse_transaction(inc_id).is_ok());
+    }
+
+    #[test]
+    fn test_bank_reverse_transaction_after_import() {
+        let mut bank = new_bank(None);
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let inc_id = bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        let rev_id = bank.reverse_transaction(inc_id).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        assert_eq!(
+            bank.transaction_by_id(seq(rev_id) + 1).unwrap().action,
+            TransactionAction::Reversal { tr_id: seq(inc_id) }
+        );
+
+        // the marker is part of the ledger, so the reversal isn't repeated elsewhere
+        let already = AccError::Storage("transaction already reversed".to_owned());
+        let mut imported = Bank::import_state(
+            &bank.export_state().unwrap(),
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(imported.reverse_transaction(inc_id).unwrap_err(), already);
+
+        let mut buf = Vec::new();
+        bank.write_snapshot(&mut buf).unwrap();
+        let mut restored = Bank::read_snapshot(
+            &mut buf.as_slice(),
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(restored.reverse_transaction(inc_id).unwrap_err(), already);
+
+        let mut replica = new_bank(None);
+        replica.replay_onto(&bank.transactions().unwrap()).unwrap();
+        assert_eq!(replica.reverse_transaction(inc_id).unwrap_err(), already);
+        assert_eq!(replica.account("test_1".to_owned()).unwrap().balance(), 100);
+        assert_eq!(
+            bank.reverse_transaction(seq(rev_id) + 1).unwrap_err(),
+            AccError::Storage("cannot reverse a reversal".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_bank_reverse_transaction_restricted_recipient() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.set_transfer_limit("test_2".to_owned(), Some(20))
+            .unwrap();
+
+        // a refund of 30 doesn't count against the recipient's limit of 20
+        let tr_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        bank.reverse_transaction(tr_id).unwrap();
+        let acc_s = bank.account("test_2".to_owned()).unwrap();
+        assert_eq!(acc_s.balance(), 0);
+        assert_eq!(acc_s.transferred, 0);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 99);
+
+        // a frozen recipient is refunded too (it can't move the money itself)
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.account("test_2".to_owned()).unwrap();
+        let tr_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 40, None, None)
+            .unwrap();
+        bank.freeze_account("test_2".to_owned()).unwrap();
+        bank.reverse_transaction(tr_id).unwrap();
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 0);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 98);
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+
+        // the freeze and the limit still apply to the recipient's own operations
+        let mut acc_s = bank.account("test_2".to_owned()).unwrap();
+        assert_eq!(
+            bank.inc_acc_balance(&mut acc_s, 5).err().unwrap(),
+            AccError::AccountFrozen
+        );
+        bank.unfreeze_account("test_2".to_owned()).unwrap();
+        let mut acc_s = bank.account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_s, 50).unwrap();
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!(
+            bank.make_transaction(&mut acc_s, &mut acc_f, 21, None, Some(0))
+                .err()
+                .unwrap(),
+            AccError::TransferLimitExceeded
+        );
+    }
+
+    #[test]
+    fn test_bank_rename_account() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("tset_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_f, 5, None, None)
+            .unwrap();
+
+        bank.rename_account("tset_1".to_owned(), "test_1".to_owned())
+            .unwrap();
+        assert_eq!(
+            bank.account("tset_1".to_owned()).err().unwrap(),
+            AccError::AccountNotExists
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 94);
+        assert!(bank.account_transactions("tset_1".to_owned()).is_err());
+
+        let trs = bank.account_transactions("test_1".to_owned()).unwrap();
+        assert_eq!(trs.len(), 5);
+        assert!(trs.iter().all(|tr| tr.account_name == "test_1"));
+        assert_eq!(
+            trs[3].action,
+            TransactionAction::TransferIn {
+                from: "test_2".to_owned(),
+                value: 5,
+                memo: None,
+            }
+        );
+        assert_eq!(
+            trs[4].action,
+            TransactionAction::Renamed {
+                from: "tset_1".to_owned()
+            }
+        );
+
+        // counterparty references in the other account history
+        let trs = bank.account_transactions("test_2".to_owned()).unwrap();
+        assert_eq!(
+            trs[1].action,
+            TransactionAction::TransferIn {
+                from: "test_1".to_owned(),
+                value: 10,
+                memo: None,
+            }
+        );
+        assert_eq!(
+            trs[2].action,
+            TransactionAction::TransferOut {
+                to: "test_1".to_owned(),
+                value: 5,
+                fee: 1,
+                memo: None,
+            }
+        );
+        assert!(!bank
+            .transactions()
+            .unwrap()
+            .iter()
+            .filter(|tr| tr.action.kind() != ActionKind::Renamed)
+            .any(|tr| tr.to_string().contains("tset_1")));
+
+        // restoring from the log still gives the same balance
+        let acc = bank
+            .restore_account_from_transactions("test_1".to_owned())
+            .unwrap();
+        assert_eq!(acc.balance(), 94);
+
+        // errors
+        assert_eq!(
+            bank.rename_account("test_1".to_owned(), "test_2".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountAlreadyExists
+        );
+        assert_eq!(
+            bank.rename_account("missing".to_owned(), "other".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        assert_eq!(
+            bank.rename_account("fee_acc".to_owned(), "other".to_owned())
+                .err()
+                .unwrap(),
+            AccError::InvalidAccountName
+        );
+        assert_eq!(
+            bank.rename_account("test_1".to_owned(), "x".repeat(MAX_ACCOUNT_NAME_LEN + 1))
+                .err()
+                .unwrap(),
+            AccError::InvalidAccountName
+        );
+    }
+
+    #[test]
+    fn test_bank_min_balance() {
+        let mut bank = new_bank(Some(1));
+        bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.set_min_balance("test_1".to_owned(), 100).unwrap();
+
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!(acc_f.min_balance, 100);
+        bank.inc_acc_balance(&mut acc_f, 150).unwrap();
+        assert_eq!(
+            bank.decr_acc_balance(&mut acc_f, 60).err().unwrap(),
+            AccError::BelowMinimumBalance
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 150);
+        bank.decr_acc_balance(&mut acc_f, 50).unwrap();
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 100);
+
+        // transfers count the fee
+        bank.inc_acc_balance(&mut acc_f, 10).unwrap();
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+                .err()
+                .unwrap(),
+            AccError::BelowMinimumBalance
+        );
+        bank.make_transaction(&mut acc_f, &mut acc_s, 9, None, None)
+            .unwrap();
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 100);
+        assert_eq!(
+            bank.external_withdraw("test_1".to_owned(), 1, "atm".to_owned())
+                .err()
+                .unwrap(),
+            AccError::BelowMinimumBalance
+        );
+
+        // not enough money takes precedence, the limit survives a restore
+        assert_eq!(
+            bank.decr_acc_balance(&mut acc_f, 101).err().unwrap(),
+            AccError::NotEnoughMoney
+        );
+        let acc = bank
+            .restore_account_from_transactions("test_1".to_owned())
+            .unwrap();
+        assert_eq!(acc.min_balance, 100);
+
+        assert_eq!(
+            bank.set_min_balance("fee_acc".to_owned(), 1).err().unwrap(),
+            AccError::InvalidAccountName
+        );
+        assert_eq!(
+            bank.set_min_balance("missing".to_owned(), 1).err().unwrap(),
+            AccError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_bank_min_balance_stale_handle() {
+        let mut bank = new_bank(None);
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+
+        // the minimum set after the handle was taken is still enforced
+        bank.set_min_balance("test_1".to_owned(), 50).unwrap();
+        assert_eq!(acc_f.min_balance, 0);
+        assert_eq!(
+            bank.decr_acc_balance(&mut acc_f, 60).err().unwrap(),
+            AccError::BelowMinimumBalance
+        );
+        assert_eq!(acc_f.min_balance, 50);
+
+        // and a handle with another minimum doesn't overwrite it
+        acc_f.min_balance = 0;
+        bank.decr_acc_balance(&mut acc_f, 10).unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.min_balance), (90, 50));
+    }
+
+    #[test]
+    fn test_bank_create_accounts() {
+        let mut bank = new_bank(None);
+        bank.create_account("test_2".to_owned()).unwrap();
+
+        let res = bank.create_accounts(vec![
+            "test_1".to_owned(),
+            "test_2".to_owned(),
+            "test_3".to_owned(),
+            "test_1".to_owned(),
+            "x".repeat(MAX_ACCOUNT_NAME_LEN + 1),
+        ]);
+        assert_eq!(res.len(), 5);
+        assert_eq!(res[0].as_ref().unwrap().name, "test_1");
+        assert_eq!(
+            res[1].as_ref().err().unwrap(),
+            &AccError::AccountAlreadyExists
+        );
+        assert_eq!(res[2].as_ref().unwrap().name, "test_3");
+        assert_eq!(
+            res[3].as_ref().err().unwrap(),
+            &AccError::AccountAlreadyExists
+        );
+        assert_eq!(
+            res[4].as_ref().err().unwrap(),
+            &AccError::InvalidAccountName
+        );
+
+        // fee account + 3 created
+        assert_eq!(bank.accounts().unwrap().len(), 4);
+        assert!(bank.create_accounts(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_bank_account_exists() {
+        let mut bank = new_bank(None);
+        bank.create_account("test_1".to_owned()).unwrap();
+
+        assert!(bank.account_exists("test_1".to_owned()).unwrap());
+        assert!(!bank.account_exists("test_2".to_owned()).unwrap());
+        assert!(bank.account_exists("fee_acc".to_owned()).unwrap());
+        // nothing is created by the check
+        assert!(!bank.account_exists("test_2".to_owned()).unwrap());
+        assert_eq!(bank.accounts().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_bank_freeze_account() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.inc_acc_balance(&mut acc_s, 100).unwrap();
+
+        bank.freeze_account("test_1".to_owned()).unwrap();
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        assert!(acc_f.frozen);
+        assert_eq!(
+            bank.inc_acc_balance(&mut acc_f, 10).err().unwrap(),
+            AccError::AccountFrozen
+        );
+        assert_eq!(
+            bank.decr_acc_balance(&mut acc_f, 10).err().unwrap(),
+            AccError::AccountFrozen
+        );
+        // as sender and as receiver
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+                .err()
+                .unwrap(),
+            AccError::AccountFrozen
+        );
+        assert_eq!(
+            bank.make_transaction(&mut acc_s, &mut acc_f, 10, None, None)
+                .err()
+                .unwrap(),
+            AccError::AccountFrozen
+        );
+        assert_eq!(
+            bank.external_deposit("test_1".to_owned(), 10, "wire".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountFrozen
+        );
+        // reads still work, nothing moved
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 100);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 100);
+        assert_eq!(bank.acc_storage.fee_account().unwrap().balance(), 0);
+        assert_eq!(
+            bank.account_transactions("test_1".to_owned())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        bank.unfreeze_account("test_1".to_owned()).unwrap();
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 10).unwrap();
+        bank.decr_acc_balance(&mut acc_f, 20).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 79);
+
+        assert_eq!(
+            bank.freeze_account("fee_acc".to_owned()).err().unwrap(),
+            AccError::InvalidAccountName
+        );
+        assert_eq!(
+            bank.freeze_account("missing".to_owned()).err().unwrap(),
+            AccError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_bank_transfer_limit() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 200).unwrap();
+        bank.set_transfer_limit("test_1".to_owned(), Some(50))
+            .unwrap();
+
+        // the fee doesn't count towards the limit
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 20, None, None)
+            .unwrap();
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().transferred, 50);
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 1, None, None)
+                .err()
+                .unwrap(),
+            AccError::TransferLimitExceeded
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 148);
+        // withdrawals are not transfers
+        bank.decr_acc_balance(&mut acc_f, 8).unwrap();
+
+        bank.reset_transfer_usage("test_1".to_owned()).unwrap();
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 51, None, None)
+                .err()
+                .unwrap(),
+            AccError::TransferLimitExceeded
+        );
+        bank.make_transaction(&mut acc_f, &mut acc_s, 50, None, None)
+            .unwrap();
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 100);
+
+        // no limit
+        bank.set_transfer_limit("test_1".to_owned(), None).unwrap();
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 60, None, None)
+            .unwrap();
+        assert_eq!(
+            bank.set_transfer_limit("missing".to_owned(), Some(1))
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        assert_eq!(
+            bank.reset_transfer_usage("missing".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_bank_transfer_limit_failed_store() {
+        let acc_storage = BrokenWrites {
+            inner: MemAccountStorage::new().unwrap(),
+            broken: false,
+        };
+        let mut bank = Bank::new(acc_storage, MemTransactionStorage::new(), None);
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.set_transfer_limit("test_1".to_owned(), Some(50))
+            .unwrap();
+
+        // a transfer that wasn't stored isn't counted
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        bank.acc_storage.broken = true;
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+                .err()
+                .unwrap(),
+            AccError::Storage("write failed".to_owned())
+        );
+        assert_eq!((acc_f.balance(), acc_f.transferred), (100, 0));
+        bank.acc_storage.broken = false;
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().transferred, 0);
+
+        bank.make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        assert_eq!((acc_f.balance(), acc_f.transferred), (70, 30));
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().transferred, 30);
+    }
+
+    #[test]
+    fn test_bank_transfer_limit_stale_handle() {
+        let mut bank = new_bank(None);
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 200).unwrap();
+
+        // the limit set after the handle was taken is still enforced
+        bank.set_transfer_limit("test_1".to_owned(), Some(50))
+            .unwrap();
+        assert_eq!(acc_f.transfer_limit, None);
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 60, None, None)
+                .err()
+                .unwrap(),
+            AccError::TransferLimitExceeded
+        );
+
+        // usage counts from the stored value, not from the handle
+        bank.make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        let mut stale = bank.account("test_1".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        stale.transfer_limit = None;
+        assert_eq!(
+            bank.make_transaction(&mut stale, &mut acc_s, 20, None, None)
+                .err()
+                .unwrap(),
+            AccError::TransferLimitExceeded
+        );
+        bank.make_transaction(&mut stale, &mut acc_s, 10, None, None)
+            .unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.transferred, acc.transfer_limit), (50, Some(50)));
+
+        // the usage counter saturates instead of overflowing
+        bank.set_transfer_limit("test_1".to_owned(), None).unwrap();
+        let mut raw = bank.acc_storage.get_account("test_1".to_owned()).unwrap();
+        raw.transferred = usize::MAX - 1;
+        bank.acc_storage.update_account(raw).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        assert_eq!(
+            bank.account("test_1".to_owned()).unwrap().transferred,
+            usize::MAX
+        );
+    }
+
+    #[test]
+    fn test_bank_flush() {
+        let mut bank = Bank::new(
+            FlushRecorder {
+                inner: MemAccountStorage::new().unwrap(),
+                flushed: false,
+            },
+            MemTransactionStorage::new(),
+            None,
+        );
+        bank.create_account("test_1".to_owned()).unwrap();
+        assert!(!bank.acc_storage.flushed);
+
+        bank.flush().unwrap();
+        assert!(bank.acc_storage.flushed);
+        // default no-op for storages without buffering
+        assert!(new_bank(None).flush().is_ok());
+    }
+
+    #[test]
+    fn test_bank_merge_account() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        let mut acc_t = bank.create_account("test_3".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.inc_acc_balance(&mut acc_s, 50).unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_t, 10, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_t, &mut acc_s, 5, None, None)
+            .unwrap();
+
+        bank.merge_account("test_2".to_owned(), "test_1".to_owned())
+            .unwrap();
+        assert!(!bank.account_exists("test_2".to_owned()).unwrap());
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 144);
+        assert!(bank.account_transactions("test_2".to_owned()).is_err());
+
+        // history: 2 own + 4 of the source + merge transfer out and in + marker
+        let trs = bank.account_transactions("test_1".to_owned()).unwrap();
+        assert_eq!(trs.len(), 9);
+        assert_eq!(
+            trs[8].action,
+            TransactionAction::Merged {
+                from: "test_2".to_owned()
+            }
+        );
+        assert!(trs.windows(2).all(|w| w[0].id < w[1].id));
+        assert!(trs.iter().all(|tr| tr.account_name == "test_1"));
+        assert!(!bank
+            .transactions()
+            .unwrap()
+            .iter()
+            .filter(|tr| tr.action.kind() != ActionKind::Merged)
+            .any(|tr| tr.to_string().contains("test_2")));
+        assert_eq!(
+            bank.account_transactions("test_3".to_owned()).unwrap()[1].action,
+            TransactionAction::TransferIn {
+                from: "test_1".to_owned(),
+                value: 10,
+                memo: None,
+            }
+        );
+        // the merged log replays to the same balance
+        assert_eq!(
+            bank.restore_account_from_transactions("test_1".to_owned())
+                .unwrap()
+                .balance(),
+            144
+        );
+        assert_eq!(bank.acc_storage.fee_account().unwrap().balance(), 2);
+
+        // an empty source only moves history
+        bank.create_account("test_4".to_owned()).unwrap();
+        bank.merge_account("test_4".to_owned(), "test_3".to_owned())
+            .unwrap();
+        assert_eq!(bank.account("test_3".to_owned()).unwrap().balance(), 4);
+        assert_eq!(
+            bank.account_transactions("test_3".to_owned())
+                .unwrap()
+                .len(),
+            5
+        );
+
+        // errors
+        for (source, target) in [
+            ("test_1", "test_1"),
+            ("fee_acc", "test_1"),
+            ("test_1", "fee_acc"),
+        ] {
+            assert_eq!(
+                bank.merge_account(source.to_owned(), target.to_owned())
+                    .err()
+                    .unwrap(),
+                AccError::InvalidAccountName
+            );
+        }
+        assert_eq!(
+            bank.merge_account("missing".to_owned(), "test_1".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        assert_eq!(
+            bank.merge_account("test_1".to_owned(), "missing".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        assert!(bank.account_exists("test_1".to_owned()).unwrap());
+    }
+
+    #[test]
+    fn test_bank_merge_account_with_hold() {
+        let mut bank = new_bank(None);
+        bank.create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        bank.create_account_with_balance("test_2".to_owned(), 10)
+            .unwrap();
+
+        let hold_id = bank.hold("test_1".to_owned(), 40).unwrap();
+        let count = bank.transaction_count().unwrap();
+        assert_eq!(
+            bank.merge_account("test_1".to_owned(), "test_2".to_owned())
+                .err()
+                .unwrap(),
+            AccError::HoldsOpen
+        );
+        assert_eq!(bank.transaction_count().unwrap(), count);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().held(), 40);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 10);
+
+        // settling the hold frees the source for the merge
+        bank.settle(hold_id, "test_2".to_owned()).unwrap();
+        bank.merge_account("test_1".to_owned(), "test_2".to_owned())
+            .unwrap();
+        assert!(!bank.account_exists("test_1".to_owned()).unwrap());
+        let acc = bank.account("test_2".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.held()), (110, 0));
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_bank_create_account_with_balance() {
+        let mut bank = new_bank(None);
+        let acc = bank
+            .create_account_with_balance("test_1".to_owned(), 500)
+            .unwrap();
+        assert_eq!(acc.balance(), 500);
+        let acc = bank
+            .create_account_with_balance("test_2".to_owned(), 0)
+            .unwrap();
+        assert_eq!(acc.balance(), 0);
+        assert_eq!(
+            bank.account_transactions("test_2".to_owned())
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            bank.create_account_with_balance("test_1".to_owned(), 1)
+                .err()
+                .unwrap(),
+            AccError::AccountAlreadyExists
+        );
+
+        // the fee account has no transactions yet, so replay the seeded accounts only
+        let mut restored = new_bank(None);
+        for name in ["test_1", "test_2"] {
+            Account::restore_account_from_transactions(
+                name.to_owned(),
+                &mut restored.acc_storage,
+                &bank.tr_storage,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            restored.account("test_1".to_owned()).unwrap().balance(),
+            500
+        );
+        assert_eq!(restored.account("test_2".to_owned()).unwrap().balance(), 0);
+    }
+
+    #[test]
+    fn test_bank_fee_balance() {
+        let mut bank = new_bank(Some(3));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        assert_eq!(bank.fee_balance().unwrap(), 0);
+
+        for value in [5, 10, 20] {
+            bank.make_transaction(&mut acc_f, &mut acc_s, value, None, None)
+                .unwrap();
+        }
+        bank.make_transaction(&mut acc_s, &mut acc_f, 1, None, None)
+            .unwrap();
+        assert_eq!(bank.fee_balance().unwrap(), 12);
+        assert_eq!(
+            bank.fee_balance().unwrap(),
+            bank.acc_storage.fee_account().unwrap().balance()
+        );
+    }
+
+    #[test]
+    fn test_bank_currency_balances() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance_in(&mut acc_f, 50, "EUR".to_owned(), None)
+            .unwrap();
+        assert_eq!(acc_f.balance(), 100);
+        assert_eq!(acc_f.balance_in(DEFAULT_CURRENCY), 100);
+        assert_eq!(acc_f.balance_in("EUR"), 50);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap(), acc_f);
+
+        // a transfer moves the value and the fee in its currency only
+        let tr_id = bank
+            .make_transaction_with_options(&mut acc_f, &mut acc_s, 20, None, in_currency("EUR"))
+            .unwrap();
+        assert_eq!((acc_f.balance(), acc_f.balance_in("EUR")), (100, 29));
+        assert_eq!((acc_s.balance(), acc_s.balance_in("EUR")), (0, 20));
+        assert_eq!(bank.fee_balance_in("EUR".to_owned()).unwrap(), 1);
+        assert_eq!(bank.fee_balance().unwrap(), 0);
+        let tr = bank.transaction_by_id(tr_id).unwrap();
+        assert_eq!(tr.currency.as_deref(), Some("EUR"));
+
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        assert_eq!((acc_f.balance(), acc_f.balance_in("EUR")), (89, 29));
+        assert_eq!(bank.fee_balance().unwrap(), 1);
+        assert_eq!(bank.fee_balance_in("EUR".to_owned()).unwrap(), 1);
+        assert_eq!(
+            bank.transaction_by_id(seq(tr_id) + 3).unwrap().currency,
+            None
+        );
+
+        // the other currencies don't cover a shortfall
+        assert_eq!(
+            bank.make_transaction_with_options(
+                &mut acc_f,
+                &mut acc_s,
+                29,
+                None,
+                in_currency("EUR")
+            )
+            .err()
+            .unwrap(),
+            AccError::NotEnoughMoney
+        );
+        assert_eq!(
+            bank.make_transaction_with_options(&mut acc_s, &mut acc_f, 1, None, in_currency("GBP"))
+                .err()
+                .unwrap(),
+            AccError::NotEnoughMoney
+        );
+        bank.decr_acc_balance_in(&mut acc_f, 9, "EUR".to_owned(), None)
+            .unwrap();
+        assert_eq!(acc_f.balance_in("EUR"), 20);
+        assert_eq!(
+            bank.decr_acc_balance_in(&mut acc_f, 21, "EUR".to_owned(), None)
+                .err()
+                .unwrap(),
+            AccError::NotEnoughMoney
+        );
+
+        // the minimum balance and holds are in the default currency
+        bank.set_min_balance("test_1".to_owned(), 80).unwrap();
+        bank.hold("test_1".to_owned(), 9).unwrap();
+        bank.decr_acc_balance_in(&mut acc_f, 20, "EUR".to_owned(), None)
+            .unwrap();
+        assert_eq!(acc_f.balance_in("EUR"), 0);
+        assert!(!acc_f.balances.contains_key("EUR"));
+        assert_eq!(acc_f.balance(), 89);
+
+        for currency in ["eur", "EURO", ""] {
+            assert_eq!(
+                bank.inc_acc_balance_in(&mut acc_f, 1, currency.to_owned(), None)
+                    .err()
+                    .unwrap(),
+                AccError::InvalidCurrency
+            );
+        }
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_bank_currency_transfer_options() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance_in(&mut acc_f, 50, "EUR".to_owned(), Some("cash".to_owned()))
+            .unwrap();
+        assert_eq!(
+            bank.account_transactions("test_1".to_owned()).unwrap()[1]
+                .memo
+                .as_deref(),
+            Some("cash")
+        );
+
+        // memo, idempotency key and fee override apply in any currency
+        let options = TransferOptions {
+            fee: Some(0),
+            memo: Some("rent".to_owned()),
+            currency: Some("EUR".to_owned()),
+        };
+        let key = Some("rent".to_owned());
+        let tr_id = bank
+            .make_transaction_with_options(&mut acc_f, &mut acc_s, 20, key.clone(), options.clone())
+            .unwrap();
+        let tr = bank.transaction_by_id(tr_id).unwrap();
+        assert_eq!(tr.currency.as_deref(), Some("EUR"));
+        assert_eq!(tr.action.memo(), Some("rent"));
+        assert_eq!(
+            bank.make_transaction_with_options(&mut acc_f, &mut acc_s, 20, key.clone(), options)
+                .unwrap(),
+            tr_id
+        );
+        assert_eq!((acc_f.balance_in("EUR"), acc_s.balance_in("EUR")), (30, 20));
+        assert_eq!(bank.fee_balance_in("EUR".to_owned()).unwrap(), 0);
+
+        // the same key in another currency is another transfer
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 20, key, None)
+                .unwrap_err(),
+            AccError::Storage("idempotency key conflict".to_owned())
+        );
+        assert_eq!(
+            bank.make_transaction_with_options(
+                &mut acc_f,
+                &mut acc_s,
+                10,
+                None,
+                in_currency("eur")
+            )
+            .unwrap_err(),
+            AccError::InvalidCurrency
+        );
+    }
+
+    #[test]
+    fn test_bank_currency_external_and_holds() {
+        let mut bank = new_bank(Some(1));
+        bank.create_account_with_balance("test_1".to_owned(), 10)
+            .unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+
+        let dep_id = bank
+            .external_deposit_in(
+                "test_1".to_owned(),
+                100,
+                "EUR".to_owned(),
+                "wire-1".to_owned(),
+            )
+            .unwrap();
+        bank.external_withdraw_in(
+            "test_1".to_owned(),
+            30,
+            "EUR".to_owned(),
+            "card-7".to_owned(),
+        )
+        .unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.balance_in("EUR")), (10, 70));
+        assert_eq!(
+            bank.external_withdraw_in("test_1".to_owned(), 71, "EUR".to_owned(), "x".to_owned())
+                .unwrap_err(),
+            AccError::NotEnoughMoney
+        );
+        assert_eq!(
+            bank.external_deposit_in("test_1".to_owned(), 1, "eur".to_owned(), "x".to_owned())
+                .unwrap_err(),
+            AccError::InvalidCurrency
+        );
+
+        // a hold reserves only its own currency
+        let hold_id = bank
+            .hold_in("test_1".to_owned(), 60, "EUR".to_owned())
+            .unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.held(), acc.held_in("EUR")), (0, 60));
+        assert_eq!(
+            (acc.available_balance(), acc.available_balance_in("EUR")),
+            (10, 10)
+        );
+        assert_eq!(
+            acc.to_string(),
+            "Account: test_1. Balance: 10. Balance EUR: 70. Held EUR: 60"
+        );
+        assert_eq!(
+            bank.hold_in("test_1".to_owned(), 11, "EUR".to_owned())
+                .unwrap_err(),
+            AccError::NotEnoughMoney
+        );
+
+        // settle moves the held amount in its currency, the fee is charged in it too
+        let tr_id = bank.settle(hold_id, "test_2".to_owned()).unwrap();
+        assert_eq!(
+            bank.transaction_by_id(tr_id).unwrap().currency.as_deref(),
+            Some("EUR")
+        );
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance_in("EUR"), acc.held_in("EUR")), (9, 0));
+        assert_eq!(
+            bank.account("test_2".to_owned()).unwrap().balance_in("EUR"),
+            60
+        );
+        assert_eq!(bank.fee_balance_in("EUR".to_owned()).unwrap(), 1);
+
+        // release and the replayed state keep the currency of the hold
+        let hold_id = bank
+            .hold_in("test_2".to_owned(), 50, "EUR".to_owned())
+            .unwrap();
+        let mut replica = new_bank(Some(1));
+        replica.replay_onto(&bank.transactions().unwrap()).unwrap();
+        assert_eq!(
+            replica.account("test_2".to_owned()).unwrap().held_in("EUR"),
+            50
+        );
+        let release_id = bank.release(hold_id).unwrap();
+        assert_eq!(
+            bank.transaction_by_id(release_id)
+                .unwrap()
+                .currency
+                .as_deref(),
+            Some("EUR")
+        );
+        assert!(bank.account("test_2".to_owned()).unwrap().held.is_empty());
+
+        // an external transfer is reversed in its currency
+        assert_eq!(
+            bank.reverse_transaction(dep_id).unwrap_err(),
+            AccError::NotEnoughMoney
+        );
+        bank.external_deposit_in(
+            "test_1".to_owned(),
+            91,
+            "EUR".to_owned(),
+            "wire-2".to_owned(),
+        )
+        .unwrap();
+        bank.reverse_transaction(dep_id).unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.balance_in("EUR")), (10, 0));
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_bank_currency_totals() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance_in(&mut acc_f, 50, "EUR".to_owned(), None)
+            .unwrap();
+        bank.make_transaction_with_options(&mut acc_f, &mut acc_s, 20, None, in_currency("EUR"))
+            .unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(2))
+            .unwrap();
+        bank.external_withdraw_in("test_2".to_owned(), 5, "EUR".to_owned(), "card".to_owned())
+            .unwrap();
+
+        // every currency is totalled on its own
+        assert_eq!(bank.total_assets().unwrap(), 100);
+        assert_eq!(bank.total_assets_in("EUR".to_owned()).unwrap(), 45);
+        assert_eq!(
+            bank.aggregates().unwrap(),
+            Aggregates {
+                total_added: 100,
+                total_withdrawn: 0,
+                total_transferred: 10,
+                total_fees: 2,
+            }
+        );
+        assert_eq!(
+            bank.aggregates_in("EUR".to_owned()).unwrap(),
+            Aggregates {
+                total_added: 50,
+                total_withdrawn: 5,
+                total_transferred: 20,
+                total_fees: 1,
+            }
+        );
+        assert_eq!(bank.fee_report().unwrap().total, 2);
+        let ledger = bank.fee_report_in("EUR".to_owned()).unwrap();
+        assert_eq!(
+            ledger.by_account,
+            BTreeMap::from([("test_1".to_owned(), 1)])
+        );
+        assert_eq!(
+            bank.aggregates_in("GBP".to_owned()).unwrap(),
+            Aggregates::default()
+        );
+
+        // fees are audited in every currency
+        let report = bank.audit_integrity().unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(
+            report.recorded_fees,
+            BTreeMap::from([("EUR".to_owned(), 1), (DEFAULT_CURRENCY.to_owned(), 2)])
+        );
+        let mut fee_acc = bank.acc_storage.fee_account().unwrap();
+        fee_acc.set_balance_in("EUR", 4);
+        bank.acc_storage.update_account(fee_acc).unwrap();
+        let report = bank.audit_integrity().unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.fee_balances["EUR"], 4);
+    }
+
+    #[test]
+    fn test_bank_currency_restore() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance_in(&mut acc_f, 50, "EUR".to_owned(), None)
+            .unwrap();
+        bank.make_transaction_with_options(&mut acc_f, &mut acc_s, 20, None, in_currency("EUR"))
+            .unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+
+        // running balances are kept per currency
+        let lines = bank.statement("test_2".to_owned()).unwrap();
+        assert_eq!(
+            lines
+                .iter()
+                .map(|x| (x.currency.as_str(), x.balance_after))
+                .collect::<Vec<_>>(),
+            [("USD", 0), ("EUR", 20), ("USD", 10)]
+        );
+
+        // a stored balance of another currency is audited and rebuilt from the log
+        let mut raw = bank.acc_storage.get_account("test_2".to_owned()).unwrap();
+        raw.set_balance_in("EUR", 3);
+        bank.acc_storage.update_account(raw).unwrap();
+        let report = bank.audit_integrity().unwrap();
+        assert_eq!(
+            report.discrepancies,
+            [BalanceDiscrepancy {
+                account_name: "test_2".to_owned(),
+                currency: "EUR".to_owned(),
+                stored: 3,
+                computed: 20,
+            }]
+        );
+        let rebuilt = bank.rebuild_account("test_2".to_owned()).unwrap();
+        assert_eq!((rebuilt.balance(), rebuilt.balance_in("EUR")), (10, 20));
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+
+        // transfer usage isn't in the log, only the balances are compared
+        let balances = |bank: &Bank<MemAccountStorage, MemTransactionStorage>| {
+            sorted_accounts(bank)
+                .into_iter()
+                .map(|acc| (acc.name, acc.balances))
+                .collect::<Vec<_>>()
+        };
+
+        let mut restored = new_bank(Some(1));
+        assert_eq!(
+            restored
+                .restore_accounts_from_bank_transactions(&bank)
+                .unwrap(),
+            []
+        );
+        assert_eq!(balances(&restored), balances(&bank));
+        assert_eq!(restored.fee_balance_in("EUR".to_owned()).unwrap(), 1);
+
+        let mut replica = new_bank(Some(1));
+        replica.replay_onto(&bank.transactions().unwrap()).unwrap();
+        assert_eq!(balances(&replica), balances(&bank));
+        assert_eq!(replica.state_hash().unwrap(), bank.state_hash().unwrap());
+
+        let mut snapshot = Vec::new();
+        bank.write_snapshot(&mut snapshot).unwrap();
+        let from_snapshot = Bank::read_snapshot(
+            &mut snapshot.as_slice(),
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(sorted_accounts(&from_snapshot), sorted_accounts(&bank));
+        let from_json = Bank::import_state(
+            &bank.export_state().unwrap(),
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(sorted_accounts(&from_json), sorted_accounts(&bank));
+        assert_eq!(
+            from_json.transactions().unwrap(),
+            bank.transactions().unwrap()
+        );
+
+        // the hash covers the other currencies
+        let mut raw = replica
+            .acc_storage
+            .get_account("test_1".to_owned())
+            .unwrap();
+        raw.set_balance_in("EUR", 1);
+        replica.acc_storage.update_account(raw).unwrap();
+        assert_ne!(replica.state_hash().unwrap(), bank.state_hash().unwrap());
+    }
+
+    #[test]
+    fn test_bank_currency_reverse_and_close() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.create_account("test_3".to_owned()).unwrap();
+        bank.inc_acc_balance_in(&mut acc_f, 50, "EUR".to_owned(), None)
+            .unwrap();
+        let tr_id = bank
+            .make_transaction_with_options(&mut acc_f, &mut acc_s, 20, None, in_currency("EUR"))
+            .unwrap();
+
+        // the refund goes back in the currency of the transfer
+        bank.reverse_transaction(tr_id).unwrap();
+        let acc_f = bank.account("test_1".to_owned()).unwrap();
+        let acc_s = bank.account("test_2".to_owned()).unwrap();
+        assert_eq!((acc_f.balance(), acc_f.balance_in("EUR")), (100, 49));
+        assert_eq!((acc_s.balance(), acc_s.balance_in("EUR")), (0, 0));
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+        let inc_id = bank
+            .inc_acc_balance_in(&mut acc_f, 7, "EUR".to_owned(), None)
+            .unwrap();
+        bank.reverse_transaction(inc_id).unwrap();
+        assert_eq!(
+            bank.account("test_1".to_owned()).unwrap().balance_in("EUR"),
+            49
+        );
+
+        // closing sweeps every currency
+        assert_eq!(
+            bank.close_to("test_1".to_owned(), "test_3".to_owned())
+                .unwrap(),
+            100
+        );
+        let acc_t = bank.account("test_3".to_owned()).unwrap();
+        assert_eq!((acc_t.balance(), acc_t.balance_in("EUR")), (100, 49));
+        assert_eq!(bank.fee_balance_in("EUR".to_owned()).unwrap(), 1);
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_bank_balance_overflow() {
+        let mut bank = new_bank(None);
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), usize::MAX - 1)
+            .unwrap();
+        let overflow = AccError::Storage("balance overflow".to_owned());
+        assert_eq!(bank.inc_acc_balance(&mut acc_f, 2).err().unwrap(), overflow);
+        assert_eq!(
+            bank.external_deposit("test_1".to_owned(), usize::MAX, "wire".to_owned())
+                .err()
+                .unwrap(),
+            overflow
+        );
+        bank.inc_acc_balance(&mut acc_f, 1).unwrap();
+        assert_eq!(acc_f.balance(), usize::MAX);
+
+        // a transfer that would overflow the receiver moves nothing
+        let mut acc_s = bank
+            .create_account_with_balance("test_2".to_owned(), 1)
+            .unwrap();
+        assert_eq!(
+            bank.make_transaction(&mut acc_s, &mut acc_f, 1, None, None)
+                .err()
+                .unwrap(),
+            overflow
+        );
+        assert_eq!(
+            bank.account("test_1".to_owned()).unwrap().balance(),
+            usize::MAX
+        );
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 1);
+    }
+
+    #[test]
+    fn test_bank_max_deposit() {
+        let mut bank = new_bank(None);
+        let mut acc = bank.create_account("test_1".to_owned()).unwrap();
+        bank.set_max_deposit(Some(100));
+
+        assert_eq!(
+            bank.inc_acc_balance(&mut acc, 101).err().unwrap(),
+            AccError::AmountTooLarge
+        );
+        assert_eq!(
+            bank.external_deposit("test_1".to_owned(), 101, "wire".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AmountTooLarge
+        );
+        assert_eq!(
+            bank.create_account_with_balance("test_2".to_owned(), 101)
+                .err()
+                .unwrap(),
+            AccError::AmountTooLarge
+        );
+        assert!(!bank.account_exists("test_2".to_owned()).unwrap());
+        bank.inc_acc_balance(&mut acc, 100).unwrap();
+        bank.external_deposit("test_1".to_owned(), 100, "wire".to_owned())
+            .unwrap();
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 200);
+
+        // interest isn't capped
+        bank.set_max_deposit(Some(1));
+        bank.apply_interest("test_1".to_owned(), 1000).unwrap();
+        bank.apply_interest_all(1000).unwrap();
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 242);
+
+        bank.set_max_deposit(None);
+        bank.inc_acc_balance(&mut acc, 1000).unwrap();
+    }
+
+    #[test]
+    fn test_bank_transaction_counts() {
+        let mut bank = new_bank(Some(1));
+        assert_eq!(bank.transaction_count().unwrap(), 0);
+        assert_eq!(
+            bank.account_transaction_count("fee_acc".to_owned())
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            bank.account_transaction_count("test_1".to_owned())
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        assert_eq!(bank.transaction_count().unwrap(), 3);
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.decr_acc_balance(&mut acc_f, 1).unwrap();
+
+        // registration, increment, transfer out, decrement
+        assert_eq!(
+            bank.account_transaction_count("test_1".to_owned()).unwrap(),
+            4
+        );
+        assert_eq!(
+            bank.account_transaction_count("test_2".to_owned()).unwrap(),
+            2
+        );
+        assert_eq!(
+            bank.account_transaction_count("fee_acc".to_owned())
+                .unwrap(),
+            1
+        );
+        assert_eq!(bank.transaction_count().unwrap(), 7);
+        assert_eq!(
+            bank.transaction_count().unwrap(),
+            bank.transactions().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_bank_snapshot_view() {
+        let mut bank = new_bank(Some(2));
+        let mut acc_f = bank
+            .create_account_with_balance("test_2".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_1".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_f, 10, None, None)
+            .unwrap();
+        bank.external_withdraw("test_1".to_owned(), 5, "atm".to_owned())
+            .unwrap();
+
+        let snapshot = bank.snapshot().unwrap();
+        let names: Vec<&str> = snapshot
+            .accounts
+            .iter()
+            .map(|acc| acc.name.as_str())
+            .collect();
+        assert_eq!(names, ["fee_acc", "test_1", "test_2"]);
+        assert!(snapshot.transactions.windows(2).all(|w| w[0].id < w[1].id));
+        assert_eq!(snapshot.transactions, sorted_transactions(&bank));
+        assert_eq!(snapshot.fee_balance, 4);
+
+        // money is only created by deposits and destroyed by withdrawals
+        let total: usize = snapshot.accounts.iter().map(|acc| acc.balance()).sum();
+        let delta: i128 = snapshot
+            .transactions
+            .iter()
+            .map(|tr| tr.action.balance_delta())
+            .sum();
+        assert_eq!(total, 95);
+        assert_eq!(total as i128, delta);
+        let clients: usize = snapshot
+            .accounts
+            .iter()
+            .filter(|acc| acc.name != "fee_acc")
+            .map(|acc| acc.balance())
+            .sum();
+        assert_eq!(clients + snapshot.fee_balance, total);
+    }
+
+    #[test]
+    fn test_bank_for_each_account() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 10)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 4, None, None)
+            .unwrap();
+
+        let mut seen = Vec::new();
+        bank.acc_storage
+            .for_each_account(&mut |acc| {
+                seen.push((acc.name.clone(), acc.balance()));
+                Ok(())
+            })
+            .unwrap();
+        seen.sort();
+        assert_eq!(
+            seen,
+            [
+                ("fee_acc".to_owned(), 1),
+                ("test_1".to_owned(), 5),
+                ("test_2".to_owned(), 4)
+            ]
+        );
+        assert_eq!(bank.total_assets().unwrap(), 10);
+
+        // an error from the callback stops the scan
+        let mut calls = 0;
+        let res = bank.acc_storage.for_each_account(&mut |_| {
+            calls += 1;
+            Err(StorageError::StorageError("stop".to_owned()))
+        });
+        assert_eq!(
+            res.err().unwrap(),
+            StorageError::StorageError("stop".to_owned())
+        );
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_bank_self_transfer() {
+        let mut bank = new_bank(Some(1));
+        let mut acc = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut same = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!(
+            bank.make_transaction(&mut acc, &mut same, 10, None, None)
+                .err()
+                .unwrap(),
+            AccError::Storage("transfer to the same account".to_owned())
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 100);
+
+        // a self-transfer in the log moves no value, only the fee is charged,
+        // whether or not the paired credit is present
+        bank.tr_storage
+            .create_transaction(
+                "test_1".to_owned(),
+                TransactionAction::TransferOut {
+                    to: "test_1".to_owned(),
+                    value: 10,
+                    fee: 1,
+                    memo: None,
+                },
+            )
+            .unwrap();
+        let acc = bank
+            .restore_account_from_transactions("test_1".to_owned())
+            .unwrap();
+        assert_eq!(acc.balance(), 99);
+        bank.tr_storage
+            .create_transaction(
+                "test_1".to_owned(),
+                TransactionAction::TransferIn {
+                    from: "test_1".to_owned(),
+                    value: 10,
+                    memo: None,
+                },
+            )
+            .unwrap();
+        let acc = bank
+            .restore_account_from_transactions("test_1".to_owned())
+            .unwrap();
+        assert_eq!(acc.balance(), 99);
+        assert_eq!(
+            bank.statement("test_1".to_owned())
+                .unwrap()
+                .last()
+                .unwrap()
+                .balance_after,
+            99
+        );
+    }
+
+    #[test]
+    fn test_bank_transactions_between() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        // ids: 1 reg, 2 inc, 3 reg, 4 out, 5 in, 6 fee
+        let ids = |trs: Vec<Transaction>| trs.iter().map(|tr| tr.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(bank.transactions_between(2, 4).unwrap()), [2, 3, 4]);
+        assert_eq!(ids(bank.transactions_between(5, 5).unwrap()), [5]);
+        assert_eq!(ids(bank.transactions_between(0, 2).unwrap()), [1, 2]);
+        // beyond the last id
+        assert_eq!(ids(bank.transactions_between(5, 100).unwrap()), [5, 6]);
+        assert!(bank.transactions_between(7, 100).unwrap().is_empty());
+        assert_eq!(
+            bank.transactions_between(0, usize::MAX).unwrap(),
+            sorted_transactions(&bank)
+        );
+        assert_eq!(
+            bank.transactions_between(4, 3).err().unwrap(),
+            StorageError::StorageError("invalid id range".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_bank_make_transaction_missing_receiver() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut ghost = Account::from(AccountTransfer::new("ghost".to_owned(), None));
+        let trs_before = bank.account_transactions("test_1".to_owned()).unwrap();
+
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut ghost, 10, None, None)
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        assert_eq!(acc_f.balance(), 100);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 100);
+        assert_eq!(
+            bank.account_transactions("test_1".to_owned()).unwrap(),
+            trs_before
+        );
+        assert_eq!(bank.fee_balance().unwrap(), 0);
+        assert!(!bank.account_exists("ghost".to_owned()).unwrap());
+    }
+
+    #[test]
+    fn test_account_display() {
+        let mut bank = new_bank(None);
+        bank.create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!(acc.to_string(), "Account: test_1. Balance: 100");
+
+        bank.set_min_balance("test_1".to_owned(), 10).unwrap();
+        bank.set_transfer_limit("test_1".to_owned(), Some(50))
+            .unwrap();
+        bank.freeze_account("test_1".to_owned()).unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!(
+            acc.to_string(),
+            "Account: test_1. Balance: 100. Min balance: 10. Transferred: 0/50. Frozen"
+        );
+        assert_eq!(
+            format!("{acc:#}"),
+            "Account: test_1\n  Balance: 100\n  Min balance: 10\n  Transfer limit: 50\n  \
+             Transferred: 0\n  Held: 0\n  Frozen: true"
+        );
+    }
+
+    #[test]
+    fn test_bank_make_transaction_fee_override() {
+        let mut bank = new_bank(Some(2));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        let fee_of =
+            |bank: &Bank<_, _>, id: TransactionId| match bank.transaction_by_id(id).unwrap().action
+            {
+                TransactionAction::TransferOut { fee, .. } => fee,
+                action => panic!("unexpected action {action:?}"),
+            };
+
+        // above the default
+        let id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(5))
+            .unwrap();
+        assert_eq!(fee_of(&bank, id), 5);
+        assert_eq!(acc_f.balance(), 85);
+        assert_eq!(bank.fee_balance().unwrap(), 5);
+
+        // below the default
+        let id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(1))
+            .unwrap();
+        assert_eq!(fee_of(&bank, id), 1);
+        assert_eq!(bank.fee_balance().unwrap(), 6);
+
+        // zero on a bank with a non-zero default
+        let id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(0))
+            .unwrap();
+        assert_eq!(fee_of(&bank, id), 0);
+        assert_eq!(bank.fee_balance().unwrap(), 6);
+        assert_eq!(
+            bank.account_transaction_count("fee_acc".to_owned())
+                .unwrap(),
+            2
+        );
+
+        // default policy without an override
+        let id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        assert_eq!(fee_of(&bank, id), 2);
+        assert_eq!(bank.fee_balance().unwrap(), 8);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 52);
+        assert_eq!(bank.total_assets().unwrap(), 100);
+
+        // a fee that overflows the debit is refused instead of wrapping
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(usize::MAX))
+                .err()
+                .unwrap(),
+            AccError::NotEnoughMoney
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 52);
+        assert_eq!(bank.fee_balance().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_bank_close_to() {
+        let mut bank = new_bank(Some(1));
+        bank.create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        bank.create_account_with_balance("test_2".to_owned(), 5)
+            .unwrap();
+
+        assert_eq!(
+            bank.close_to("test_1".to_owned(), "test_2".to_owned())
+                .unwrap(),
+            100
+        );
+        assert!(!bank.account_exists("test_1".to_owned()).unwrap());
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 105);
+        assert_eq!(bank.fee_balance().unwrap(), 0);
+        // the history of the closed account stays in the log
+        let trs = bank.account_transactions("test_1".to_owned()).unwrap();
+        assert_eq!(trs[trs.len() - 1].action, TransactionAction::Closed);
+        assert_eq!(
+            trs[trs.len() - 2].action,
+            TransactionAction::TransferOut {
+                to: "test_2".to_owned(),
+                value: 100,
+                fee: 0,
+                memo: None,
+            }
+        );
+
+        // zero balance: closed without a transfer
+        bank.create_account("test_3".to_owned()).unwrap();
+        let count = bank.transaction_count().unwrap();
+        assert_eq!(
+            bank.close_to("test_3".to_owned(), "test_2".to_owned())
+                .unwrap(),
+            0
+        );
+        assert!(!bank.account_exists("test_3".to_owned()).unwrap());
+        assert_eq!(bank.transaction_count().unwrap(), count + 1);
+
+        // errors
+        bank.create_account_with_balance("test_4".to_owned(), 1)
+            .unwrap();
+        assert_eq!(
+            bank.close_to("test_4".to_owned(), "missing".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        for (name, destination) in [
+            ("test_4", "test_4"),
+            ("test_4", "fee_acc"),
+            ("fee_acc", "test_4"),
+        ] {
+            assert_eq!(
+                bank.close_to(name.to_owned(), destination.to_owned())
+                    .err()
+                    .unwrap(),
+                AccError::InvalidAccountName
+            );
+        }
+        assert_eq!(bank.account("test_4".to_owned()).unwrap().balance(), 1);
+    }
+
+    #[test]
+    fn test_bank_close_to_restricted_account() {
+        let mut bank = new_bank(Some(1));
+        bank.create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+
+        // an open hold blocks the close and leaves the account untouched
+        let hold_id = bank.hold("test_1".to_owned(), 30).unwrap();
+        let count = bank.transaction_count().unwrap();
+        assert_eq!(
+            bank.close_to("test_1".to_owned(), "test_2".to_owned())
+                .err()
+                .unwrap(),
+            AccError::HoldsOpen
+        );
+        assert_eq!(bank.transaction_count().unwrap(), count);
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.held()), (100, 30));
+
+        // once the hold is closed the whole balance is swept, ignoring the minimum
+        // balance and the transfer limit
+        bank.release(hold_id).unwrap();
+        bank.set_min_balance("test_1".to_owned(), 50).unwrap();
+        bank.set_transfer_limit("test_1".to_owned(), Some(10))
+            .unwrap();
+        assert_eq!(
+            bank.close_to("test_1".to_owned(), "test_2".to_owned())
+                .unwrap(),
+            100
+        );
+        assert!(!bank.account_exists("test_1".to_owned()).unwrap());
+        let acc = bank.account("test_2".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.transferred), (100, 0));
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_bank_close_to_name_reuse() {
+        let mut bank = new_bank(None);
+        bank.create_account_with_balance("test_1".to_owned(), 10)
+            .unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+        bank.close_to("test_1".to_owned(), "test_2".to_owned())
+            .unwrap();
+
+        // the closed history stays under the name, so it can't be taken over
+        let history = bank.account_transaction_count("test_1".to_owned());
+        assert_eq!(
+            bank.create_account("test_1".to_owned()).unwrap_err(),
+            AccError::InvalidAccountName
+        );
+        assert_eq!(
+            bank.rename_account("test_2".to_owned(), "test_1".to_owned())
+                .unwrap_err(),
+            AccError::InvalidAccountName
+        );
+        assert!(!bank.account_exists("test_1".to_owned()).unwrap());
+        assert_eq!(bank.account_transaction_count("test_1".to_owned()), history);
+
+        // a merged account moved its history away, the name is free again
+        bank.create_account("test_3".to_owned()).unwrap();
+        bank.merge_account("test_3".to_owned(), "test_2".to_owned())
+            .unwrap();
+        bank.create_account("test_3".to_owned()).unwrap();
+        assert_eq!(bank.statement("test_3".to_owned()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_bank_transactions_sorted_by_id() {
+        // the memory storage groups transactions by account, so its own order is arbitrary
+        let mut tr_storage = MemTransactionStorage::new();
+        for id in 1..=50 {
+            tr_storage
+                .restore_transaction(TransactionTransfer {
+                    id: 51 - id,
+                    action: TransactionAction::Increment(id),
+                    account_name: format!("test_{}", id % 7),
+                    created_at: 0,
+                    memo: None,
+                    uuid: None,
+                    currency: None,
+                })
+                .unwrap();
+        }
+        let bank = Bank::new(MemAccountStorage::new().unwrap(), tr_storage, None);
+
+        let ids: Vec<usize> = bank
+            .transactions()
+            .unwrap()
+            .iter()
+            .map(|tr| tr.id)
+            .collect();
+        assert_eq!(ids, (1..=50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_bank_find_accounts_by_prefix() {
+        let mut bank = new_bank(None);
+        for name in ["alice", "bob", "alex", "al"] {
+            bank.create_account(name.to_owned()).unwrap();
+        }
+        let names = |accs: Vec<Account>| accs.into_iter().map(|acc| acc.name).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(bank.find_accounts_by_prefix("al".to_owned(), 10).unwrap()),
+            ["al", "alex", "alice"]
+        );
+        assert_eq!(
+            names(bank.find_accounts_by_prefix("ale".to_owned(), 10).unwrap()),
+            ["alex"]
+        );
+        assert_eq!(
+            names(bank.find_accounts_by_prefix("al".to_owned(), 2).unwrap()),
+            ["al", "alex"]
+        );
+        assert!(bank
+            .find_accounts_by_prefix("z".to_owned(), 10)
+            .unwrap()
+            .is_empty());
+        assert!(bank
+            .find_accounts_by_prefix("al".to_owned(), 0)
+            .unwrap()
+            .is_empty());
+        // the fee account is excluded even for a matching or empty prefix
+        assert!(bank
+            .find_accounts_by_prefix("fee".to_owned(), 10)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            names(bank.find_accounts_by_prefix(String::new(), 10).unwrap()),
+            ["al", "alex", "alice", "bob"]
+        );
+    }
+
+    #[test]
+    fn test_bank_transaction_memo() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+
+        let tr_id = bank
+            .make_transaction_with_memo(
+                &mut acc_f,
+                &mut acc_s,
+                10,
+                None,
+                None,
+                Some("rent".to_owned()),
+            )
+            .unwrap();
+        let tr = bank.transaction_by_id(tr_id).unwrap();
+        assert_eq!(tr.action.memo(), Some("rent"));
+        assert!(bank
+            .tr_storage
+            .transaction_by_id(tr_id)
+            .unwrap()
+            .to_string()
+            .ends_with(", Memo: rent"));
+        let incoming = bank.account_transactions("test_2".to_owned()).unwrap();
+        assert_eq!(incoming.last().unwrap().action.memo(), Some("rent"));
+
+        // memo survives a json export
+        let json = bank.export_state().unwrap();
+        let state = BankState::from_json(&json).unwrap();
+        let exported = state
+            .transactions
+            .iter()
+            .find(|tr| tr.id == seq(tr_id))
+            .unwrap();
+        assert_eq!(exported.action.memo(), Some("rent"));
+
+        // a memo at the limit is accepted, a longer one rejects the whole transfer
+        bank.make_transaction_with_memo(
+            &mut acc_f,
+            &mut acc_s,
+            1,
+            None,
+            None,
+            Some("m".repeat(MAX_MEMO_LEN)),
+        )
+        .unwrap();
+        let trs_count = bank.transactions().unwrap().len();
+        assert_eq!(
+            bank.make_transaction_with_memo(
+                &mut acc_f,
+                &mut acc_s,
+                1,
+                None,
+                None,
+                Some("m".repeat(MAX_MEMO_LEN + 1)),
+            )
+            .err()
+            .unwrap(),
+            AccError::Storage("memo too long".to_owned())
+        );
+        assert_eq!(bank.transactions().unwrap().len(), trs_count);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 87);
+
+        // plain transfers carry no memo
+        let tr_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 1, None, None)
+            .unwrap();
+        assert_eq!(bank.transaction_by_id(tr_id).unwrap().action.memo(), None);
+    }
+
+    #[test]
+    fn test_bank_balance_change_memo() {
+        let mut bank = new_bank(Some(1));
+        let mut acc = bank.create_account("test_1".to_owned()).unwrap();
+
+        let inc_id = bank
+            .inc_acc_balance_with_memo(&mut acc, 50, Some("salary".to_owned()))
+            .unwrap();
+        let decr_id = bank
+            .decr_acc_balance_with_memo(&mut acc, 20, Some("atm".to_owned()))
+            .unwrap();
+        assert_eq!(
+            bank.transaction_by_id(inc_id).unwrap().memo.as_deref(),
+            Some("salary")
+        );
+        let decr = bank.tr_storage.transaction_by_id(decr_id).unwrap();
+        assert_eq!(decr.memo(), Some("atm"));
+        assert!(decr.to_string().ends_with(", Memo: atm"));
+
+        // memos survive an export and import
+        let json = bank.export_state().unwrap();
+        let restored = Bank::import_state(
+            &json,
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            restored.transaction_by_id(inc_id).unwrap().memo.as_deref(),
+            Some("salary")
+        );
+        assert_eq!(
+            restored.transaction_by_id(decr_id).unwrap().memo.as_deref(),
+            Some("atm")
+        );
+
+        // a memo at the limit is accepted, a longer one rejects the change
+        bank.inc_acc_balance_with_memo(&mut acc, 1, Some("m".repeat(MAX_MEMO_LEN)))
+            .unwrap();
+        let trs_count = bank.transactions().unwrap().len();
+        assert_eq!(
+            bank.inc_acc_balance_with_memo(&mut acc, 1, Some("m".repeat(MAX_MEMO_LEN + 1)))
+                .err()
+                .unwrap(),
+            AccError::Storage("memo too long".to_owned())
+        );
+        assert_eq!(
+            bank.decr_acc_balance_with_memo(&mut acc, 1, Some("m".repeat(MAX_MEMO_LEN + 1)))
+                .err()
+                .unwrap(),
+            AccError::Storage("memo too long".to_owned())
+        );
+        assert_eq!(bank.transactions().unwrap().len(), trs_count);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 31);
+
+        // plain changes carry no memo
+        let tr_id = bank.inc_acc_balance(&mut acc, 1).unwrap();
+        assert_eq!(bank.transaction_by_id(tr_id).unwrap().memo, None);
+    }
+
+    #[test]
+    fn test_bank_rebuild_account() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_f, 5, None, None)
+            .unwrap();
+        bank.set_min_balance("test_2".to_owned(), 2).unwrap();
+
+        // corrupt the stored balances
+        for name in ["test_1", "test_2"] {
+            let mut raw = bank.acc_storage.get_account(name.to_owned()).unwrap();
+            raw.set_balance(1000);
+            bank.acc_storage.update_account(raw).unwrap();
+        }
+
+        assert_eq!(
+            bank.rebuild_account("test_1".to_owned()).unwrap().balance(),
+            74
+        );
+        let acc = bank.rebuild_account("test_2".to_owned()).unwrap();
+        assert_eq!(acc.balance(), 24);
+        // settings are kept
+        assert_eq!(acc.min_balance, 2);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 74);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 24);
+
+        assert_eq!(
+            bank.rebuild_account("missing".to_owned()).err().unwrap(),
+            AccError::AccountNotExists
+        );
+        assert!(!bank.account_exists("missing".to_owned()).unwrap());
+    }
+
+    #[test]
+    fn test_bank_transfer_all() {
+        let mut bank = new_bank(Some(1));
+        bank.create_account_with_balance("test_1".to_owned(), 10)
+            .unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+
+        assert_eq!(
+            bank.transfer_all("test_1".to_owned(), "test_2".to_owned())
+                .unwrap(),
+            9
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 0);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 9);
+        assert_eq!(bank.fee_balance().unwrap(), 1);
+
+        // balance not above the fee
+        bank.create_account_with_balance("test_3".to_owned(), 1)
+            .unwrap();
+        for name in ["test_1", "test_3"] {
+            assert_eq!(
+                bank.transfer_all(name.to_owned(), "test_2".to_owned())
+                    .err()
+                    .unwrap(),
+                AccError::NotEnoughMoney
+            );
+        }
+        assert_eq!(
+            bank.transfer_all("missing".to_owned(), "test_2".to_owned())
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+
+        // percent fees and the minimum balance are accounted for
+        let mut bank = Bank::new(
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::new(),
+            Some(Box::new(PercentFee { bps: 1000 })),
+        );
+        bank.create_account_with_balance("test_1".to_owned(), 1005)
+            .unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+        bank.set_min_balance("test_1".to_owned(), 5).unwrap();
+        assert_eq!(
+            bank.transfer_all("test_1".to_owned(), "test_2".to_owned())
+                .unwrap(),
+            909
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 6);
+        assert_eq!(bank.fee_balance().unwrap(), 90);
+    }
+
+    #[test]
+    fn test_bank_audit_integrity() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 30, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_f, 5, None, Some(3))
+            .unwrap();
+        bank.create_account("test_3".to_owned()).unwrap();
+        bank.close_to("test_3".to_owned(), "test_1".to_owned())
+            .unwrap();
+
+        let report = bank.audit_integrity().unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(
+            report.recorded_fees,
+            BTreeMap::from([(DEFAULT_CURRENCY.to_string(), 4)])
+        );
+        assert_eq!(
+            report.fee_balances,
+            BTreeMap::from([(DEFAULT_CURRENCY.to_string(), 4)])
+        );
+
+        let mut raw = bank.acc_storage.get_account("test_2".to_owned()).unwrap();
+        raw.set_balance(raw.balance() - 7);
+        bank.acc_storage.update_account(raw).unwrap();
+
+        let report = bank.audit_integrity().unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(
+            report.discrepancies,
+            [BalanceDiscrepancy {
+                account_name: "test_2".to_owned(),
+                currency: DEFAULT_CURRENCY.to_string(),
+                stored: 15,
+                computed: 22,
+            }]
+        );
+        assert_eq!(report.discrepancies[0].delta(), -7);
+        // the audit doesn't repair anything
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 15);
+
+        let mut fee_acc = bank.acc_storage.fee_account().unwrap();
+        fee_acc.set_balance(fee_acc.balance() + 2);
+        bank.acc_storage.update_account(fee_acc).unwrap();
+        let report = bank.audit_integrity().unwrap();
+        assert_eq!(
+            report.fee_balances,
+            BTreeMap::from([(DEFAULT_CURRENCY.to_string(), 6)])
+        );
+        assert_eq!(
+            report.recorded_fees,
+            BTreeMap::from([(DEFAULT_CURRENCY.to_string(), 4)])
+        );
+        assert_eq!(
+            report
+                .discrepancies
+                .iter()
+                .map(|x| (x.account_name.as_str(), x.delta()))
+                .collect::<Vec<_>>(),
+            [("fee_acc", 2), ("test_2", -7)]
+        );
+    }
+
+    #[test]
+    fn test_bank_accounts_with_balance_over() {
+        let mut bank = new_bank(Some(100));
+        for (name, balance) in [
+            ("test_1", 50),
+            ("test_2", 100),
+            ("test_3", 150),
+            ("test_4", 250),
+        ] {
+            bank.create_account_with_balance(name.to_owned(), balance)
+                .unwrap();
+        }
+        let summary = |accs: Vec<Account>| {
+            accs.into_iter()
+                .map(|acc| (acc.name.clone(), acc.balance()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            summary(bank.accounts_with_balance_over(100).unwrap()),
+            [("test_4".to_owned(), 250), ("test_3".to_owned(), 150)]
+        );
+        assert_eq!(bank.accounts_with_balance_over(49).unwrap().len(), 4);
+        assert!(bank.accounts_with_balance_over(250).unwrap().is_empty());
+
+        // the fee account is excluded
+        bank.transfer_all("test_4".to_owned(), "test_1".to_owned())
+            .unwrap();
+        assert_eq!(bank.fee_balance().unwrap(), 100);
+        assert_eq!(
+            summary(bank.accounts_with_balance_over(99).unwrap()),
+            [
+                ("test_1".to_owned(), 200),
+                ("test_3".to_owned(), 150),
+                ("test_2".to_owned(), 100)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bank_get_or_create_account() {
+        let mut bank = new_bank(None);
+        let (mut acc, created) = bank.get_or_create_account("test".to_owned()).unwrap();
+        assert!(created);
+        bank.inc_acc_balance(&mut acc, 10).unwrap();
+
+        let (existing, created) = bank.get_or_create_account("test".to_owned()).unwrap();
+        assert!(!created);
+        assert_eq!(existing, acc);
+        assert_eq!(existing.balance(), 10);
+        // no second registration is logged
+        assert_eq!(
+            bank.account_transaction_count("test".to_owned()).unwrap(),
+            2
+        );
+
+        assert_eq!(
+            bank.get_or_create_account(FEE_ACCOUNT_NAME.to_owned())
+                .err()
+                .unwrap(),
+            AccError::InvalidAccountName
+        );
+    }
+
+    #[test]
+    fn test_bank_restore_accounts_partial_failures() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        // an account without a log and one whose log goes below zero
+        bank.acc_storage
+            .create_account(AccountTransfer::new("no_log".to_owned(), Some(5)))
+            .unwrap();
+        bank.acc_storage
+            .create_account(AccountTransfer::new("negative".to_owned(), None))
+            .unwrap();
+        bank.tr_storage
+            .create_transaction("negative".to_owned(), TransactionAction::Decrement(3))
+            .unwrap();
+
+        let mut restored = new_bank(Some(1));
+        let mut failures = restored
+            .restore_accounts_from_bank_transactions(&bank)
+            .unwrap();
+        failures.sort_by(|a, b| a.account_name.cmp(&b.account_name));
+        assert_eq!(
+            failures,
+            [
+                RestoreFailure {
+                    account_name: "negative".to_owned(),
+                    error: AccError::Storage("negative balance in log".to_owned()),
+                },
+                RestoreFailure {
+                    account_name: "no_log".to_owned(),
+                    error: AccError::AccountNotExists,
+                },
+            ]
+        );
+        assert_eq!(
+            sorted_accounts(&restored)
+                .iter()
+                .map(|acc| (acc.name.as_str(), acc.balance()))
+                .collect::<Vec<_>>(),
+            [("fee_acc", 1), ("test_1", 89), ("test_2", 10)]
+        );
+
+        // a bank without charged fees has no fee account log
+        let mut bank = new_bank(None);
+        bank.create_account_with_balance("test".to_owned(), 7)
+            .unwrap();
+        let mut restored = new_bank(None);
+        assert!(restored
+            .restore_accounts_from_bank_transactions(&bank)
+            .unwrap()
+            .is_empty());
+        assert_eq!(restored.account("test".to_owned()).unwrap().balance(), 7);
+    }
+
+    #[test]
+    fn test_bank_set_fee() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        assert_eq!(bank.fee(10), 1);
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+
+        bank.set_fee(5);
+        assert_eq!(bank.fee(10), 5);
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        assert_eq!(bank.fee_balance().unwrap(), 6);
+        assert_eq!(acc_f.balance(), 74);
+
+        bank.set_fee_policy(Box::new(PercentFee { bps: 1000 }));
+        assert_eq!(bank.fee(50), 5);
+        bank.make_transaction(&mut acc_f, &mut acc_s, 20, None, None)
+            .unwrap();
+        assert_eq!(bank.fee_balance().unwrap(), 8);
+
+        // earlier transfers keep the fee they were charged
+        let fees = bank
+            .transactions()
+            .unwrap()
+            .into_iter()
+            .filter_map(|tr| match tr.action {
+                TransactionAction::TransferOut { fee, .. } => Some(fee),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(fees, [1, 5, 2]);
+    }
+
+    #[test]
+    fn test_bank_uuid_transaction_ids() {
+        let mut bank = Bank::new(
+            MemAccountStorage::new().unwrap(),
+            MemTransactionStorage::with_id_mode(TransactionIdMode::Uuid),
+            Some(Box::new(FlatFee(1))),
+        );
+        let is_uuid = |id: &TransactionId| matches!(id, TransactionId::Uuid(_));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        let inc_id = bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        assert!(is_uuid(&inc_id));
+
+        // the uuid is returned again for a repeated key
+        let tr_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, Some("key".to_owned()), None)
+            .unwrap();
+        assert!(is_uuid(&tr_id));
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 10, Some("key".to_owned()), None)
+                .unwrap(),
+            tr_id
+        );
+        let tr = bank.transaction_by_id(tr_id).unwrap();
+        assert_eq!(tr.account_name, "test_1");
+
+        // ids taken by the bank resolve the uuid, markers keep the sequence number
+        let rev_id = bank.reverse_transaction(tr_id).unwrap();
+        assert!(is_uuid(&rev_id));
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 99);
+        assert_eq!(
+            bank.reverse_transaction(tr_id).unwrap_err(),
+            AccError::Storage("transaction already reversed".to_owned())
+        );
+        let hold_id = bank.hold("test_1".to_owned(), 40).unwrap();
+        let settle_id = bank.settle(hold_id, "test_2".to_owned()).unwrap();
+        assert!(is_uuid(&settle_id));
+        assert_eq!(
+            bank.release(hold_id).unwrap_err(),
+            AccError::Storage("hold already closed".to_owned())
+        );
+        let hold_seq = bank.transaction_by_id(hold_id).unwrap().id;
+        let release = bank.account_transactions("test_1".to_owned()).unwrap();
+        assert_eq!(
+            release.last().unwrap().action,
+            TransactionAction::Release {
+                hold_id: hold_seq,
+                amount: 40
+            }
+        );
+
+        let ids = bank
+            .transaction_ids_for_account("test_1".to_owned())
+            .unwrap();
+        assert!(ids.iter().all(is_uuid));
+        assert_eq!(ids[1], inc_id);
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_bank_transaction_ids_for_account() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_f, 5, None, None)
+            .unwrap();
+
+        for name in ["test_1", "test_2"] {
+            let ids = bank.transaction_ids_for_account(name.to_owned()).unwrap();
+            let full = bank
+                .account_transactions(name.to_owned())
+                .unwrap()
+                .into_iter()
+                .map(|tr| TransactionId::Seq(tr.id))
+                .collect::<Vec<_>>();
+            assert_eq!(ids, full);
+            assert!(ids.windows(2).all(|w| seq(w[0]) < seq(w[1])));
+        }
+        // the incoming transfer is listed for the receiver
+        assert_eq!(
+            bank.transaction_ids_for_account("test_2".to_owned())
+                .unwrap()
+                .len(),
+            3
+        );
+
+        assert_eq!(
+            bank.transaction_ids_for_account("missing".to_owned())
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+
+        // the fee account is listed from its first fee
+        let mut bank = new_bank(None);
+        let fee_name = bank.acc_storage.fee_account().unwrap().name;
+        assert!(bank
+            .transaction_ids_for_account(fee_name.clone())
+            .unwrap()
+            .is_empty());
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        let tr_id = bank
+            .make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(2))
+            .unwrap();
+        assert_eq!(
+            bank.transaction_ids_for_account(fee_name).unwrap(),
+            vec![TransactionId::Seq(seq(tr_id) + 2)]
+        );
+    }
+
+    #[test]
+    fn test_bank_withdraw_all() {
+        let mut bank = new_bank(None);
+        let mut acc = bank.create_account("test".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 100).unwrap();
+
+        let tr_id = bank.withdraw_all("test".to_owned()).unwrap();
+        assert_eq!(bank.account("test".to_owned()).unwrap().balance(), 0);
+        let decrements = bank
+            .account_transactions_filtered("test".to_owned(), Some(ActionKind::Decrement))
+            .unwrap();
+        assert_eq!(decrements.len(), 1);
+        assert_eq!(decrements[0].id, seq(tr_id));
+        assert_eq!(decrements[0].action, TransactionAction::Decrement(100));
+
+        assert_eq!(
+            bank.withdraw_all("test".to_owned()).err().unwrap(),
+            AccError::EmptyTransaction
+        );
+
+        // the minimum balance stays on the account
+        let mut acc = bank.account("test".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 30).unwrap();
+        bank.set_min_balance("test".to_owned(), 10).unwrap();
+        bank.withdraw_all("test".to_owned()).unwrap();
+        assert_eq!(bank.account("test".to_owned()).unwrap().balance(), 10);
+        assert_eq!(
+            bank.withdraw_all("test".to_owned()).err().unwrap(),
+            AccError::EmptyTransaction
+        );
+
+        let mut acc = bank.account("test".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 5).unwrap();
+        bank.freeze_account("test".to_owned()).unwrap();
+        assert_eq!(
+            bank.withdraw_all("test".to_owned()).err().unwrap(),
+            AccError::AccountFrozen
+        );
+        assert_eq!(
+            bank.withdraw_all("missing".to_owned()).err().unwrap(),
+            AccError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_bank_transactions_since() {
+        let mut bank = new_bank(None);
+        let mut acc = bank.create_account("test".to_owned()).unwrap();
+        for value in 1..=4 {
+            bank.inc_acc_balance(&mut acc, value).unwrap();
+        }
+
+        let ids = |trs: Vec<Transaction>| trs.into_iter().map(|tr| tr.id).collect::<Vec<_>>();
+        assert_eq!(ids(bank.transactions_since(3).unwrap()), [4, 5]);
+        assert_eq!(ids(bank.transactions_since(0).unwrap()), [1, 2, 3, 4, 5]);
+        assert!(bank.transactions_since(5).unwrap().is_empty());
+        assert!(bank.transactions_since(usize::MAX).unwrap().is_empty());
+
+        // the cursor picks up new activity only
+        bank.inc_acc_balance(&mut acc, 10).unwrap();
+        let new = bank.transactions_since(5).unwrap();
+        assert_eq!(ids(new), [6]);
+    }
+
+    #[test]
+    fn test_bank_custom_fee_account_name() {
+        let acc_storage = MemAccountStorage::with_fee_account_name("bank_fees".to_owned()).unwrap();
+        assert_eq!(acc_storage.fee_account_name(), "bank_fees");
+        let mut bank = Bank::new(
+            acc_storage,
+            MemTransactionStorage::new(),
+            Some(Box::new(FlatFee(2))),
+        );
+
+        // the default name is an ordinary account now
+        let mut acc_f = bank
+            .create_account_with_balance(FEE_ACCOUNT_NAME.to_owned(), 50)
+            .unwrap();
+        let mut acc_s = bank.create_account("test".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        assert_eq!(bank.fee_balance().unwrap(), 2);
+        assert_eq!(bank.account("bank_fees".to_owned()).unwrap().balance(), 2);
+        assert_eq!(
+            bank.account(FEE_ACCOUNT_NAME.to_owned()).unwrap().balance(),
+            38
+        );
+
+        // the configured name is reserved
+        assert_eq!(
+            bank.create_account("bank_fees".to_owned()).err().unwrap(),
+            AccError::InvalidAccountName
+        );
+        assert_eq!(
+            bank.rename_account("test".to_owned(), "bank_fees".to_owned())
+                .err()
+                .unwrap(),
+            AccError::InvalidAccountName
+        );
+        assert_eq!(
+            bank.find_accounts_by_prefix(String::new(), 10)
+                .unwrap()
+                .into_iter()
+                .map(|acc| acc.name)
+                .collect::<Vec<_>>(),
+            [FEE_ACCOUNT_NAME, "test"]
+        );
+    }
+
+    #[test]
+    fn test_bank_fee_report() {
+        let mut bank = new_bank(Some(2));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank
+            .create_account_with_balance("test_2".to_owned(), 100)
+            .unwrap();
+        let mut acc_t = bank.create_account("test_3".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_t, 10, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(5))
+            .unwrap();
+        bank.make_transaction(&mut acc_s, &mut acc_t, 10, None, None)
+            .unwrap();
+        bank.make_transaction(&mut acc_t, &mut acc_f, 10, None, Some(0))
+            .unwrap();
+
+        let report = bank.fee_report().unwrap();
+        assert_eq!(report.total, 9);
+        assert_eq!(report.total, bank.fee_balance().unwrap());
+        assert_eq!(
+            report.by_account,
+            BTreeMap::from([("test_1".to_owned(), 7), ("test_2".to_owned(), 2)])
+        );
+        assert_eq!(
+            new_bank(Some(1)).fee_report().unwrap(),
+            FeeLedger::default()
+        );
+    }
+
+    #[test]
+    fn test_bank_fee_account_overflow() {
+        let mut bank = new_bank(Some(2));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        let mut fee_acc = bank.acc_storage.fee_account().unwrap();
+        fee_acc.set_balance(usize::MAX - 1);
+        bank.acc_storage.update_account(fee_acc).unwrap();
+        let trs_count = bank.transaction_count().unwrap();
+
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+                .err()
+                .unwrap(),
+            AccError::Storage("balance overflow".to_owned())
+        );
+        // nothing moved
+        assert_eq!(bank.transaction_count().unwrap(), trs_count);
+        assert_eq!(acc_f.balance(), 100);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 100);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 0);
+        assert_eq!(bank.fee_balance().unwrap(), usize::MAX - 1);
+
+        // a fee that still fits is accepted
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(1))
+            .unwrap();
+        assert_eq!(bank.fee_balance().unwrap(), usize::MAX);
+    }
+
+    #[test]
+    fn test_bank_deposit_and_transfer() {
+        let mut bank = new_bank(Some(1));
+        bank.create_account_with_balance("test_1".to_owned(), 10)
+            .unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+
+        let (deposit_id, tr_id) = bank
+            .deposit_and_transfer("test_1".to_owned(), 20, "test_2".to_owned(), 25)
+            .unwrap();
+        assert_eq!(
+            bank.transaction_by_id(deposit_id).unwrap().action,
+            TransactionAction::Increment(20)
+        );
+        assert_eq!(
+            bank.transaction_by_id(tr_id).unwrap().action.kind(),
+            ActionKind::TransferOut
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 4);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 25);
+        assert_eq!(bank.fee_balance().unwrap(), 1);
+
+        // forward amount + fee exceeds the new balance: nothing is deposited
+        let trs_count = bank.transaction_count().unwrap();
+        assert_eq!(
+            bank.deposit_and_transfer("test_1".to_owned(), 20, "test_2".to_owned(), 24)
+                .err()
+                .unwrap(),
+            AccError::NotEnoughMoney
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 4);
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 25);
+        assert_eq!(bank.fee_balance().unwrap(), 1);
+        assert_eq!(bank.transaction_count().unwrap(), trs_count);
+
+        // a minimum balance above the current balance would also block a compensating
+        // decrement, the transfer is refused before the deposit
+        bank.set_min_balance("test_1".to_owned(), 10).unwrap();
+        assert_eq!(
+            bank.deposit_and_transfer("test_1".to_owned(), 20, "test_2".to_owned(), 20)
+                .err()
+                .unwrap(),
+            AccError::BelowMinimumBalance
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 4);
+        assert_eq!(bank.transaction_count().unwrap(), trs_count);
+        bank.freeze_account("test_2".to_owned()).unwrap();
+        assert_eq!(
+            bank.deposit_and_transfer("test_1".to_owned(), 20, "test_2".to_owned(), 5)
+                .err()
+                .unwrap(),
+            AccError::AccountFrozen
+        );
+        assert_eq!(bank.transaction_count().unwrap(), trs_count);
+
+        // a missing destination is detected before the deposit
+        let trs_count = bank.transaction_count().unwrap();
+        assert_eq!(
+            bank.deposit_and_transfer("test_1".to_owned(), 20, "missing".to_owned(), 5)
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        assert_eq!(bank.transaction_count().unwrap(), trs_count);
+    }
+
+    #[test]
+    fn test_bank_deposit_and_transfer_checked_up_front() {
+        let mut bank = new_bank(Some(1));
+        bank.create_account("test_1".to_owned()).unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+        let trs_count = bank.transaction_count().unwrap();
+
+        // a fee the fee account can't take fails before the deposit is stored
+        let mut fee_acc = bank.acc_storage.fee_account().unwrap();
+        fee_acc.set_balance(usize::MAX);
+        bank.acc_storage.update_account(fee_acc.clone()).unwrap();
+        assert_eq!(
+            bank.deposit_and_transfer("test_1".to_owned(), 20, "test_2".to_owned(), 5)
+                .err()
+                .unwrap(),
+            AccError::Storage("balance overflow".to_owned())
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 0);
+        assert_eq!(bank.transaction_count().unwrap(), trs_count);
+
+        // an empty deposit
+        fee_acc.set_balance(0);
+        bank.acc_storage.update_account(fee_acc).unwrap();
+        assert_eq!(
+            bank.deposit_and_transfer("test_1".to_owned(), 0, "test_2".to_owned(), 5)
+                .err()
+                .unwrap(),
+            AccError::EmptyTransaction
+        );
+        assert_eq!(bank.transaction_count().unwrap(), trs_count);
+
+        // a transfer over the limit, no deposit and no reversal are logged
+        bank.set_transfer_limit("test_1".to_owned(), Some(4))
+            .unwrap();
+        assert_eq!(
+            bank.deposit_and_transfer("test_1".to_owned(), 20, "test_2".to_owned(), 5)
+                .err()
+                .unwrap(),
+            AccError::TransferLimitExceeded
+        );
+        assert_eq!(bank.transaction_count().unwrap(), trs_count);
+        assert!(bank
+            .account_transactions_filtered("test_1".to_owned(), Some(ActionKind::Reversal))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_bank_accounts_count() {
+        let mut bank = new_bank(None);
+        assert_eq!(bank.accounts_count(false).unwrap(), 0);
+        assert_eq!(bank.accounts_count(true).unwrap(), 1);
+
+        for name in ["test_1", "test_2", "test_3"] {
+            bank.create_account(name.to_owned()).unwrap();
+        }
+        assert_eq!(bank.accounts_count(false).unwrap(), 3);
+        assert_eq!(bank.accounts_count(true).unwrap(), 4);
+        assert_eq!(
+            bank.accounts_count(true).unwrap(),
+            bank.accounts().unwrap().len()
+        );
+
+        bank.close_to("test_3".to_owned(), "test_1".to_owned())
+            .unwrap();
+        assert_eq!(bank.accounts_count(false).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_bank_replay_onto() {
+        let mut source = new_bank(Some(1));
+        let mut acc_f = source
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = source.create_account("test_2".to_owned()).unwrap();
+        source
+            .make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        let first = source.transactions().unwrap();
+
+        source
+            .make_transaction(&mut acc_s, &mut acc_f, 4, None, None)
+            .unwrap();
+        source
+            .external_withdraw("test_1".to_owned(), 20, "card".to_owned())
+            .unwrap();
+        let all = source.transactions().unwrap();
+
+        let mut target = new_bank(Some(1));
+        target.replay_onto(&first).unwrap();
+        assert_eq!(target.account("test_2".to_owned()).unwrap().balance(), 10);
+        // overlapping and unordered slices apply only the new transactions
+        let mut shuffled = all.clone();
+        shuffled.reverse();
+        target.replay_onto(&shuffled).unwrap();
+        target.replay_onto(&all).unwrap();
+
+        let balances = |bank: &Bank<MemAccountStorage, MemTransactionStorage>| {
+            sorted_accounts(bank)
+                .into_iter()
+                .map(|acc| (acc.name.clone(), acc.balance()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(balances(&target), balances(&source));
+        assert_eq!(target.transactions().unwrap(), all);
+        assert!(target.audit_integrity().unwrap().is_consistent());
+
+        // new ids continue after the replicated ones
+        let mut acc = target.account("test_2".to_owned()).unwrap();
+        let id = target.inc_acc_balance(&mut acc, 1).unwrap();
+        assert_eq!(seq(id), all.last().unwrap().id + 1);
+
+        // a transaction of an unregistered account
+        let mut target = new_bank(None);
+        assert_eq!(
+            target.replay_onto(&all[1..]).err().unwrap(),
+            AccError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_bank_replay_onto_account_changes() {
+        let mut source = new_bank(Some(1));
+        for name in ["a", "c", "d", "e"] {
+            source
+                .create_account_with_balance(name.to_owned(), 10)
+                .unwrap();
+        }
+        let mut replica = new_bank(Some(1));
+        replica
+            .replay_onto(&source.transactions().unwrap())
+            .unwrap();
+
+        // each change is replayed on its own and all of them at once on a fresh replica
+        source.close_to("c".to_owned(), "a".to_owned()).unwrap();
+        replica
+            .replay_onto(&source.transactions().unwrap())
+            .unwrap();
+        assert!(!replica.account_exists("c".to_owned()).unwrap());
+        assert_eq!(replica.state_hash().unwrap(), source.state_hash().unwrap());
+
+        source
+            .rename_account("a".to_owned(), "b".to_owned())
+            .unwrap();
+        source
+            .external_deposit("b".to_owned(), 5, "wire".to_owned())
+            .unwrap();
+        replica
+            .replay_onto(&source.transactions().unwrap())
+            .unwrap();
+        assert_eq!(replica.account("b".to_owned()).unwrap().balance(), 25);
+        assert_eq!(replica.state_hash().unwrap(), source.state_hash().unwrap());
+
+        source
+            .merge_account("d".to_owned(), "e".to_owned())
+            .unwrap();
+        replica
+            .replay_onto(&source.transactions().unwrap())
+            .unwrap();
+        assert!(!replica.account_exists("d".to_owned()).unwrap());
+        assert_eq!(replica.state_hash().unwrap(), source.state_hash().unwrap());
+        assert_eq!(sorted_transactions(&replica), sorted_transactions(&source));
+
+        let mut fresh = new_bank(Some(1));
+        fresh.replay_onto(&source.transactions().unwrap()).unwrap();
+        assert_eq!(fresh.state_hash().unwrap(), source.state_hash().unwrap());
+        assert!(fresh.audit_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_bank_holds() {
+        let mut bank = new_bank(Some(1));
+        bank.create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+
+        let hold_id = bank.hold("test_1".to_owned(), 60).unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!(
+            (acc.balance(), acc.held(), acc.available_balance()),
+            (100, 60, 40)
+        );
+        assert_eq!(acc.to_string(), "Account: test_1. Balance: 100. Held: 60");
+
+        // held money can't be spent twice
+        assert_eq!(
+            bank.hold("test_1".to_owned(), 41).err().unwrap(),
+            AccError::NotEnoughMoney
+        );
+        assert_eq!(
+            bank.transfer_all("test_1".to_owned(), "test_2".to_owned())
+                .unwrap(),
+            39
+        );
+        assert_eq!(
+            bank.withdraw_all("test_1".to_owned()).unwrap_err(),
+            AccError::EmptyTransaction
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 60);
+
+        // settle transfers the held amount, the fee comes from the available balance
+        assert_eq!(
+            bank.settle(hold_id, "test_2".to_owned()).err().unwrap(),
+            AccError::NotEnoughMoney
+        );
+        let mut acc = bank.account("test_1".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 1).unwrap();
+        let tr_id = bank.settle(hold_id, "test_2".to_owned()).unwrap();
+        assert_eq!(
+            bank.transaction_by_id(tr_id).unwrap().action.kind(),
+            ActionKind::TransferOut
+        );
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.held()), (0, 0));
+        assert_eq!(bank.account("test_2".to_owned()).unwrap().balance(), 99);
+        assert_eq!(
+            bank.settle(hold_id, "test_2".to_owned()).err().unwrap(),
+            AccError::Storage("hold already closed".to_owned())
+        );
+
+        // release makes the money available again without moving it
+        let hold_id = bank.hold("test_2".to_owned(), 50).unwrap();
+        let release_id = bank.release(hold_id).unwrap();
+        assert_eq!(
+            bank.transaction_by_id(release_id).unwrap().action,
+            TransactionAction::Release {
+                hold_id: seq(hold_id),
+                amount: 50
+            }
+        );
+        let acc = bank.account("test_2".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.held()), (99, 0));
+        assert_eq!(
+            bank.release(hold_id).err().unwrap(),
+            AccError::Storage("hold already closed".to_owned())
+        );
+        assert_eq!(
+            bank.release(release_id).err().unwrap(),
+            AccError::TransactionNotExists
+        );
+        assert_eq!(
+            bank.hold("test_2".to_owned(), 0).err().unwrap(),
+            AccError::EmptyTransaction
+        );
+        assert!(bank.audit_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_bank_holds_stale_handle() {
+        let mut bank = new_bank(None);
+        bank.create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        let mut acc_f = bank.account("test_1".to_owned()).unwrap();
+
+        // the handle was taken before the hold, the held money still can't be spent
+        let hold_id = bank.hold("test_1".to_owned(), 60).unwrap();
+        assert_eq!(acc_f.held(), 0);
+        assert_eq!(
+            bank.make_transaction(&mut acc_f, &mut acc_s, 50, None, None)
+                .err()
+                .unwrap(),
+            AccError::NotEnoughMoney
+        );
+        assert_eq!(acc_f.held(), 60);
+
+        // and a handle that misses the hold doesn't drop it from storage
+        acc_f.held.clear();
+        bank.decr_acc_balance(&mut acc_f, 40).unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.held()), (60, 60));
+
+        // a failed settle keeps the hold reserved
+        bank.freeze_account("test_2".to_owned()).unwrap();
+        assert_eq!(
+            bank.settle(hold_id, "test_2".to_owned()).err().unwrap(),
+            AccError::AccountFrozen
+        );
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().held(), 60);
+        bank.unfreeze_account("test_2".to_owned()).unwrap();
+        bank.settle(hold_id, "test_2".to_owned()).unwrap();
+        let acc = bank.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.held()), (0, 0));
+    }
+
+    #[test]
+    fn test_bank_restore_read_failure() {
+        let mut acc_storage = BrokenReads {
+            inner: MemAccountStorage::new().unwrap(),
+            broken: false,
+        };
+        acc_storage
+            .create_account(AccountTransfer {
+                min_balance: 5,
+                ..AccountTransfer::new("test".to_owned(), Some(10))
+            })
+            .unwrap();
+
+        // a failed read is reported, not taken for a missing account
+        acc_storage.broken = true;
+        assert_eq!(
+            Account::store_restored_balance(
+                "test".to_owned(),
+                HashMap::from([(DEFAULT_CURRENCY.to_string(), 20)]),
+                HashMap::new(),
+                &mut acc_storage
+            )
+            .err()
+            .unwrap(),
+            AccError::Storage("read failed".to_owned())
+        );
+        acc_storage.broken = false;
+        let acc = acc_storage.get_account("test".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.min_balance), (10, 5));
+
+        // the stored settings are kept, a missing account is created
+        let acc = Account::store_restored_balance(
+            "test".to_owned(),
+            HashMap::from([(DEFAULT_CURRENCY.to_string(), 20)]),
+            HashMap::new(),
+            &mut acc_storage,
+        )
+        .unwrap();
+        assert_eq!((acc.balance(), acc.min_balance), (20, 5));
+        let acc = Account::store_restored_balance(
+            "new".to_owned(),
+            HashMap::from([(DEFAULT_CURRENCY.to_string(), 7)]),
+            HashMap::new(),
+            &mut acc_storage,
+        )
+        .unwrap();
+        assert_eq!((acc.balance(), acc.min_balance), (7, 0));
+    }
+
+    #[test]
+    fn test_bank_holds_restore() {
+        let mut bank = new_bank(None);
+        bank.create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+        let hold_id = bank.hold("test_1".to_owned(), 30).unwrap();
+        bank.hold("test_1".to_owned(), 20).unwrap();
+        bank.release(hold_id).unwrap();
+
+        // the held amount is rebuilt from the log
+        let mut raw = bank.acc_storage.get_account("test_1".to_owned()).unwrap();
+        raw.held.clear();
+        bank.acc_storage.update_account(raw).unwrap();
+        let acc = bank.rebuild_account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.held()), (100, 20));
+
+        let mut replica = new_bank(None);
+        replica.replay_onto(&bank.transactions().unwrap()).unwrap();
+        let acc = replica.account("test_1".to_owned()).unwrap();
+        assert_eq!((acc.balance(), acc.held()), (100, 20));
+    }
+
+    // splits csv text into rows of unquoted fields
+    fn parse_csv(csv: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        for line in csv.lines() {
+            let (mut fields, mut field, mut quoted) = (Vec::new(), String::new(), false);
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted && chars.peek() == Some(&'"') => {
+                        field.push('"');
+                        chars.next();
+                    }
+                    '"' => quoted = !quoted,
+                    ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                    c => field.push(c),
+                }
+            }
+            fields.push(field);
+            rows.push(fields);
+        }
+        rows
+    }
+
+    #[test]
+    fn test_bank_export_csv() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("smith, john".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("say \"hi\"".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.inc_acc_balance_in(&mut acc_f, 50, "EUR".to_owned(), None)
+            .unwrap();
+        bank.make_transaction_with_options(&mut acc_f, &mut acc_s, 20, None, in_currency("EUR"))
+            .unwrap();
+
+        let accounts = parse_csv(&bank.export_accounts_csv().unwrap());
+        assert_eq!(
+            accounts,
+            [
+                ["name", "currency", "balance"],
+                ["fee_acc", "USD", "1"],
+                ["fee_acc", "EUR", "1"],
+                ["say \"hi\"", "USD", "10"],
+                ["say \"hi\"", "EUR", "20"],
+                ["smith, john", "USD", "89"],
+                ["smith, john", "EUR", "29"]
+            ]
+        );
+
+        let csv = bank.export_transactions_csv().unwrap();
+        let rows = parse_csv(&csv);
+        assert_eq!(rows.len(), bank.transaction_count().unwrap() + 1);
+        assert_eq!(
+            rows[0],
+            [
+                "id",
+                "account_name",
+                "action",
+                "amount",
+                "to",
+                "fee",
+                "currency"
+            ]
+        );
+        assert!(rows.iter().all(|row| row.len() == 7));
+        assert_eq!(
+            rows[2],
+            ["2", "smith, john", "increment", "100", "", "", "USD"]
+        );
+        let transfers = rows
+            .iter()
+            .filter(|row| row[2] == "transfer_out")
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transfers[0][1..],
+            [
+                "smith, john",
+                "transfer_out",
+                "10",
+                "say \"hi\"",
+                "1",
+                "USD"
+            ]
+        );
+        assert_eq!(
+            transfers[1][1..],
+            [
+                "smith, john",
+                "transfer_out",
+                "20",
+                "say \"hi\"",
+                "1",
+                "EUR"
+            ]
+        );
+        let incoming = rows.iter().find(|row| row[2] == "transfer_in").unwrap();
+        assert_eq!(
+            incoming[1..],
+            ["say \"hi\"", "transfer_in", "10", "", "", "USD"]
+        );
+        assert!(csv.contains(",\"smith, john\",") && csv.contains("\"say \"\"hi\"\"\""));
+    }
+
+    #[test]
+    fn test_bank_state_hash() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+
+        // the same balances reached another way, accounts created in another order
+        let mut other = new_bank(None);
+        let mut acc_s = other
+            .create_account_with_balance("test_2".to_owned(), 10)
+            .unwrap();
+        other
+            .create_account_with_balance("test_1".to_owned(), 89)
+            .unwrap();
+        let mut fee_acc = other.acc_storage.fee_account().unwrap();
+        fee_acc.set_balance(1);
+        other.acc_storage.update_account(fee_acc).unwrap();
+        assert_eq!(bank.state_hash().unwrap(), other.state_hash().unwrap());
+        assert_eq!(bank.state_hash().unwrap(), bank.state_hash().unwrap());
+
+        other.inc_acc_balance(&mut acc_s, 1).unwrap();
+        assert_ne!(bank.state_hash().unwrap(), other.state_hash().unwrap());
+
+        // account names are part of the hash
+        let mut bank = new_bank(None);
+        bank.create_account("a".to_owned()).unwrap();
+        let mut other = new_bank(None);
+        other.create_account("b".to_owned()).unwrap();
+        assert_ne!(bank.state_hash().unwrap(), other.state_hash().unwrap());
+        assert_ne!(
+            bank.state_hash().unwrap(),
+            new_bank(None).state_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_bank_freeze_stale_handle() {
+        let mut bank = new_bank(Some(1));
+        let mut acc_f = bank.create_account("test_1".to_owned()).unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 100).unwrap();
+        bank.inc_acc_balance(&mut acc_s, 100).unwrap();
+
+        // handles taken before the freeze still see the account as active
+        bank.freeze_account("test_1".to_owned()).unwrap();
+        assert!(!acc_f.frozen);
+        assert_eq!(
+            bank.decr_acc_balance(&mut acc_f, 10).err().unwrap(),
+            AccError::AccountFrozen
+        );
+        assert_eq!(
+            bank.make_transaction(&mut acc_s, &mut acc_f, 10, None, None)
+                .err()
+                .unwrap(),
+            AccError::AccountFrozen
+        );
+        assert!(acc_f.frozen);
+
+        // and a handle that still says frozen doesn't write the flag back
+        bank.unfreeze_account("test_1".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc_f, 10).unwrap();
+        assert!(!bank.account("test_1".to_owned()).unwrap().frozen);
+        assert_eq!(bank.account("test_1".to_owned()).unwrap().balance(), 110);
+    }
+
+    #[test]
+    fn test_bank_first_last_transaction() {
+        let mut bank = new_bank(None);
+        assert!(bank.first_transaction().unwrap().is_none());
+        assert!(bank.last_transaction().unwrap().is_none());
+
+        let mut acc = bank.create_account("test_1".to_owned()).unwrap();
+        bank.create_account("test_2".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 10).unwrap();
+
+        let first = bank.first_transaction().unwrap().unwrap();
+        assert_eq!(first.id, 1);
+        assert_eq!(first.account_name, "test_1");
+
+        let last = bank.last_transaction().unwrap().unwrap();
+        assert_eq!(last.id, 3);
+        assert_eq!(last.action, TransactionAction::Increment(10));
+
+        // ids are not contiguous after a restore
+        bank.tr_storage
+            .restore_transaction(TransactionTransfer {
+                id: 10,
+                action: TransactionAction::Increment(1),
+                account_name: "test_2".to_owned(),
+                created_at: 0,
+                memo: None,
+                uuid: None,
+                currency: None,
+            })
+            .unwrap();
+        assert_eq!(bank.first_transaction().unwrap().unwrap().id, 1);
+        assert_eq!(bank.last_transaction().unwrap().unwrap().id, 10);
+    }
+
+    #[test]
+    fn test_bank_stream_account_csv() {
+        let mut bank = new_bank(None);
+        let mut acc = bank.create_account("test".to_owned()).unwrap();
+        bank.inc_acc_balance(&mut acc, 10).unwrap();
+        bank.decr_acc_balance(&mut acc, 3).unwrap();
+        bank.inc_acc_balance(&mut acc, 5).unwrap();
+
+        let mut w = CountingWriter::default();
+        bank.stream_account_csv("test".to_owned(), &mut w).unwrap();
+        assert_eq!(
+            w.lines,
+            vec![
+                "id,action,amount,fee,balance,currency",
+                "1,registration,0,,0,USD",
+                "2,increment,10,,10,USD",
+                "3,decrement,3,,7,USD",
+                "4,increment,5,,12,USD",
+            ]
+        );
+
+        // a long history is written incrementally
+        for _ in 0..1000 {
+            bank.inc_acc_balance(&mut acc, 1).unwrap();
+        }
+        let mut w = CountingWriter::default();
+        bank.stream_account_csv("test".to_owned(), &mut w).unwrap();
+        assert_eq!(w.lines.len(), 1005);
+        assert_eq!(w.lines[1004], "1004,increment,1,,1012,USD");
+        assert!(w.bytes > 10_000);
+        assert!(w.max_write < 64);
+
+        // unknown account, nothing is written
+        let mut w = CountingWriter::default();
+        assert_eq!(
+            bank.stream_account_csv("not_exists".to_owned(), &mut w)
+                .err()
+                .unwrap(),
+            AccError::AccountNotExists
+        );
+        assert!(w.lines.is_empty());
+
+        // a transfer amount doesn't include the fee, as in export_transactions_csv
+        let mut other = bank.create_account("other".to_owned()).unwrap();
+        bank.make_transaction(&mut acc, &mut other, 10, None, Some(2))
+            .unwrap();
+        let mut w = CountingWriter::default();
+        bank.stream_account_csv("test".to_owned(), &mut w).unwrap();
+        assert_eq!(w.lines[1005], "1006,transfer_out,10,2,1000,USD");
+        let csv = bank.export_transactions_csv().unwrap();
+        assert!(csv.contains("\n1006,test,transfer_out,10,other,2,USD\n"));
+
+        // the running balance of another currency is kept apart
+        bank.inc_acc_balance_in(&mut acc, 7, "EUR".to_owned(), None)
+            .unwrap();
+        bank.decr_acc_balance_in(&mut acc, 2, "EUR".to_owned(), None)
+            .unwrap();
+        let mut w = CountingWriter::default();
+        bank.stream_account_csv("test".to_owned(), &mut w).unwrap();
+        assert_eq!(
+            w.lines[1006..],
+            ["1009,increment,7,,7,EUR", "1010,decrement,2,,5,EUR"]
+        );
+    }
+
+    #[test]
+    fn test_bank_stream_account_csv_without_log() {
+        let mut bank = new_bank(None);
+        let fee_name = bank.acc_storage.fee_account().unwrap().name;
+
+        // the fee account exists but has no transactions before the first fee
+        let mut w = CountingWriter::default();
+        bank.stream_account_csv(fee_name.clone(), &mut w).unwrap();
+        assert_eq!(w.lines, vec!["id,action,amount,fee,balance,currency"]);
+
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("test_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, Some(2))
+            .unwrap();
+        let mut w = CountingWriter::default();
+        bank.stream_account_csv(fee_name, &mut w).unwrap();
+        assert_eq!(w.lines.len(), 2);
+    }
 }
@@ -1,23 +1,94 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 
 use crate::bank::storage::{
-    AccountStorage, AccountTransfer, TransactionAction, TransactionStorage,
+    set_currency_balance, AccountStorage, AccountTransfer, ExternalDirection, TransactionAction,
+    TransactionId, TransactionStorage, DEFAULT_CURRENCY, MAX_MEMO_LEN,
 };
 
 use super::storage::Error as StorageError;
 
-#[derive(Debug)]
+// amount per currency code
+pub type CurrencyAmounts = HashMap<String, usize>;
+
+#[derive(Debug, PartialEq, Eq)]
 pub struct Account {
-    pub balance: usize,
+    // balance per currency code (currencies with a zero balance are left out);
+    // the minimum balance and the transfer limit are in the default currency
+    pub balances: HashMap<String, usize>,
     pub name: String,
+    pub min_balance: usize,
+    pub frozen: bool,
+    pub transfer_limit: Option<usize>,
+    pub transferred: usize,
+    // held amount per currency (currencies without holds are left out)
+    pub held: HashMap<String, usize>,
 }
 
+// one line summary with set limits and flags; `{:#}` prints every field on its own line
 impl Display for Account {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Account: {}. Balance: {}", self.name, self.balance)
+        let limit = self
+            .transfer_limit
+            .map_or("none".to_string(), |limit| limit.to_string());
+        if f.alternate() {
+            writeln!(f, "Account: {}", self.name)?;
+            writeln!(f, "  Balance: {}", self.balance())?;
+            for (currency, balance) in self.other_balances() {
+                writeln!(f, "  Balance {currency}: {balance}")?;
+            }
+            writeln!(f, "  Min balance: {}", self.min_balance)?;
+            writeln!(f, "  Transfer limit: {limit}")?;
+            writeln!(f, "  Transferred: {}", self.transferred)?;
+            writeln!(f, "  Held: {}", self.held())?;
+            for (currency, held) in other_amounts(&self.held) {
+                writeln!(f, "  Held {currency}: {held}")?;
+            }
+            write!(f, "  Frozen: {}", self.frozen)
+        } else {
+            write!(f, "Account: {}. Balance: {}", self.name, self.balance())?;
+            for (currency, balance) in self.other_balances() {
+                write!(f, ". Balance {currency}: {balance}")?;
+            }
+            if self.min_balance > 0 {
+                write!(f, ". Min balance: {}", self.min_balance)?;
+            }
+            if self.transfer_limit.is_some() {
+                write!(f, ". Transferred: {}/{limit}", self.transferred)?;
+            }
+            if self.held() > 0 {
+                write!(f, ". Held: {}", self.held())?;
+            }
+            for (currency, held) in other_amounts(&self.held) {
+                write!(f, ". Held {currency}: {held}")?;
+            }
+            if self.frozen {
+                write!(f, ". Frozen")?;
+            }
+            Ok(())
+        }
     }
 }
 
+// order of accounts in listings (ties are broken by name)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountSort {
+    NameAsc,
+    BalanceDesc,
+    BalanceAsc,
+}
+
+// how a transfer is made besides its value
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransferOptions {
+    // None - no fee (Bank: the fee policy decides)
+    pub fee: Option<usize>,
+    // stored on both sides of the transfer
+    pub memo: Option<String>,
+    // currency of the value and the fee (None - DEFAULT_CURRENCY)
+    pub currency: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error {
     Storage(String),
@@ -26,6 +97,20 @@ pub enum Error {
     EmptyTransaction,
     NotEnoughMoney,
     TransactionNotExists,
+    InvalidAccountName,
+    BelowMinimumBalance,
+    AccountFrozen,
+    TransferLimitExceeded,
+    AmountTooLarge,
+    HoldsOpen,
+    InvalidCurrency,
+}
+
+// account that could not be restored from a transaction log
+#[derive(Debug, PartialEq, Eq)]
+pub struct RestoreFailure {
+    pub account_name: String,
+    pub error: Error,
 }
 
 impl From<StorageError> for Error {
@@ -39,11 +124,55 @@ impl From<StorageError> for Error {
     }
 }
 
+// balance after crediting value
+// errors: Storage (balance overflow)
+fn checked_credit(balance: usize, value: usize) -> Result<usize, Error> {
+    balance
+        .checked_add(value)
+        .ok_or_else(|| Error::Storage("balance overflow".to_string()))
+}
+
+// errors: InvalidCurrency (not three ascii uppercase letters)
+fn check_currency(currency: &str) -> Result<(), Error> {
+    if currency.len() != 3 || !currency.bytes().all(|x| x.is_ascii_uppercase()) {
+        return Err(Error::InvalidCurrency);
+    }
+    Ok(())
+}
+
+// currency stored with a transaction (None for the default one)
+pub(crate) fn currency_tag(currency: &str) -> Option<String> {
+    (currency != DEFAULT_CURRENCY).then(|| currency.to_string())
+}
+
+// value of a transfer and its fee, both in `currency`
+#[derive(Clone, Copy)]
+struct Transfer<'a> {
+    value: usize,
+    fee: usize,
+    currency: &'a str,
+    // the value counts towards the sender's transfer limit
+    counted: bool,
+}
+
+// errors: Storage (memo longer than MAX_MEMO_LEN chars)
+fn check_memo(memo: Option<&str>) -> Result<(), Error> {
+    if memo.is_some_and(|memo| memo.chars().count() > MAX_MEMO_LEN) {
+        return Err(Error::Storage("memo too long".to_string()));
+    }
+    Ok(())
+}
+
 impl From<AccountTransfer> for Account {
     fn from(value: AccountTransfer) -> Self {
         Account {
             name: value.name,
-            balance: value.balance,
+            balances: value.balances,
+            min_balance: value.min_balance,
+            frozen: value.frozen,
+            transfer_limit: value.transfer_limit,
+            transferred: value.transferred,
+            held: value.held,
         }
     }
 }
@@ -61,55 +190,125 @@ impl Account {
         tr_storage.create_transaction(name.clone(), TransactionAction::Registration)?;
         Ok(Account {
             name: name.clone(),
-            balance: Default::default(),
+            balances: HashMap::new(),
+            min_balance: 0,
+            frozen: false,
+            transfer_limit: None,
+            transferred: 0,
+            held: HashMap::new(),
         })
     }
 
     // task 2 part 1
     // increments an account balance
-    // errors: EmptyTransaction, Storage, AccountNotExists
+    // errors: EmptyTransaction, AccountFrozen, Storage, AccountNotExists
     pub fn inc_balance<S: AccountStorage, T: TransactionStorage>(
         &mut self,
         value: usize,
         acc_storage: &mut S,
         tr_storage: &mut T,
-    ) -> Result<usize, Error> {
+    ) -> Result<TransactionId, Error> {
+        self.inc_balance_with_memo(value, None, acc_storage, tr_storage)
+    }
+
+    // same as inc_balance, the memo is stored with the increment
+    // errors: as inc_balance, Storage (memo too long)
+    pub fn inc_balance_with_memo<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        memo: Option<String>,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        self.inc_balance_in(value, DEFAULT_CURRENCY, memo, acc_storage, tr_storage)
+    }
+
+    // same as inc_balance_with_memo, the value is credited in `currency`
+    // errors: as inc_balance_with_memo, InvalidCurrency
+    pub fn inc_balance_in<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        currency: &str,
+        memo: Option<String>,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
         if value == 0 {
             return Err(Error::EmptyTransaction);
         }
+        check_currency(currency)?;
+        check_memo(memo.as_deref())?;
+        let mut acc_tr = self.load(acc_storage)?;
+        self.check_not_frozen()?;
 
-        let mut acc_tr = self.transfer_data();
-        acc_tr.balance += value;
+        let balance = checked_credit(acc_tr.balance_in(currency), value)?;
+        acc_tr.set_balance_in(currency, balance);
         acc_storage.update_account(acc_tr)?;
-        let tr_tr = tr_storage
-            .create_transaction(self.name.clone(), TransactionAction::Increment(value))?;
-        self.balance += value;
-        Ok(tr_tr.id)
+        let tr_tr = tr_storage.create_transaction_in(
+            self.name.clone(),
+            TransactionAction::Increment(value),
+            memo,
+            currency_tag(currency),
+        )?;
+        self.set_balance_in(currency, balance);
+        Ok(tr_tr.transaction_id())
     }
 
     // task 2 part 2
     // decrements an account balance
-    // errors: EmptyTransaction, Storage, NotEnoughMoney
+    // errors: EmptyTransaction, Storage, NotEnoughMoney, BelowMinimumBalance, AccountFrozen
     pub fn decr_balance<S: AccountStorage, T: TransactionStorage>(
         &mut self,
         value: usize,
         acc_storage: &mut S,
         tr_storage: &mut T,
-    ) -> Result<usize, Error> {
-        if value > self.balance {
-            return Err(Error::NotEnoughMoney);
-        }
+    ) -> Result<TransactionId, Error> {
+        self.decr_balance_with_memo(value, None, acc_storage, tr_storage)
+    }
+
+    // same as decr_balance, the memo is stored with the decrement
+    // errors: as decr_balance, Storage (memo too long)
+    pub fn decr_balance_with_memo<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        memo: Option<String>,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        self.decr_balance_in(value, DEFAULT_CURRENCY, memo, acc_storage, tr_storage)
+    }
 
-        let mut raw = self.transfer_data();
-        raw.balance -= value;
+    // same as decr_balance_with_memo, the value is debited in `currency`
+    // errors: as decr_balance_with_memo, InvalidCurrency
+    pub fn decr_balance_in<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        currency: &str,
+        memo: Option<String>,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        check_currency(currency)?;
+        check_memo(memo.as_deref())?;
+        let mut raw = self.load(acc_storage)?;
+        self.check_withdrawal_in(value, currency)?;
+
+        let balance = raw.balance_in(currency) - value;
+        raw.set_balance_in(currency, balance);
         acc_storage.update_account(raw)?;
-        self.balance -= value;
-        let tr_tr = tr_storage
-            .create_transaction(self.name.clone(), TransactionAction::Decrement(value))?;
-        Ok(tr_tr.id)
+        self.set_balance_in(currency, balance);
+        let tr_tr = tr_storage.create_transaction_in(
+            self.name.clone(),
+            TransactionAction::Decrement(value),
+            memo,
+            currency_tag(currency),
+        )?;
+        Ok(tr_tr.transaction_id())
     }
 
     // task 3 make transactions from an one account to another
+    // errors: EmptyTransaction, NotEnoughMoney, BelowMinimumBalance, AccountFrozen,
+    // TransferLimitExceeded, AccountNotExists, Storage
     pub fn make_transaction<S: AccountStorage, T: TransactionStorage>(
         &mut self,
         value: usize,
@@ -117,47 +316,307 @@ impl Account {
         fee_amount: Option<usize>,
         acc_storage: &mut S,
         tr_storage: &mut T,
-    ) -> Result<usize, Error> {
-        let def_fee = 0;
+    ) -> Result<TransactionId, Error> {
+        self.make_transaction_with_memo(value, to, fee_amount, None, acc_storage, tr_storage)
+    }
+
+    // same as make_transaction, the memo is stored on both sides of the transfer
+    // errors: as make_transaction, Storage (memo too long)
+    pub fn make_transaction_with_memo<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        to: &mut Account,
+        fee_amount: Option<usize>,
+        memo: Option<String>,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        let options = TransferOptions {
+            fee: fee_amount,
+            memo,
+            currency: None,
+        };
+        self.make_transaction_with_options(value, to, options, acc_storage, tr_storage)
+    }
+
+    // same as make_transaction with the fee, memo and currency of options; in another
+    // currency the value and the fee are debited from the sender in it and credited in
+    // it to the receiver and the fee account (which keeps fees per currency)
+    // errors: as make_transaction_with_memo, InvalidCurrency
+    pub fn make_transaction_with_options<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        to: &mut Account,
+        options: TransferOptions,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        let currency = options.currency.as_deref().unwrap_or(DEFAULT_CURRENCY);
+        check_currency(currency)?;
+        check_memo(options.memo.as_deref())?;
+        let transfer = Transfer {
+            value,
+            fee: options.fee.unwrap_or_default(),
+            currency,
+            counted: true,
+        };
+        self.checked_transfer(transfer, to, options.memo, acc_storage, tr_storage)
+    }
+
+    // errors: see make_transaction
+    fn checked_transfer<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        transfer: Transfer,
+        to: &mut Account,
+        memo: Option<String>,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        // both sides are checked against their stored records, the receiver must
+        // exist and the fee account must be able to take the fee before the
+        // sender is touched
+        self.load(acc_storage)?;
+        to.load(acc_storage)?;
+        self.check_transfer_in(transfer.value, transfer.currency, to, transfer.fee)?;
+        checked_credit(
+            acc_storage.fee_account()?.balance_in(transfer.currency),
+            transfer.fee,
+        )?;
+        self.store_transfer(to, transfer, memo, acc_storage, tr_storage)
+    }
+
+    // moves value to `to` on behalf of the bank (refunds, sweeps of closed accounts):
+    // only the available balance is checked, frozen flags, the minimum balance and the
+    // transfer limit don't apply and the value isn't counted in `transferred`; logged
+    // as a fee free transfer
+    // errors: EmptyTransaction, NotEnoughMoney, AccountNotExists, Storage (same account,
+    // balance overflow)
+    pub fn bank_transfer<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        currency: &str,
+        to: &mut Account,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
         if value == 0 {
-            Err(Error::EmptyTransaction)
-        } else if value + fee_amount.unwrap_or(def_fee) > self.balance {
-            Err(Error::NotEnoughMoney)
+            return Err(Error::EmptyTransaction);
+        }
+        self.load(acc_storage)?;
+        to.load(acc_storage)?;
+        if to.name == self.name {
+            return Err(Error::Storage("transfer to the same account".to_string()));
+        }
+        if value > self.available_balance_in(currency) {
+            return Err(Error::NotEnoughMoney);
+        }
+        checked_credit(to.balance_in(currency), value)?;
+        let transfer = Transfer {
+            value,
+            fee: 0,
+            currency,
+            counted: false,
+        };
+        self.store_transfer(to, transfer, None, acc_storage, tr_storage)
+    }
+
+    // stores a checked transfer: debits value + fee from the account (and counts the
+    // value in its transfer usage), credits value to `to` and fee to the fee account;
+    // the handles are updated only after their stored records
+    // errors: AccountNotExists, Storage
+    fn store_transfer<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        to: &mut Account,
+        transfer: Transfer,
+        memo: Option<String>,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        let Transfer {
+            value,
+            fee,
+            currency,
+            counted,
+        } = transfer;
+        let mut raw_self = acc_storage.get_account(self.name.clone())?;
+        let mut raw_to = acc_storage.get_account(to.name.clone())?;
+        let debit = value + fee;
+        let self_balance = raw_self.balance_in(currency) - debit;
+        let to_balance = raw_to.balance_in(currency) + value;
+        // the transfer limit applies to the default currency only
+        let transferred = if counted && currency == DEFAULT_CURRENCY {
+            raw_self.transferred.saturating_add(value)
         } else {
-            let mut raw_self = self.transfer_data();
-            raw_self.balance -= value + fee_amount.unwrap_or(def_fee);
+            raw_self.transferred
+        };
+        raw_self.set_balance_in(currency, self_balance);
+        raw_self.transferred = transferred;
+        raw_to.set_balance_in(currency, to_balance);
 
-            let mut raw_to = to.transfer_data();
-            raw_to.balance += value;
+        // decrement balance of sender
+        acc_storage.update_account(raw_self)?;
+        let self_tr = tr_storage.create_transaction_in(
+            self.name.clone(),
+            TransactionAction::TransferOut {
+                to: to.name.clone(),
+                value,
+                fee,
+                memo: memo.clone(),
+            },
+            None,
+            currency_tag(currency),
+        )?;
+        self.set_balance_in(currency, self_balance);
+        self.transferred = transferred;
 
-            // increment balance of sender
-            acc_storage.update_account(raw_self)?;
-            let self_tr = tr_storage.create_transaction(
-                self.name.clone(),
-                TransactionAction::Decrement(value + fee_amount.unwrap_or(def_fee)),
+        // increment balance of receiver (paired credit, so the receiver sees the transfer)
+        acc_storage.update_account(raw_to)?;
+        tr_storage.create_transaction_in(
+            to.name.clone(),
+            TransactionAction::TransferIn {
+                from: self.name.clone(),
+                value,
+                memo,
+            },
+            None,
+            currency_tag(currency),
+        )?;
+        to.set_balance_in(currency, to_balance);
+
+        // increment fee acc (fees are kept in the currency of the transfer)
+        let mut fee_acc = acc_storage.fee_account()?;
+        let fee_balance = checked_credit(fee_acc.balance_in(currency), fee)?;
+        fee_acc.set_balance_in(currency, fee_balance);
+        acc_storage.update_account(fee_acc.clone())?;
+
+        // create fee transaction (linked to the sender side)
+        if fee > 0 {
+            tr_storage.create_transaction_in(
+                fee_acc.name,
+                TransactionAction::Fee {
+                    transfer_id: self_tr.id,
+                    amount: fee,
+                },
+                None,
+                currency_tag(currency),
             )?;
-            self.balance -= value + fee_amount.unwrap_or(def_fee);
-
-            // increment balance of receiver
-            acc_storage.update_account(raw_to)?;
-            tr_storage.create_transaction(to.name.clone(), TransactionAction::Increment(value))?;
-            to.balance += value;
-
-            // increment fee acc
-            let mut fee_acc = acc_storage.fee_account()?;
-            fee_acc.balance += fee_amount.unwrap_or(def_fee);
-            acc_storage.update_account(fee_acc.clone())?;
-
-            // create fee transaction
-            if fee_amount.unwrap_or(def_fee) > 0 {
-                tr_storage.create_transaction(
-                    fee_acc.name,
-                    TransactionAction::Increment(fee_amount.unwrap_or(def_fee)),
-                )?;
+        }
+
+        Ok(self_tr.transaction_id())
+    }
+
+    // moves money in `currency` between the account and an external system (no
+    // counterparty account)
+    // errors: EmptyTransaction, NotEnoughMoney, BelowMinimumBalance, AccountFrozen, Storage,
+    // AccountNotExists, InvalidCurrency
+    pub fn external_transfer<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        direction: ExternalDirection,
+        value: usize,
+        currency: &str,
+        reference: String,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        if value == 0 {
+            return Err(Error::EmptyTransaction);
+        }
+        check_currency(currency)?;
+
+        let mut raw = self.load(acc_storage)?;
+        self.check_not_frozen()?;
+        let balance = match direction {
+            ExternalDirection::Deposit => checked_credit(raw.balance_in(currency), value)?,
+            ExternalDirection::Withdrawal => {
+                self.check_withdrawal_in(value, currency)?;
+                raw.balance_in(currency) - value
             }
+        };
+        raw.set_balance_in(currency, balance);
+        acc_storage.update_account(raw)?;
+        let tr_tr = tr_storage.create_transaction_in(
+            self.name.clone(),
+            TransactionAction::External {
+                direction,
+                amount: value,
+                reference,
+            },
+            None,
+            currency_tag(currency),
+        )?;
+        self.set_balance_in(currency, balance);
+        Ok(tr_tr.transaction_id())
+    }
+
+    // reserves value of the balance (it can't be spent until released), returns the hold id
+    // errors: EmptyTransaction, NotEnoughMoney, BelowMinimumBalance, AccountFrozen, Storage
+    pub fn hold<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        self.hold_in(value, DEFAULT_CURRENCY, acc_storage, tr_storage)
+    }
 
-            Ok(self_tr.id)
+    // same as hold, the value is reserved in `currency`
+    // errors: as hold, InvalidCurrency
+    pub fn hold_in<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        value: usize,
+        currency: &str,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        if value == 0 {
+            return Err(Error::EmptyTransaction);
         }
+        check_currency(currency)?;
+        let mut raw = self.load(acc_storage)?;
+        self.check_withdrawal_in(value, currency)?;
+
+        let held = raw.held_in(currency) + value;
+        raw.set_held_in(currency, held);
+        acc_storage.update_account(raw)?;
+        let tr_tr = tr_storage.create_transaction_in(
+            self.name.clone(),
+            TransactionAction::Hold(value),
+            None,
+            currency_tag(currency),
+        )?;
+        self.set_held_in(currency, held);
+        Ok(tr_tr.transaction_id())
+    }
+
+    // makes value reserved in `currency` by the hold `hold_id` available again,
+    // returns the transaction id
+    // errors: Storage (more than held)
+    pub fn release_hold<S: AccountStorage, T: TransactionStorage>(
+        &mut self,
+        hold_id: usize,
+        value: usize,
+        currency: &str,
+        acc_storage: &mut S,
+        tr_storage: &mut T,
+    ) -> Result<TransactionId, Error> {
+        let mut raw = self.load(acc_storage)?;
+        let held = raw
+            .held_in(currency)
+            .checked_sub(value)
+            .ok_or_else(|| Error::Storage("release exceeds held amount".to_string()))?;
+        raw.set_held_in(currency, held);
+        acc_storage.update_account(raw)?;
+        let tr_tr = tr_storage.create_transaction_in(
+            self.name.clone(),
+            TransactionAction::Release {
+                hold_id,
+                amount: value,
+            },
+            None,
+            currency_tag(currency),
+        )?;
+        self.set_held_in(currency, held);
+        Ok(tr_tr.transaction_id())
     }
 
     // restores account from transaction
@@ -167,26 +626,64 @@ impl Account {
         acc_storage: &mut S,
         tr_storage: &T,
     ) -> Result<Account, Error> {
-        let trs = tr_storage.account_transactions(name.clone())?;
-        let mut acc_t = AccountTransfer {
-            name: name.clone(),
-            balance: 0,
-        };
+        let (balances, held) = Account::replayed_balances(name.clone(), tr_storage)?;
+        Account::store_restored_balance(name, balances, held, acc_storage)
+    }
 
+    // balances and held amounts (both per currency) of the account computed from its
+    // transaction log
+    // errors: AccountNotExists (no log for the account), Storage (incl. negative balance)
+    pub fn replayed_balances<T: TransactionStorage>(
+        name: String,
+        tr_storage: &T,
+    ) -> Result<(CurrencyAmounts, CurrencyAmounts), Error> {
+        let trs = tr_storage.account_transactions(name)?;
+        let mut replayed: HashMap<String, i128> = HashMap::new();
+        let mut replayed_held: HashMap<String, i128> = HashMap::new();
         for tr in trs {
-            match tr.action {
-                TransactionAction::Registration => (),
-                TransactionAction::Increment(amount) => acc_t.balance += amount,
-                TransactionAction::Decrement(amount) => acc_t.balance -= amount,
-            }
+            *replayed.entry(tr.currency().to_string()).or_default() += tr.balance_delta();
+            *replayed_held.entry(tr.currency().to_string()).or_default() += tr.action.held_delta();
+        }
+        let negative = |_| Error::Storage("negative balance in log".to_string());
+        let mut balances = HashMap::new();
+        for (currency, balance) in replayed {
+            set_currency_balance(
+                &mut balances,
+                &currency,
+                usize::try_from(balance).map_err(negative)?,
+            );
+        }
+        let mut held = HashMap::new();
+        for (currency, amount) in replayed_held {
+            set_currency_balance(
+                &mut held,
+                &currency,
+                usize::try_from(amount).map_err(negative)?,
+            );
         }
+        Ok((balances, held))
+    }
+
+    // sets the balances and held amounts of the stored account (created if missing)
+    // errors: Storage
+    pub fn store_restored_balance<S: AccountStorage>(
+        name: String,
+        balances: HashMap<String, usize>,
+        held: HashMap<String, usize>,
+        acc_storage: &mut S,
+    ) -> Result<Account, Error> {
+        // settings are not in the log, keep the stored ones
+        let mut acc_t = match acc_storage.get_account(name.clone()) {
+            Ok(acc_t) => acc_t,
+            Err(StorageError::AccountNotExists) => AccountTransfer::new(name, None),
+            Err(err) => return Err(Error::from(err)),
+        };
+        acc_t.balances = balances;
+        acc_t.held = held;
 
         // try update account or recreate wit new data
         match acc_storage.update_account(acc_t.clone()) {
-            Ok(acc) => Ok(Account {
-                name: acc.name.clone(),
-                balance: acc.balance,
-            }),
+            Ok(acc) => Ok(Account::from(acc)),
             Err(StorageError::AccountNotExists) => {
                 let acc_t = acc_storage.create_account(acc_t)?;
                 Ok(Account::from(acc_t))
@@ -195,16 +692,140 @@ impl Account {
         }
     }
 
-    // get transfer data
-    fn transfer_data(&self) -> AccountTransfer {
-        AccountTransfer {
-            name: self.name.clone(),
-            balance: self.balance,
+    // checks value and the fee can be sent to `to` as both handles are now,
+    // returns the amount to debit from the sender
+    // errors: EmptyTransaction, NotEnoughMoney, BelowMinimumBalance, AccountFrozen,
+    // TransferLimitExceeded, Storage (same account, balance overflow)
+    pub fn check_transfer(&self, value: usize, to: &Account, fee: usize) -> Result<usize, Error> {
+        self.check_transfer_in(value, DEFAULT_CURRENCY, to, fee)
+    }
+
+    // errors: see check_transfer
+    fn check_transfer_in(
+        &self,
+        value: usize,
+        currency: &str,
+        to: &Account,
+        fee: usize,
+    ) -> Result<usize, Error> {
+        if value == 0 {
+            return Err(Error::EmptyTransaction);
+        }
+        if to.name == self.name {
+            return Err(Error::Storage("transfer to the same account".to_string()));
+        }
+        let debit = value.checked_add(fee).ok_or(Error::NotEnoughMoney)?;
+        self.check_withdrawal_in(debit, currency)?;
+        if currency == DEFAULT_CURRENCY {
+            self.check_transfer_limit(value)?;
+        }
+        to.check_not_frozen()?;
+        checked_credit(to.balance_in(currency), value)?;
+        Ok(debit)
+    }
+
+    // errors: AccountFrozen
+    fn check_not_frozen(&self) -> Result<(), Error> {
+        if self.frozen {
+            Err(Error::AccountFrozen)
+        } else {
+            Ok(())
+        }
+    }
+
+    // errors: TransferLimitExceeded
+    fn check_transfer_limit(&self, value: usize) -> Result<(), Error> {
+        match self.transfer_limit {
+            Some(limit) if self.transferred.saturating_add(value) > limit => {
+                Err(Error::TransferLimitExceeded)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // checks value can be taken from the account in `currency`
+    // errors: AccountFrozen, NotEnoughMoney, BelowMinimumBalance
+    fn check_withdrawal_in(&self, value: usize, currency: &str) -> Result<(), Error> {
+        self.check_not_frozen()?;
+        let available = self.available_balance_in(currency);
+        let min_balance = if currency == DEFAULT_CURRENCY {
+            self.min_balance
+        } else {
+            0
+        };
+        if value > available {
+            Err(Error::NotEnoughMoney)
+        } else if available - value < min_balance {
+            Err(Error::BelowMinimumBalance)
+        } else {
+            Ok(())
         }
     }
 
+    // stored record of the account to be updated, the handle takes the stored balance
+    // and flags so a stale handle can't undo changes made through the bank
+    // errors: AccountNotExists, Storage
+    fn load<S: AccountStorage>(&mut self, acc_storage: &S) -> Result<AccountTransfer, Error> {
+        let stored = acc_storage.get_account(self.name.clone())?;
+        self.balances = stored.balances.clone();
+        self.frozen = stored.frozen;
+        self.held = stored.held.clone();
+        self.min_balance = stored.min_balance;
+        self.transfer_limit = stored.transfer_limit;
+        self.transferred = stored.transferred;
+        Ok(stored)
+    }
+
+    // default currency balance that can be spent (not reserved by holds)
+    pub fn available_balance(&self) -> usize {
+        self.available_balance_in(DEFAULT_CURRENCY)
+    }
+
+    pub fn available_balance_in(&self, currency: &str) -> usize {
+        self.balance_in(currency)
+            .saturating_sub(self.held_in(currency))
+    }
+
+    // held amount in the default currency
+    pub fn held(&self) -> usize {
+        self.held_in(DEFAULT_CURRENCY)
+    }
+
+    pub fn held_in(&self, currency: &str) -> usize {
+        self.held.get(currency).copied().unwrap_or_default()
+    }
+
+    fn set_held_in(&mut self, currency: &str, value: usize) {
+        set_currency_balance(&mut self.held, currency, value)
+    }
+
     // task 10 get
+    // balance in the default currency
     pub fn balance(&self) -> usize {
-        self.balance
+        self.balance_in(DEFAULT_CURRENCY)
+    }
+
+    pub fn balance_in(&self, currency: &str) -> usize {
+        self.balances.get(currency).copied().unwrap_or_default()
     }
+
+    fn set_balance_in(&mut self, currency: &str, value: usize) {
+        set_currency_balance(&mut self.balances, currency, value)
+    }
+
+    // balances in other than the default currency sorted by currency
+    fn other_balances(&self) -> Vec<(&str, usize)> {
+        other_amounts(&self.balances)
+    }
+}
+
+// amounts in other than the default currency sorted by currency
+fn other_amounts(amounts: &HashMap<String, usize>) -> Vec<(&str, usize)> {
+    let mut amounts = amounts
+        .iter()
+        .filter(|(currency, _)| *currency != DEFAULT_CURRENCY)
+        .map(|(currency, amount)| (currency.as_str(), *amount))
+        .collect::<Vec<_>>();
+    amounts.sort();
+    amounts
 }
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+// computes the fee charged for a transfer of `amount`
+pub trait FeePolicy: Send + Sync {
+    fn fee(&self, amount: usize) -> usize;
+}
+
+// the same fee for every transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatFee(pub usize);
+
+// fee as a share of the amount in basis points (1 bps = 0.01%), rounded down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PercentFee {
+    pub bps: u32,
+}
+
+// fees charged for transfers in total and per paying account
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FeeLedger {
+    pub total: usize,
+    pub by_account: BTreeMap<String, usize>,
+}
+
+impl FeeLedger {
+    // adds a fee paid by `account_name`, returns false (ledger unchanged) on overflow
+    pub fn record(&mut self, account_name: &str, fee: usize) -> bool {
+        let Some(total) = self.total.checked_add(fee) else {
+            return false;
+        };
+        let paid = self
+            .by_account
+            .get(account_name)
+            .copied()
+            .unwrap_or_default();
+        let Some(paid) = paid.checked_add(fee) else {
+            return false;
+        };
+        self.total = total;
+        self.by_account.insert(account_name.to_string(), paid);
+        true
+    }
+}
+
+impl FeePolicy for FlatFee {
+    fn fee(&self, _amount: usize) -> usize {
+        self.0
+    }
+}
+
+impl FeePolicy for PercentFee {
+    fn fee(&self, amount: usize) -> usize {
+        // above 100% the fee can exceed usize, it saturates (and the transfer is refused)
+        usize::try_from(amount as u128 * self.bps as u128 / 10000).unwrap_or(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_fee() {
+        assert_eq!(FlatFee(0).fee(100), 0);
+        assert_eq!(FlatFee(3).fee(1), 3);
+        assert_eq!(FlatFee(3).fee(1_000_000), 3);
+    }
+
+    #[test]
+    fn test_fee_ledger() {
+        let mut ledger = FeeLedger::default();
+        assert!(ledger.record("a", 2));
+        assert!(ledger.record("b", 3));
+        assert!(ledger.record("a", 1));
+        assert_eq!(ledger.total, 6);
+        assert_eq!(
+            ledger.by_account,
+            BTreeMap::from([("a".to_string(), 3), ("b".to_string(), 3)])
+        );
+
+        // overflow leaves the ledger unchanged
+        assert!(!ledger.record("c", usize::MAX));
+        assert_eq!(ledger.total, 6);
+        assert!(!ledger.by_account.contains_key("c"));
+    }
+
+    #[test]
+    fn test_percent_fee() {
+        let policy = PercentFee { bps: 150 };
+        assert_eq!(policy.fee(1000), 15);
+        assert_eq!(policy.fee(10), 0);
+        // no overflow on large amounts
+        assert_eq!(PercentFee { bps: 10000 }.fee(usize::MAX), usize::MAX);
+        assert_eq!(PercentFee { bps: 10000 }.fee(42), 42);
+        assert_eq!(PercentFee { bps: 20000 }.fee(usize::MAX), usize::MAX);
+        assert_eq!(PercentFee { bps: 20000 }.fee(42), 84);
+    }
+}
@@ -1 +1,5 @@
+pub mod file;
 pub mod memory;
+pub mod replay;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,545 @@
+use crate::bank::implements::memory::storage::{MemAccountStorage, MemTransactionStorage};
+use crate::bank::storage::{
+    AccountStorage, AccountTransfer, Error, TransactionAction, TransactionId, TransactionIdMode,
+    TransactionStorage, TransactionTransfer, FEE_ACCOUNT_NAME,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// json file guarded by an OS lock on a lock file; written atomically (temp file + rename)
+struct StoreFile {
+    path: PathBuf,
+    // locked while the storage is open; the OS releases the lock when the file is
+    // closed or the process dies, so a crash doesn't leave the store locked
+    _lock: File,
+}
+
+// account storage persisted to a json file on each mutation
+pub struct FileAccountStorage {
+    inner: MemAccountStorage,
+    file: StoreFile,
+}
+
+// transaction storage persisted to a json file on each mutation
+pub struct FileTransactionStorage {
+    inner: MemTransactionStorage,
+    file: StoreFile,
+}
+
+fn storage_error<E: ToString>(err: E) -> Error {
+    Error::StorageError(err.to_string())
+}
+
+fn with_extension(path: &Path, ext: &str) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(ext);
+    PathBuf::from(p)
+}
+
+impl StoreFile {
+    // takes the lock for the path (the lock file itself is left in place, it is
+    // the lock that guards the store)
+    // Errors: StorageError (already opened by another storage)
+    fn open(path: PathBuf) -> Result<Self, Error> {
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(with_extension(&path, ".lock"))
+            .map_err(storage_error)?;
+        match lock.try_lock() {
+            Ok(()) => Ok(StoreFile { path, _lock: lock }),
+            Err(TryLockError::WouldBlock) => Err(Error::StorageError(format!(
+                "storage {} is already opened",
+                path.display()
+            ))),
+            Err(TryLockError::Error(err)) => Err(storage_error(err)),
+        }
+    }
+
+    // reads stored data (None if the file is missing or empty)
+    fn load<T: DeserializeOwned>(&self) -> Result<Option<T>, Error> {
+        match fs::read(&self.path) {
+            Ok(data) if data.is_empty() => Ok(None),
+            Ok(data) => Ok(Some(serde_json::from_slice(&data).map_err(storage_error)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(storage_error(err)),
+        }
+    }
+
+    // writes to a temp file first so a crash mid-write keeps the old data
+    fn save<T: Serialize>(&self, value: &T) -> Result<(), Error> {
+        let tmp_path = with_extension(&self.path, ".tmp");
+        let data = serde_json::to_vec(value).map_err(storage_error)?;
+        let mut tmp = File::create(&tmp_path).map_err(storage_error)?;
+        tmp.write_all(&data).map_err(storage_error)?;
+        tmp.sync_all().map_err(storage_error)?;
+        fs::rename(&tmp_path, &self.path).map_err(storage_error)
+    }
+}
+
+impl FileAccountStorage {
+    // opens (or creates) a storage at path, creates the fee account if the file is empty
+    // Errors: StorageError
+    pub fn new(path: PathBuf) -> Result<Self, Error> {
+        Self::with_fee_account_name(path, FEE_ACCOUNT_NAME.to_string())
+    }
+
+    // same as new, the fee account is stored under `fee_acc_name` (a store has to be
+    // reopened with the name it was created with)
+    // Errors: StorageError
+    pub fn with_fee_account_name(path: PathBuf, fee_acc_name: String) -> Result<Self, Error> {
+        let file = StoreFile::open(path)?;
+        let mut inner = MemAccountStorage::with_fee_account_name(fee_acc_name)?;
+        let accounts: Option<Vec<AccountTransfer>> = file.load()?;
+        let s = match accounts {
+            Some(accounts) => {
+                for acc in accounts {
+                    if let Err(Error::AccountNotExists) = inner.update_account(acc.clone()) {
+                        inner.create_account(acc)?;
+                    }
+                }
+                FileAccountStorage { inner, file }
+            }
+            None => {
+                let s = FileAccountStorage { inner, file };
+                s.persist()?;
+                s
+            }
+        };
+        Ok(s)
+    }
+
+    fn persist(&self) -> Result<(), Error> {
+        let mut accounts = self.inner.accounts()?;
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+        self.file.save(&accounts)
+    }
+}
+
+impl FileTransactionStorage {
+    // opens (or creates) a storage at path
+    // Errors: StorageError
+    pub fn new(path: PathBuf) -> Result<Self, Error> {
+        Self::with_id_mode(path, TransactionIdMode::Sequential)
+    }
+
+    // same as new, new transactions are given ids of the mode (stored transactions
+    // keep theirs)
+    // Errors: StorageError
+    pub fn with_id_mode(path: PathBuf, id_mode: TransactionIdMode) -> Result<Self, Error> {
+        let file = StoreFile::open(path)?;
+        let mut inner = MemTransactionStorage::with_id_mode(id_mode);
+        let transactions: Option<Vec<TransactionTransfer>> = file.load()?;
+        let mut transactions = transactions.unwrap_or_default();
+        transactions.sort_by_key(|x| x.id);
+        for tr in transactions {
+            inner.restore_transaction(tr)?;
+        }
+        Ok(FileTransactionStorage { inner, file })
+    }
+
+    fn persist(&self) -> Result<(), Error> {
+        let mut transactions = self.inner.transactions()?;
+        transactions.sort_by_key(|x| x.id);
+        self.file.save(&transactions)
+    }
+}
+
+impl AccountStorage for FileAccountStorage {
+    fn create_account(&mut self, raw_data: AccountTransfer) -> Result<AccountTransfer, Error> {
+        let acc = self.inner.create_account(raw_data)?;
+        self.persist()?;
+        Ok(acc)
+    }
+
+    fn get_account(&self, name: String) -> Result<AccountTransfer, Error> {
+        self.inner.get_account(name)
+    }
+
+    fn update_account(&mut self, transfer_data: AccountTransfer) -> Result<AccountTransfer, Error> {
+        let acc = self.inner.update_account(transfer_data)?;
+        self.persist()?;
+        Ok(acc)
+    }
+
+    fn fee_account(&self) -> Result<AccountTransfer, Error> {
+        self.inner.fee_account()
+    }
+
+    fn accounts(&self) -> Result<Vec<AccountTransfer>, Error> {
+        self.inner.accounts()
+    }
+
+    fn for_each_account(
+        &self,
+        f: &mut dyn FnMut(&AccountTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.inner.for_each_account(f)
+    }
+
+    fn count(&self) -> Result<usize, Error> {
+        self.inner.count()
+    }
+
+    fn rename_account(&mut self, old: String, new: String) -> Result<AccountTransfer, Error> {
+        let acc = self.inner.rename_account(old, new)?;
+        self.persist()?;
+        Ok(acc)
+    }
+
+    fn delete_account(&mut self, name: String) -> Result<AccountTransfer, Error> {
+        let acc = self.inner.delete_account(name)?;
+        self.persist()?;
+        Ok(acc)
+    }
+}
+
+impl TransactionStorage for FileTransactionStorage {
+    fn create_transaction_in(
+        &mut self,
+        account_name: String,
+        action: TransactionAction,
+        memo: Option<String>,
+        currency: Option<String>,
+    ) -> Result<TransactionTransfer, Error> {
+        let tr = self
+            .inner
+            .create_transaction_in(account_name, action, memo, currency)?;
+        self.persist()?;
+        Ok(tr)
+    }
+
+    fn transactions(&self) -> Result<Vec<TransactionTransfer>, Error> {
+        self.inner.transactions()
+    }
+
+    fn transactions_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.inner.transactions_paged(offset, limit)
+    }
+
+    fn transactions_between(
+        &self,
+        from_id: usize,
+        to_id: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.inner.transactions_between(from_id, to_id)
+    }
+
+    fn account_transactions(
+        &self,
+        account_name: String,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.inner.account_transactions(account_name)
+    }
+
+    fn for_each_account_transaction(
+        &self,
+        account_name: String,
+        f: &mut dyn FnMut(TransactionTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.inner.for_each_account_transaction(account_name, f)
+    }
+
+    fn transaction_by_id(
+        &self,
+        id: impl Into<TransactionId>,
+    ) -> Result<TransactionTransfer, Error> {
+        self.inner.transaction_by_id(id)
+    }
+
+    fn first_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        self.inner.first_transaction()
+    }
+
+    fn last_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        self.inner.last_transaction()
+    }
+
+    fn transaction_count(&self) -> Result<usize, Error> {
+        self.inner.transaction_count()
+    }
+
+    fn account_transaction_count(&self, account_name: String) -> Result<usize, Error> {
+        self.inner.account_transaction_count(account_name)
+    }
+
+    fn restore_transaction(&mut self, tr: TransactionTransfer) -> Result<(), Error> {
+        self.inner.restore_transaction(tr)?;
+        self.persist()
+    }
+
+    fn rename_account_references(&mut self, old: String, new: String) -> Result<(), Error> {
+        self.inner.rename_account_references(old, new)?;
+        self.persist()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::account::Account;
+    use crate::bank::storage::Error as StorageError;
+
+    use super::*;
+
+    // unique path in the temp dir, cleaned up on drop
+    struct TestPath(PathBuf);
+
+    impl TestPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "rust_bank_{}_{}.json",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(with_extension(&path, ".lock"));
+            TestPath(path)
+        }
+    }
+
+    impl Drop for TestPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(with_extension(&self.0, ".lock"));
+        }
+    }
+
+    fn exists(path: &Path, ext: &str) -> bool {
+        with_extension(path, ext).exists()
+    }
+
+    #[test]
+    fn test_file_storage_accounts_round_trip() {
+        let path = TestPath::new("accounts_round_trip");
+        {
+            let mut storage = FileAccountStorage::new(path.0.clone()).unwrap();
+            assert_eq!(storage.fee_account().unwrap().balance(), 0);
+            storage
+                .create_account(AccountTransfer::new("test_1".to_owned(), None))
+                .unwrap();
+            storage
+                .create_account(AccountTransfer::new("test_2".to_owned(), Some(5)))
+                .unwrap();
+            storage
+                .update_account(AccountTransfer::new("test_1".to_owned(), Some(42)))
+                .unwrap();
+            let mut fee_acc = storage.fee_account().unwrap();
+            fee_acc.set_balance(3);
+            storage.update_account(fee_acc).unwrap();
+        }
+
+        let storage = FileAccountStorage::new(path.0.clone()).unwrap();
+        assert_eq!(storage.accounts().unwrap().len(), 3);
+        assert_eq!(
+            storage.get_account("test_1".to_owned()).unwrap().balance(),
+            42
+        );
+        assert_eq!(
+            storage.get_account("test_2".to_owned()).unwrap().balance(),
+            5
+        );
+        assert_eq!(storage.fee_account().unwrap().balance(), 3);
+        assert!(!exists(&path.0, ".tmp"));
+    }
+
+    #[test]
+    fn test_file_storage_custom_fee_account_name() {
+        let path = TestPath::new("custom_fee_account_name");
+        {
+            let mut storage =
+                FileAccountStorage::with_fee_account_name(path.0.clone(), "bank_fees".to_owned())
+                    .unwrap();
+            let mut fee_acc = storage.fee_account().unwrap();
+            assert_eq!(fee_acc.name, "bank_fees");
+            fee_acc.set_balance(3);
+            storage.update_account(fee_acc).unwrap();
+        }
+
+        let storage =
+            FileAccountStorage::with_fee_account_name(path.0.clone(), "bank_fees".to_owned())
+                .unwrap();
+        assert_eq!(storage.accounts().unwrap().len(), 1);
+        assert_eq!(storage.fee_account().unwrap().balance(), 3);
+        assert_eq!(
+            storage
+                .get_account(FEE_ACCOUNT_NAME.to_owned())
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_file_storage_transactions_round_trip() {
+        let path = TestPath::new("transactions_round_trip");
+        {
+            let mut storage = FileTransactionStorage::new(path.0.clone()).unwrap();
+            storage
+                .create_transaction("test_1".to_owned(), TransactionAction::Registration)
+                .unwrap();
+            storage
+                .create_transaction("test_2".to_owned(), TransactionAction::Registration)
+                .unwrap();
+            storage
+                .create_transaction("test_1".to_owned(), TransactionAction::Increment(10))
+                .unwrap();
+        }
+
+        let mut storage = FileTransactionStorage::new(path.0.clone()).unwrap();
+        assert_eq!(storage.transactions().unwrap().len(), 3);
+        let tr = storage.transaction_by_id(3).unwrap();
+        assert_eq!(tr.account_name, "test_1");
+        assert_eq!(tr.action, TransactionAction::Increment(10));
+        assert_eq!(
+            storage
+                .account_transactions("test_1".to_owned())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // ids continue after reopen
+        let tr = storage
+            .create_transaction("test_2".to_owned(), TransactionAction::Increment(1))
+            .unwrap();
+        assert_eq!(tr.id, 4);
+    }
+
+    #[test]
+    fn test_file_storage_uuid_id_mode() {
+        let path = TestPath::new("uuid_id_mode");
+        let tr = {
+            let mut storage =
+                FileTransactionStorage::with_id_mode(path.0.clone(), TransactionIdMode::Uuid)
+                    .unwrap();
+            storage
+                .create_transaction("test_1".to_owned(), TransactionAction::Registration)
+                .unwrap()
+        };
+        let uuid = tr.uuid.unwrap();
+
+        // the uuids are persisted, and still resolve in the sequential mode
+        let mut storage = FileTransactionStorage::new(path.0.clone()).unwrap();
+        assert_eq!(storage.transaction_by_id(uuid).unwrap().id, tr.id);
+        let tr = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Increment(1))
+            .unwrap();
+        assert_eq!(tr.transaction_id(), TransactionId::Seq(2));
+    }
+
+    #[test]
+    fn test_file_storage_account_balances_persist() {
+        let acc_path = TestPath::new("bank_accounts");
+        let tr_path = TestPath::new("bank_transactions");
+        {
+            let mut acc_storage = FileAccountStorage::new(acc_path.0.clone()).unwrap();
+            let mut tr_storage = FileTransactionStorage::new(tr_path.0.clone()).unwrap();
+            let mut acc =
+                Account::new("test".to_owned(), &mut acc_storage, &mut tr_storage).unwrap();
+            acc.inc_balance(100, &mut acc_storage, &mut tr_storage)
+                .unwrap();
+            acc.decr_balance(30, &mut acc_storage, &mut tr_storage)
+                .unwrap();
+        }
+
+        let acc_storage = FileAccountStorage::new(acc_path.0.clone()).unwrap();
+        let tr_storage = FileTransactionStorage::new(tr_path.0.clone()).unwrap();
+        assert_eq!(
+            acc_storage
+                .get_account("test".to_owned())
+                .unwrap()
+                .balance(),
+            70
+        );
+        assert_eq!(
+            tr_storage
+                .account_transactions("test".to_owned())
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_file_storage_currency_balances() {
+        let path = TestPath::new("currency_balances");
+        {
+            let mut storage = FileAccountStorage::new(path.0.clone()).unwrap();
+            let mut acc = AccountTransfer::new("test".to_owned(), Some(5));
+            acc.set_balance_in("EUR", 7);
+            storage.create_account(acc).unwrap();
+        }
+
+        let storage = FileAccountStorage::new(path.0.clone()).unwrap();
+        let acc = storage.get_account("test".to_owned()).unwrap();
+        assert_eq!(acc.balance(), 5);
+        assert_eq!(acc.balance_in("EUR"), 7);
+    }
+
+    #[test]
+    fn test_file_storage_single_balance_file() {
+        // written before balances were kept per currency
+        let path = TestPath::new("single_balance");
+        fs::write(
+            &path.0,
+            br#"[{"name":"fee_acc","balance":2},{"name":"test","balance":40,"held":5}]"#,
+        )
+        .unwrap();
+
+        let storage = FileAccountStorage::new(path.0.clone()).unwrap();
+        let acc = storage.get_account("test".to_owned()).unwrap();
+        assert_eq!(acc.balance(), 40);
+        assert_eq!(acc.balances.len(), 1);
+        assert_eq!(acc.held(), 5);
+        assert_eq!(storage.fee_account().unwrap().balance(), 2);
+    }
+
+    #[test]
+    fn test_file_storage_lock() {
+        let path = TestPath::new("lock");
+        let storage = FileAccountStorage::new(path.0.clone()).unwrap();
+        assert!(exists(&path.0, ".lock"));
+
+        // second open of the same file is rejected
+        let res = FileAccountStorage::new(path.0.clone());
+        assert!(matches!(res, Err(StorageError::StorageError(_))));
+
+        // lock is released on drop
+        drop(storage);
+        assert!(FileAccountStorage::new(path.0.clone()).is_ok());
+    }
+
+    #[test]
+    fn test_file_storage_reopen_after_crash() {
+        let path = TestPath::new("crash");
+        {
+            let mut storage = FileAccountStorage::new(path.0.clone()).unwrap();
+            storage
+                .create_account(AccountTransfer::new("test".to_owned(), Some(7)))
+                .unwrap();
+        }
+        // the lock file is left behind as after a crash, only the lock is gone
+        assert!(exists(&path.0, ".lock"));
+        let storage = FileAccountStorage::new(path.0.clone()).unwrap();
+        assert_eq!(storage.get_account("test".to_owned()).unwrap().balance(), 7);
+    }
+
+    #[test]
+    fn test_file_storage_corrupted_file() {
+        let path = TestPath::new("corrupted");
+        fs::write(&path.0, b"{not json").unwrap();
+        let res = FileAccountStorage::new(path.0.clone());
+        assert!(matches!(res, Err(StorageError::StorageError(_))));
+
+        // a failed open doesn't keep the store locked
+        fs::write(&path.0, b"").unwrap();
+        assert!(FileAccountStorage::new(path.0.clone()).is_ok());
+    }
+}
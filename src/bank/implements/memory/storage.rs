@@ -1,8 +1,10 @@
 use crate::bank::storage::{
-    AccountStorage, AccountTransfer, Error, TransactionAction, TransactionStorage,
-    TransactionTransfer,
+    AccountStorage, AccountTransfer, Error, TransactionAction, TransactionId, TransactionIdMode,
+    TransactionStorage, TransactionTransfer, FEE_ACCOUNT_NAME,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
 pub struct MemAccountStorage {
     storage: HashMap<String, AccountTransfer>,
@@ -10,40 +12,101 @@ pub struct MemAccountStorage {
     fee_acc_name: String,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct MemTransactionStorageItem {
     pub id: usize,
     pub action: TransactionAction,
+    pub created_at: u64,
+    pub memo: Option<String>,
+    pub uuid: Option<Uuid>,
+    pub currency: Option<String>,
 }
 
 pub struct MemTransactionStorage {
     storage: HashMap<String, Vec<MemTransactionStorageItem>>,
+    // transaction id -> account name (account transactions are ordered by id)
+    index: BTreeMap<usize, String>,
+    // uuid -> transaction id
+    uuid_index: HashMap<Uuid, usize>,
     last_tr_id: usize,
+    // clock used to stamp new transactions (unix millis)
+    now: fn() -> u64,
+    id_mode: TransactionIdMode,
 }
 
 impl MemAccountStorage {
     pub fn new() -> Result<Self, Error> {
-        let fee_acc_name = "fee_acc".to_string();
+        Self::with_fee_account_name(FEE_ACCOUNT_NAME.to_string())
+    }
+
+    // creates a storage whose fee account is stored under `fee_acc_name`
+    pub fn with_fee_account_name(fee_acc_name: String) -> Result<Self, Error> {
         let mut s = MemAccountStorage {
             storage: Default::default(),
             fee_acc_name: fee_acc_name.clone(),
         };
 
-        let _ = s.create_account(AccountTransfer {
-            name: fee_acc_name,
-            balance: 0,
-        })?;
+        let _ = s.create_account(AccountTransfer::new(fee_acc_name, None))?;
         Ok(s)
     }
+
+    pub fn fee_account_name(&self) -> &str {
+        &self.fee_acc_name
+    }
 }
 
 impl MemTransactionStorage {
     pub fn new() -> Self {
+        Self::with_clock(system_now)
+    }
+
+    // creates a storage with a custom clock (useful for deterministic tests)
+    pub fn with_clock(now: fn() -> u64) -> Self {
+        Self::with_clock_and_id_mode(now, TransactionIdMode::Sequential)
+    }
+
+    // creates a storage giving new transactions ids of the mode
+    pub fn with_id_mode(id_mode: TransactionIdMode) -> Self {
+        Self::with_clock_and_id_mode(system_now, id_mode)
+    }
+
+    pub fn with_clock_and_id_mode(now: fn() -> u64, id_mode: TransactionIdMode) -> Self {
         MemTransactionStorage {
             storage: Default::default(),
+            index: Default::default(),
+            uuid_index: Default::default(),
             last_tr_id: 0,
+            now,
+            id_mode,
         }
     }
+
+    pub fn id_mode(&self) -> TransactionIdMode {
+        self.id_mode
+    }
+
+    // O(log(n)); n - number of transactions
+    fn transaction_by_seq(&self, id: usize) -> Result<TransactionTransfer, Error> {
+        let account_name = self.index.get(&id).ok_or(Error::TransactionNotExists)?;
+        let trs = self
+            .storage
+            .get(account_name)
+            .ok_or(Error::TransactionNotExists)?;
+        let pos = trs
+            .binary_search_by_key(&id, |x| x.id)
+            .map_err(|_| Error::TransactionNotExists)?;
+        let mut tr = TransactionTransfer::from(trs[pos].clone());
+        tr.account_name = account_name.clone();
+        Ok(tr)
+    }
+}
+
+// current unix time in millis
+pub(crate) fn system_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
 }
 
 impl Default for MemTransactionStorage {
@@ -58,6 +121,10 @@ impl From<MemTransactionStorageItem> for TransactionTransfer {
             id: value.id,
             action: value.action,
             account_name: String::new(),
+            created_at: value.created_at,
+            memo: value.memo,
+            uuid: value.uuid,
+            currency: value.currency,
         }
     }
 }
@@ -102,19 +169,58 @@ impl AccountStorage for MemAccountStorage {
     fn accounts(&self) -> Result<Vec<AccountTransfer>, Error> {
         Ok(self.storage.values().cloned().collect())
     }
+
+    fn for_each_account(
+        &self,
+        f: &mut dyn FnMut(&AccountTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.storage.values().try_for_each(f)
+    }
+
+    // O(1)
+    fn count(&self) -> Result<usize, Error> {
+        Ok(self.storage.len())
+    }
+
+    fn rename_account(&mut self, old: String, new: String) -> Result<AccountTransfer, Error> {
+        if self.storage.contains_key(&new) {
+            return Err(Error::AccountAlreadyExists);
+        }
+        let mut acc = self.storage.remove(&old).ok_or(Error::AccountNotExists)?;
+        acc.name = new.clone();
+        self.storage.insert(new, acc.clone());
+        Ok(acc)
+    }
+
+    fn delete_account(&mut self, name: String) -> Result<AccountTransfer, Error> {
+        self.storage.remove(&name).ok_or(Error::AccountNotExists)
+    }
 }
 
 impl TransactionStorage for MemTransactionStorage {
-    fn create_transaction(
+    fn create_transaction_in(
         &mut self,
         account_name: String,
         action: TransactionAction,
+        memo: Option<String>,
+        currency: Option<String>,
     ) -> Result<TransactionTransfer, Error> {
         self.last_tr_id += 1;
+        let uuid = self.id_mode.new_uuid();
         let item = MemTransactionStorageItem {
             id: self.last_tr_id,
             action,
+            created_at: (self.now)(),
+            memo,
+            uuid,
+            currency,
         };
+        let mut tr = TransactionTransfer::from(item.clone());
+        tr.account_name = account_name.clone();
+        self.index.insert(item.id, account_name.clone());
+        if let Some(uuid) = uuid {
+            self.uuid_index.insert(uuid, item.id);
+        }
         match self.storage.entry(account_name.clone()) {
             std::collections::hash_map::Entry::Occupied(mut occupied_entry) => {
                 occupied_entry.get_mut().push(item);
@@ -123,14 +229,14 @@ impl TransactionStorage for MemTransactionStorage {
                 vacant_entry.insert(vec![item]);
             }
         }
-        Ok(TransactionTransfer::from(item))
+        Ok(tr)
     }
 
     fn transactions(&self) -> Result<Vec<TransactionTransfer>, Error> {
         let mut transactions = Vec::new();
         for (name, trs) in self.storage.iter() {
             for tr in trs.iter() {
-                let mut tt = TransactionTransfer::from(*tr);
+                let mut tt = TransactionTransfer::from(tr.clone());
                 tt.account_name = name.clone();
                 transactions.push(tt);
             }
@@ -138,6 +244,20 @@ impl TransactionStorage for MemTransactionStorage {
         Ok(transactions)
     }
 
+    // O((offset + limit) * log(n)); n - number of transactions
+    fn transactions_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.index
+            .keys()
+            .skip(offset)
+            .take(limit)
+            .map(|id| self.transaction_by_seq(*id))
+            .collect()
+    }
+
     // O(n); n - number of an account transactions
     fn account_transactions(
         &self,
@@ -146,7 +266,7 @@ impl TransactionStorage for MemTransactionStorage {
         let mut transactions = Vec::new();
         if let Some(trs) = self.storage.get(&account_name) {
             for tr in trs.iter() {
-                let mut tt = TransactionTransfer::from(*tr);
+                let mut tt = TransactionTransfer::from(tr.clone());
                 tt.account_name = account_name.clone();
                 transactions.push(tt);
             }
@@ -156,17 +276,130 @@ impl TransactionStorage for MemTransactionStorage {
         }
     }
 
+    fn for_each_account_transaction(
+        &self,
+        account_name: String,
+        f: &mut dyn FnMut(TransactionTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let trs = self
+            .storage
+            .get(&account_name)
+            .ok_or(Error::AccountNotExists)?;
+        for tr in trs.iter() {
+            let mut tt = TransactionTransfer::from(tr.clone());
+            tt.account_name = account_name.clone();
+            f(tt)?;
+        }
+        Ok(())
+    }
+
+    // O(k * log(n)); k - number of transactions in the range
+    fn transactions_between(
+        &self,
+        from_id: usize,
+        to_id: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        if from_id > to_id {
+            return Ok(Vec::new());
+        }
+        self.index
+            .range(from_id..=to_id)
+            .map(|(id, _)| self.transaction_by_seq(*id))
+            .collect()
+    }
+
+    // O(log(n)); n - number of transactions
+    fn transaction_by_id(
+        &self,
+        id: impl Into<TransactionId>,
+    ) -> Result<TransactionTransfer, Error> {
+        match id.into() {
+            TransactionId::Seq(id) => self.transaction_by_seq(id),
+            TransactionId::Uuid(uuid) => {
+                let id = self
+                    .uuid_index
+                    .get(&uuid)
+                    .ok_or(Error::TransactionNotExists)?;
+                self.transaction_by_seq(*id)
+            }
+        }
+    }
+
+    // O(log(n)); n - number of transactions
+    fn first_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        self.index
+            .first_key_value()
+            .map(|(id, _)| self.transaction_by_seq(*id))
+            .transpose()
+    }
+
+    // O(log(n)); n - number of transactions
+    fn last_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        self.index
+            .last_key_value()
+            .map(|(id, _)| self.transaction_by_seq(*id))
+            .transpose()
+    }
+
+    // O(1)
+    fn transaction_count(&self) -> Result<usize, Error> {
+        Ok(self.index.len())
+    }
+
+    // O(1)
+    fn account_transaction_count(&self, account_name: String) -> Result<usize, Error> {
+        Ok(self.storage.get(&account_name).map_or(0, |trs| trs.len()))
+    }
+
+    // errors: StorageError (a transaction with the id or uuid is already stored)
+    fn restore_transaction(&mut self, tr: TransactionTransfer) -> Result<(), Error> {
+        if self.index.contains_key(&tr.id) {
+            return Err(Error::StorageError(format!(
+                "transaction {} already exists",
+                tr.id
+            )));
+        }
+        if let Some(uuid) = tr.uuid.filter(|uuid| self.uuid_index.contains_key(uuid)) {
+            return Err(Error::StorageError(format!(
+                "transaction {uuid} already exists"
+            )));
+        }
+        self.last_tr_id = self.last_tr_id.max(tr.id);
+        self.index.insert(tr.id, tr.account_name.clone());
+        if let Some(uuid) = tr.uuid {
+            self.uuid_index.insert(uuid, tr.id);
+        }
+        let trs = self.storage.entry(tr.account_name).or_default();
+        let item = MemTransactionStorageItem {
+            id: tr.id,
+            action: tr.action,
+            created_at: tr.created_at,
+            memo: tr.memo,
+            uuid: tr.uuid,
+            currency: tr.currency,
+        };
+        // keep account transactions ordered by id
+        let pos = trs.partition_point(|x| x.id < item.id);
+        trs.insert(pos, item);
+        Ok(())
+    }
+
     // O(n); n - number of transactions
-    fn transaction_by_id(&self, id: usize) -> Result<TransactionTransfer, Error> {
-        match self
-            .transactions()?
-            .into_iter()
-            .filter(|x| x.id == id)
-            .last()
-        {
-            Some(tr) => Ok(tr),
-            None => Err(Error::TransactionNotExists),
+    fn rename_account_references(&mut self, old: String, new: String) -> Result<(), Error> {
+        if let Some(mut trs) = self.storage.remove(&old) {
+            for tr in trs.iter() {
+                self.index.insert(tr.id, new.clone());
+            }
+            let target = self.storage.entry(new.clone()).or_default();
+            target.append(&mut trs);
+            target.sort_by_key(|x| x.id);
+        }
+        for trs in self.storage.values_mut() {
+            for tr in trs.iter_mut() {
+                tr.action.rename_counterparty(&old, &new);
+            }
         }
+        Ok(())
     }
 }
 
@@ -184,14 +417,11 @@ mod tests {
         let test_name = "test".to_string();
 
         // test empty get
-        assert_eq!(storage.get_account(test_name.clone()).is_err(), true);
+        assert!(storage.get_account(test_name.clone()).is_err());
 
         // test success insert
-        let raw = AccountTransfer {
-            name: test_name.clone(),
-            balance: 0,
-        };
-        assert_eq!(storage.create_account(raw).is_ok(), true);
+        let raw = AccountTransfer::new(test_name.clone(), None);
+        assert!(storage.create_account(raw).is_ok());
 
         let result = storage.get_account(test_name.clone());
         assert_eq!(
@@ -206,19 +436,13 @@ mod tests {
         let test_name = "test".to_string();
 
         // test add new acc (not existed early)
-        let mut raw = AccountTransfer {
-            name: test_name.clone(),
-            balance: 0,
-        };
-        assert_eq!(storage.create_account(raw).is_ok(), true);
+        let mut raw = AccountTransfer::new(test_name.clone(), None);
+        assert!(storage.create_account(raw).is_ok());
 
         // test create acc with same name
-        raw = AccountTransfer {
-            name: test_name.clone(),
-            balance: 0,
-        };
+        raw = AccountTransfer::new(test_name.clone(), None);
         let result = storage.create_account(raw);
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
         assert_eq!(result.err().unwrap(), StorageError::AccountAlreadyExists);
     }
 
@@ -228,28 +452,19 @@ mod tests {
         let test_name = "test".to_string();
 
         // updates non existed account
-        let raw = AccountTransfer {
-            name: "not_exist".to_string(),
-            balance: 0,
-        };
+        let raw = AccountTransfer::new("not_exist".to_string(), None);
         let result = storage.update_account(raw);
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
         assert_eq!(result.err().unwrap(), StorageError::AccountNotExists);
 
         // test add new acc (not existed early)
-        let raw = AccountTransfer {
-            name: test_name.clone(),
-            balance: 0,
-        };
+        let raw = AccountTransfer::new(test_name.clone(), None);
         let acc = storage.create_account(raw).unwrap();
 
-        let to_update = AccountTransfer {
-            name: acc.name.clone(),
-            balance: 123,
-        };
+        let to_update = AccountTransfer::new(acc.name.clone(), Some(123));
         let res = storage.update_account(to_update);
-        assert_eq!(res.is_ok(), true);
-        assert_eq!(res.unwrap().balance, 123);
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().balance(), 123);
     }
 
     #[test]
@@ -314,6 +529,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_storage_transactions_paged() {
+        let mut storage = MemTransactionStorage::new();
+        for name in ["test_1", "test_2", "test_1", "test_3", "test_2"] {
+            storage
+                .create_transaction(name.to_owned(), TransactionAction::Registration)
+                .unwrap();
+        }
+
+        let page = storage.transactions_paged(0, 2).unwrap();
+        assert_eq!(page.iter().map(|x| x.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        let page = storage.transactions_paged(2, 2).unwrap();
+        assert_eq!(page.iter().map(|x| x.id).collect::<Vec<_>>(), vec![3, 4]);
+
+        // last page is shorter than the limit
+        let page = storage.transactions_paged(4, 2).unwrap();
+        assert_eq!(page.iter().map(|x| x.id).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(page[0].account_name, "test_2");
+
+        // offset past the end
+        assert!(storage.transactions_paged(5, 2).unwrap().is_empty());
+        assert!(storage.transactions_paged(100, 2).unwrap().is_empty());
+
+        // zero limit
+        assert!(storage.transactions_paged(0, 0).unwrap().is_empty());
+
+        // unbounded limit
+        assert_eq!(storage.transactions_paged(3, usize::MAX).unwrap().len(), 2);
+    }
+
     #[test]
     fn test_storage_account_transactions() {
         let mut storage = MemTransactionStorage::new();
@@ -356,6 +602,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_storage_account_transactions_many_accounts() {
+        // 10k transactions across 100 accounts, each account only sees its own
+        let mut storage = MemTransactionStorage::new();
+        for i in 0..10_000 {
+            storage
+                .create_transaction(format!("test_{}", i % 100), TransactionAction::Increment(i))
+                .unwrap();
+        }
+        for acc in [0, 42, 99] {
+            let trs = storage.account_transactions(format!("test_{acc}")).unwrap();
+            assert_eq!(trs.len(), 100);
+            assert!(trs.windows(2).all(|w| w[0].id < w[1].id));
+            assert!(trs.iter().all(|tr| (tr.id - 1) % 100 == acc));
+        }
+    }
+
     #[test]
     fn test_storage_get_transaction_by_id() {
         let mut storage = MemTransactionStorage::new();
@@ -387,7 +650,213 @@ mod tests {
             TransactionAction::Increment(15)
         );
 
-        assert_eq!(storage.transaction_by_id(4).is_err(), true);
+        assert!(storage.transaction_by_id(4).is_err());
+
+        // out of range id maps to the transaction error, not the account one
+        assert_eq!(
+            storage.transaction_by_id(999).err().unwrap(),
+            StorageError::TransactionNotExists
+        );
+        assert_eq!(
+            MemTransactionStorage::new()
+                .transaction_by_id(1)
+                .err()
+                .unwrap(),
+            StorageError::TransactionNotExists
+        );
+    }
+
+    #[test]
+    fn test_storage_get_transaction_by_id_sparse() {
+        // restored ids have gaps and come out of order
+        let mut storage = MemTransactionStorage::new();
+        for (id, name) in [(7, "test_1"), (2, "test_2"), (4, "test_1"), (10, "test_2")] {
+            storage
+                .restore_transaction(TransactionTransfer {
+                    id,
+                    action: TransactionAction::Increment(id),
+                    account_name: name.to_owned(),
+                    created_at: 0,
+                    memo: None,
+                    uuid: None,
+                    currency: None,
+                })
+                .unwrap();
+        }
+        for (id, name) in [(2, "test_2"), (4, "test_1"), (7, "test_1"), (10, "test_2")] {
+            let tr = storage.transaction_by_id(id).unwrap();
+            assert_eq!(tr.account_name, name);
+            assert_eq!(tr.action, TransactionAction::Increment(id));
+        }
+        assert_eq!(
+            storage.transaction_by_id(3).err().unwrap(),
+            StorageError::TransactionNotExists
+        );
+        assert_eq!(
+            storage
+                .create_transaction("test_3".to_owned(), TransactionAction::Registration)
+                .unwrap()
+                .id,
+            11
+        );
+        assert_eq!(
+            storage.transaction_by_id(11).unwrap().account_name,
+            "test_3"
+        );
+
+        // the index follows renamed accounts
+        storage
+            .rename_account_references("test_1".to_owned(), "test_4".to_owned())
+            .unwrap();
+        assert_eq!(storage.transaction_by_id(4).unwrap().account_name, "test_4");
+        assert_eq!(storage.transaction_by_id(7).unwrap().account_name, "test_4");
+    }
+
+    #[test]
+    fn test_storage_restore_duplicate_transaction() {
+        let mut storage = MemTransactionStorage::new();
+        let uuid = Uuid::new_v4();
+        let restored = |id, name: &str| TransactionTransfer {
+            id,
+            action: TransactionAction::Increment(id),
+            account_name: name.to_owned(),
+            created_at: 0,
+            memo: None,
+            uuid: Some(uuid),
+            currency: None,
+        };
+        storage.restore_transaction(restored(3, "test_1")).unwrap();
+
+        // the stored transaction is kept, even under another account
+        assert_eq!(
+            storage
+                .restore_transaction(restored(3, "test_2"))
+                .err()
+                .unwrap(),
+            StorageError::StorageError("transaction 3 already exists".to_owned())
+        );
+        assert_eq!(
+            storage
+                .restore_transaction(restored(4, "test_1"))
+                .err()
+                .unwrap(),
+            StorageError::StorageError(format!("transaction {uuid} already exists"))
+        );
+        assert_eq!(storage.transaction_count().unwrap(), 1);
+        assert_eq!(storage.transaction_by_id(3).unwrap().account_name, "test_1");
+        assert_eq!(storage.transaction_by_id(uuid).unwrap().id, 3);
+        assert!(storage
+            .account_transactions("test_2".to_owned())
+            .unwrap_or_default()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_storage_sequential_id_mode() {
+        let mut storage = MemTransactionStorage::new();
+        assert_eq!(storage.id_mode(), TransactionIdMode::Sequential);
+        let tr = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Registration)
+            .unwrap();
+        assert_eq!(tr.uuid, None);
+        assert_eq!(tr.transaction_id(), TransactionId::Seq(tr.id));
+        assert_eq!(
+            storage
+                .transaction_by_id(TransactionId::Seq(tr.id))
+                .unwrap()
+                .id,
+            tr.id
+        );
+        assert_eq!(
+            storage.transaction_by_id(Uuid::new_v4()).err().unwrap(),
+            StorageError::TransactionNotExists
+        );
+    }
+
+    #[test]
+    fn test_storage_uuid_id_mode() {
+        let mut storage = MemTransactionStorage::with_id_mode(TransactionIdMode::Uuid);
+        assert_eq!(storage.id_mode(), TransactionIdMode::Uuid);
+        let first = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Registration)
+            .unwrap();
+        let second = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Increment(10))
+            .unwrap();
+        let (first_uuid, second_uuid) = (first.uuid.unwrap(), second.uuid.unwrap());
+        assert_ne!(first_uuid, second_uuid);
+        assert_eq!(second.transaction_id(), TransactionId::Uuid(second_uuid));
+
+        assert_eq!(storage.transaction_by_id(first_uuid).unwrap().id, first.id);
+        assert_eq!(
+            storage.transaction_by_id(second_uuid).unwrap().id,
+            second.id
+        );
+        // the sequence number still resolves and keeps ordering
+        assert_eq!(
+            storage.transaction_by_id(second.id).unwrap().uuid,
+            Some(second_uuid)
+        );
+        let paged: Vec<_> = storage
+            .transactions_paged(0, 10)
+            .unwrap()
+            .into_iter()
+            .map(|tr| tr.uuid)
+            .collect();
+        assert_eq!(paged, [Some(first_uuid), Some(second_uuid)]);
+        assert_eq!(
+            storage.first_transaction().unwrap().unwrap().uuid,
+            Some(first_uuid)
+        );
+        assert_eq!(
+            storage.last_transaction().unwrap().unwrap().uuid,
+            Some(second_uuid)
+        );
+        assert_eq!(
+            storage.transaction_by_id(Uuid::new_v4()).err().unwrap(),
+            StorageError::TransactionNotExists
+        );
+
+        // restored transactions keep their uuid
+        let mut restored = MemTransactionStorage::with_id_mode(TransactionIdMode::Uuid);
+        restored.restore_transaction(second.clone()).unwrap();
+        assert_eq!(
+            restored.transaction_by_id(second_uuid).unwrap().id,
+            second.id
+        );
+
+        // the mode combines with a custom clock
+        let mut storage =
+            MemTransactionStorage::with_clock_and_id_mode(|| 42, TransactionIdMode::Uuid);
+        let tr = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Registration)
+            .unwrap();
+        assert_eq!(tr.created_at, 42);
+        assert!(tr.uuid.is_some());
+    }
+
+    #[test]
+    fn test_storage_transaction_created_at() {
+        let mut storage = MemTransactionStorage::with_clock(|| 1_700_000_000_000);
+        let tr = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Registration)
+            .unwrap();
+        assert_eq!(tr.created_at, 1_700_000_000_000);
+        assert_eq!(
+            storage.transaction_by_id(tr.id).unwrap().created_at,
+            1_700_000_000_000
+        );
+        assert_eq!(
+            storage.account_transactions("test_1".to_owned()).unwrap()[0].created_at,
+            1_700_000_000_000
+        );
+
+        // default clock stamps with the current time
+        let mut storage = MemTransactionStorage::new();
+        let tr = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Registration)
+            .unwrap();
+        assert!(tr.created_at > 0);
     }
 
     #[test]
@@ -398,11 +867,11 @@ mod tests {
 
         // test create account with new name
         let mut acc = Account::new(target_name.clone(), &mut acc_storage, &mut tr_storage);
-        assert_eq!(acc.is_ok(), true);
+        assert!(acc.is_ok());
 
         // test error to create acc with same name
         acc = Account::new(target_name.clone(), &mut acc_storage, &mut tr_storage);
-        assert_eq!(acc.is_err(), true);
+        assert!(acc.is_err());
 
         // test transactions
         let trs = tr_storage
@@ -429,7 +898,7 @@ mod tests {
             .unwrap();
         assert_eq!(trs.len(), 2);
         assert_eq!(trs[1].action, TransactionAction::Increment(10));
-        assert_eq!(tr_id, trs[1].id);
+        assert_eq!(tr_id, trs[1].transaction_id());
 
         assert_eq!(
             acc.inc_balance(0, &mut acc_storage, &mut tr_storage)
@@ -457,7 +926,7 @@ mod tests {
             .unwrap();
         assert_eq!(trs.len(), 3);
         assert_eq!(trs[2].action, TransactionAction::Decrement(10));
-        assert_eq!(tr_id, trs[2].id);
+        assert_eq!(tr_id, trs[2].transaction_id());
     }
 
     #[test]
@@ -479,17 +948,38 @@ mod tests {
         assert_eq!(acc_s.balance(), 10);
 
         let tr = tr_storage.transaction_by_id(tr_id).unwrap();
-        assert_eq!(tr.id, tr_id);
-        assert_eq!(tr.action, TransactionAction::Decrement(10));
+        assert_eq!(tr.transaction_id(), tr_id);
+        assert_eq!(
+            tr.action,
+            TransactionAction::TransferOut {
+                to: "person_2".to_owned(),
+                value: 10,
+                fee: 0,
+                memo: None,
+            }
+        );
+
+        // receiver gets a paired incoming transaction
+        let trs = tr_storage
+            .account_transactions("person_2".to_owned())
+            .unwrap();
+        assert_eq!(
+            trs.last().unwrap().action,
+            TransactionAction::TransferIn {
+                from: "person_1".to_owned(),
+                value: 10,
+                memo: None,
+            }
+        );
 
-        assert_eq!(acc_storage.fee_account().unwrap().balance, 0);
+        assert_eq!(acc_storage.fee_account().unwrap().balance(), 0);
 
         // tr with fees
         let _ = acc_f
             .make_transaction(10, &mut acc_s, Some(10), &mut acc_storage, &mut tr_storage)
             .unwrap();
         assert_eq!(acc_f.balance(), 70);
-        assert_eq!(acc_storage.fee_account().unwrap().balance, 10);
+        assert_eq!(acc_storage.fee_account().unwrap().balance(), 10);
     }
 
     #[test]
@@ -503,16 +993,13 @@ mod tests {
         let _ = acc_f.inc_balance(1, &mut acc_storage, &mut tr_storage);
         let _ = acc_f.inc_balance(20, &mut acc_storage, &mut tr_storage);
 
-        let _ = acc_storage.update_account(AccountTransfer {
-            name: "person_1".to_owned(),
-            balance: 0,
-        });
+        let _ = acc_storage.update_account(AccountTransfer::new("person_1".to_owned(), None));
 
         // test account exists
         let res = Account::restore_account_from_transactions(
             acc_name.clone(),
             &mut acc_storage,
-            &mut tr_storage,
+            &tr_storage,
         );
         assert_eq!(res.unwrap().balance(), 26);
 
@@ -520,8 +1007,8 @@ mod tests {
         let res = Account::restore_account_from_transactions(
             "not_exists".to_owned(),
             &mut acc_storage,
-            &mut tr_storage,
+            &tr_storage,
         );
-        assert_eq!(res.is_err(), true);
+        assert!(res.is_err());
     }
 }
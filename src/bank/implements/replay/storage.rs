@@ -0,0 +1,437 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bank::storage::{
+    AccountStorage, AccountTransfer, Error, TransactionAction, TransactionId, TransactionStorage,
+    TransactionTransfer,
+};
+
+// successful mutation of a storage in the order it happened
+#[derive(Debug, Clone)]
+pub enum ReplayOp {
+    CreateAccount(AccountTransfer),
+    UpdateAccount(AccountTransfer),
+    RenameAccount { old: String, new: String },
+    DeleteAccount(String),
+    // transactions are kept with their ids and creation time
+    CreateTransaction(TransactionTransfer),
+    RenameAccountReferences { old: String, new: String },
+}
+
+// storage decorator recording every successful mutation of the inner storage
+// (used to simulate crash recovery by replaying the log into a fresh storage);
+// logs made by `share` append to the same journal, so account and transaction
+// mutations keep their relative order
+pub struct ReplayLog<S> {
+    inner: S,
+    ops: Rc<RefCell<Vec<ReplayOp>>>,
+}
+
+// account and transaction storages used as a single replay target
+pub struct Storages<A, T> {
+    pub accounts: A,
+    pub transactions: T,
+}
+
+impl<S> ReplayLog<S> {
+    pub fn new(inner: S) -> Self {
+        ReplayLog {
+            inner,
+            ops: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    // wraps another storage recording into the journal of this log
+    // (e.g. the transaction storage of a bank whose account storage is logged)
+    pub fn share<U>(&self, inner: U) -> ReplayLog<U> {
+        ReplayLog {
+            inner,
+            ops: Rc::clone(&self.ops),
+        }
+    }
+
+    pub fn ops(&self) -> Vec<ReplayOp> {
+        self.ops.borrow().clone()
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn record<T>(
+        &mut self,
+        res: Result<T, Error>,
+        op: impl FnOnce(&T) -> ReplayOp,
+    ) -> Result<T, Error> {
+        if let Ok(value) = &res {
+            self.ops.borrow_mut().push(op(value));
+        }
+        res
+    }
+
+    // re-applies the journal in order, accounts and transactions together, so the
+    // target never has accounts and a log from different points in time
+    // Errors: any error of the target storage
+    pub fn replay_into(
+        &self,
+        target: &mut (impl AccountStorage + TransactionStorage),
+    ) -> Result<(), Error> {
+        for op in self.ops() {
+            match op {
+                ReplayOp::CreateAccount(acc) => {
+                    target.create_account(acc)?;
+                }
+                ReplayOp::UpdateAccount(acc) => {
+                    target.update_account(acc)?;
+                }
+                ReplayOp::RenameAccount { old, new } => {
+                    target.rename_account(old, new)?;
+                }
+                ReplayOp::DeleteAccount(name) => {
+                    target.delete_account(name)?;
+                }
+                ReplayOp::CreateTransaction(tr) => target.restore_transaction(tr)?,
+                ReplayOp::RenameAccountReferences { old, new } => {
+                    target.rename_account_references(old, new)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: AccountStorage> AccountStorage for ReplayLog<S> {
+    fn create_account(&mut self, raw_data: AccountTransfer) -> Result<AccountTransfer, Error> {
+        let res = self.inner.create_account(raw_data);
+        self.record(res, |acc| ReplayOp::CreateAccount(acc.clone()))
+    }
+
+    fn get_account(&self, name: String) -> Result<AccountTransfer, Error> {
+        self.inner.get_account(name)
+    }
+
+    fn update_account(&mut self, transfer_data: AccountTransfer) -> Result<AccountTransfer, Error> {
+        let res = self.inner.update_account(transfer_data);
+        self.record(res, |acc| ReplayOp::UpdateAccount(acc.clone()))
+    }
+
+    fn fee_account(&self) -> Result<AccountTransfer, Error> {
+        self.inner.fee_account()
+    }
+
+    fn accounts(&self) -> Result<Vec<AccountTransfer>, Error> {
+        self.inner.accounts()
+    }
+
+    fn for_each_account(
+        &self,
+        f: &mut dyn FnMut(&AccountTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.inner.for_each_account(f)
+    }
+
+    fn count(&self) -> Result<usize, Error> {
+        self.inner.count()
+    }
+
+    fn rename_account(&mut self, old: String, new: String) -> Result<AccountTransfer, Error> {
+        let res = self.inner.rename_account(old.clone(), new.clone());
+        self.record(res, |_| ReplayOp::RenameAccount { old, new })
+    }
+
+    fn delete_account(&mut self, name: String) -> Result<AccountTransfer, Error> {
+        let res = self.inner.delete_account(name.clone());
+        self.record(res, |_| ReplayOp::DeleteAccount(name))
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
+
+impl<S: TransactionStorage> TransactionStorage for ReplayLog<S> {
+    fn create_transaction_in(
+        &mut self,
+        account_name: String,
+        action: TransactionAction,
+        memo: Option<String>,
+        currency: Option<String>,
+    ) -> Result<TransactionTransfer, Error> {
+        let res = self
+            .inner
+            .create_transaction_in(account_name, action, memo, currency);
+        self.record(res, |tr| ReplayOp::CreateTransaction(tr.clone()))
+    }
+
+    fn transactions(&self) -> Result<Vec<TransactionTransfer>, Error> {
+        self.inner.transactions()
+    }
+
+    fn transactions_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.inner.transactions_paged(offset, limit)
+    }
+
+    fn transactions_between(
+        &self,
+        from_id: usize,
+        to_id: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.inner.transactions_between(from_id, to_id)
+    }
+
+    fn account_transactions(
+        &self,
+        account_name: String,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.inner.account_transactions(account_name)
+    }
+
+    fn for_each_account_transaction(
+        &self,
+        account_name: String,
+        f: &mut dyn FnMut(TransactionTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.inner.for_each_account_transaction(account_name, f)
+    }
+
+    fn transaction_by_id(
+        &self,
+        id: impl Into<TransactionId>,
+    ) -> Result<TransactionTransfer, Error> {
+        self.inner.transaction_by_id(id)
+    }
+
+    fn first_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        self.inner.first_transaction()
+    }
+
+    fn last_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        self.inner.last_transaction()
+    }
+
+    fn transaction_count(&self) -> Result<usize, Error> {
+        self.inner.transaction_count()
+    }
+
+    fn account_transaction_count(&self, account_name: String) -> Result<usize, Error> {
+        self.inner.account_transaction_count(account_name)
+    }
+
+    fn restore_transaction(&mut self, tr: TransactionTransfer) -> Result<(), Error> {
+        let logged = tr.clone();
+        let res = self.inner.restore_transaction(tr);
+        self.record(res, |_| ReplayOp::CreateTransaction(logged))
+    }
+
+    fn rename_account_references(&mut self, old: String, new: String) -> Result<(), Error> {
+        let res = self
+            .inner
+            .rename_account_references(old.clone(), new.clone());
+        self.record(res, |_| ReplayOp::RenameAccountReferences { old, new })
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
+
+impl<A: AccountStorage, T> AccountStorage for Storages<A, T> {
+    fn create_account(&mut self, raw_data: AccountTransfer) -> Result<AccountTransfer, Error> {
+        self.accounts.create_account(raw_data)
+    }
+
+    fn get_account(&self, name: String) -> Result<AccountTransfer, Error> {
+        self.accounts.get_account(name)
+    }
+
+    fn update_account(&mut self, transfer_data: AccountTransfer) -> Result<AccountTransfer, Error> {
+        self.accounts.update_account(transfer_data)
+    }
+
+    fn fee_account(&self) -> Result<AccountTransfer, Error> {
+        self.accounts.fee_account()
+    }
+
+    fn accounts(&self) -> Result<Vec<AccountTransfer>, Error> {
+        self.accounts.accounts()
+    }
+
+    fn for_each_account(
+        &self,
+        f: &mut dyn FnMut(&AccountTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.accounts.for_each_account(f)
+    }
+
+    fn count(&self) -> Result<usize, Error> {
+        self.accounts.count()
+    }
+
+    fn rename_account(&mut self, old: String, new: String) -> Result<AccountTransfer, Error> {
+        self.accounts.rename_account(old, new)
+    }
+
+    fn delete_account(&mut self, name: String) -> Result<AccountTransfer, Error> {
+        self.accounts.delete_account(name)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.accounts.flush()
+    }
+}
+
+impl<A, T: TransactionStorage> TransactionStorage for Storages<A, T> {
+    fn create_transaction_in(
+        &mut self,
+        account_name: String,
+        action: TransactionAction,
+        memo: Option<String>,
+        currency: Option<String>,
+    ) -> Result<TransactionTransfer, Error> {
+        self.transactions
+            .create_transaction_in(account_name, action, memo, currency)
+    }
+
+    fn transactions(&self) -> Result<Vec<TransactionTransfer>, Error> {
+        self.transactions.transactions()
+    }
+
+    fn transactions_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.transactions.transactions_paged(offset, limit)
+    }
+
+    fn transactions_between(
+        &self,
+        from_id: usize,
+        to_id: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.transactions.transactions_between(from_id, to_id)
+    }
+
+    fn account_transactions(
+        &self,
+        account_name: String,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.transactions.account_transactions(account_name)
+    }
+
+    fn for_each_account_transaction(
+        &self,
+        account_name: String,
+        f: &mut dyn FnMut(TransactionTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.transactions
+            .for_each_account_transaction(account_name, f)
+    }
+
+    fn transaction_by_id(
+        &self,
+        id: impl Into<TransactionId>,
+    ) -> Result<TransactionTransfer, Error> {
+        self.transactions.transaction_by_id(id)
+    }
+
+    fn first_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        self.transactions.first_transaction()
+    }
+
+    fn last_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        self.transactions.last_transaction()
+    }
+
+    fn transaction_count(&self) -> Result<usize, Error> {
+        self.transactions.transaction_count()
+    }
+
+    fn account_transaction_count(&self, account_name: String) -> Result<usize, Error> {
+        self.transactions.account_transaction_count(account_name)
+    }
+
+    fn restore_transaction(&mut self, tr: TransactionTransfer) -> Result<(), Error> {
+        self.transactions.restore_transaction(tr)
+    }
+
+    fn rename_account_references(&mut self, old: String, new: String) -> Result<(), Error> {
+        self.transactions.rename_account_references(old, new)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.transactions.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::fee::FlatFee;
+    use crate::bank::implements::memory::storage::{MemAccountStorage, MemTransactionStorage};
+    use crate::bank::Bank;
+
+    use super::*;
+
+    #[test]
+    fn test_replay_log_restores_bank() {
+        let acc_log = ReplayLog::new(MemAccountStorage::new().unwrap());
+        let tr_log = acc_log.share(MemTransactionStorage::new());
+        let mut bank = Bank::new(acc_log, tr_log, Some(Box::new(FlatFee(1))));
+        let mut acc_f = bank
+            .create_account_with_balance("test_1".to_owned(), 100)
+            .unwrap();
+        let mut acc_s = bank.create_account("tset_2".to_owned()).unwrap();
+        bank.make_transaction(&mut acc_f, &mut acc_s, 10, None, None)
+            .unwrap();
+        bank.rename_account("tset_2".to_owned(), "test_2".to_owned())
+            .unwrap();
+        bank.decr_acc_balance(&mut acc_f, 5).unwrap();
+        bank.set_min_balance("test_2".to_owned(), 3).unwrap();
+        // failed operations are not recorded
+        assert!(bank.decr_acc_balance(&mut acc_f, 1000).is_err());
+
+        let snapshot = bank.snapshot().unwrap();
+        let (acc_log, tr_log) = bank.into_storages();
+        // both logs write to one journal: the transaction log sees the account
+        // changes and holds every stored transaction
+        let ops = tr_log.ops();
+        assert!(matches!(
+            ops[..2],
+            [ReplayOp::CreateAccount(_), ReplayOp::CreateTransaction(_)]
+        ));
+        assert!(ops
+            .iter()
+            .any(|op| matches!(op, ReplayOp::RenameAccount { .. })));
+        assert_eq!(
+            ops.iter()
+                .filter(|op| matches!(op, ReplayOp::CreateTransaction(_)))
+                .count(),
+            snapshot.transactions.len()
+        );
+
+        let mut target = Storages {
+            accounts: MemAccountStorage::new().unwrap(),
+            transactions: MemTransactionStorage::new(),
+        };
+        acc_log.replay_into(&mut target).unwrap();
+
+        let replayed_bank = Bank::new(target.accounts, target.transactions, None);
+        assert!(replayed_bank.audit_integrity().unwrap().is_consistent());
+        let replayed = replayed_bank.snapshot().unwrap();
+        assert_eq!(replayed.accounts, snapshot.accounts);
+        assert_eq!(replayed.transactions, snapshot.transactions);
+        assert_eq!(replayed.fee_balance, 1);
+        assert_eq!(
+            replayed
+                .accounts
+                .iter()
+                .map(|acc| (acc.name.as_str(), acc.balance()))
+                .collect::<Vec<_>>(),
+            [("fee_acc", 1), ("test_1", 84), ("test_2", 10)]
+        );
+    }
+}
@@ -0,0 +1,931 @@
+use crate::bank::implements::memory::storage::system_now;
+use crate::bank::storage::{
+    AccountStorage, AccountTransfer, Error, TransactionAction, TransactionId, TransactionIdMode,
+    TransactionStorage, TransactionTransfer, DEFAULT_CURRENCY, FEE_ACCOUNT_NAME,
+};
+use rusqlite::types::{Type, Value};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::HashMap;
+use std::path::Path;
+
+// account storage backed by an sqlite `accounts` table (the default currency
+// balance and held amount are kept in `balance` and `held`, those in other currencies
+// as json in `balances` and `holds`)
+pub struct SqliteAccountStorage {
+    conn: Connection,
+    // name reserved for bank fees account
+    fee_acc_name: String,
+}
+
+// transaction storage backed by an sqlite `transactions` table
+// (actions are stored as json, ids are sqlite row ids)
+pub struct SqliteTransactionStorage {
+    conn: Connection,
+    id_mode: TransactionIdMode,
+}
+
+fn storage_error<E: ToString>(err: E) -> Error {
+    Error::StorageError(err.to_string())
+}
+
+fn account_from_row(row: &Row) -> rusqlite::Result<AccountTransfer> {
+    let amounts = |idx: usize| -> rusqlite::Result<HashMap<String, usize>> {
+        let json: String = row.get(idx)?;
+        serde_json::from_str(&json)
+            .map_err(|err| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, err.into()))
+    };
+    let mut acc = AccountTransfer {
+        name: row.get(0)?,
+        balances: amounts(7)?,
+        min_balance: row.get::<_, i64>(2)? as usize,
+        frozen: row.get(3)?,
+        transfer_limit: row.get::<_, Option<i64>>(4)?.map(|x| x as usize),
+        transferred: row.get::<_, i64>(5)? as usize,
+        held: amounts(8)?,
+    };
+    acc.set_balance(row.get::<_, i64>(1)? as usize);
+    acc.set_held_in(DEFAULT_CURRENCY, row.get::<_, i64>(6)? as usize);
+    Ok(acc)
+}
+
+// json of the amounts (balances or held) in other than the default currency
+fn other_currencies_json(amounts: &HashMap<String, usize>) -> Result<String, Error> {
+    let mut amounts = amounts.clone();
+    amounts.remove(DEFAULT_CURRENCY);
+    serde_json::to_string(&amounts).map_err(storage_error)
+}
+
+// raw transaction row: id, account_name, action (json), created_at, memo, uuid, currency
+type TransactionRow = (
+    i64,
+    String,
+    String,
+    i64,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+fn transaction_row(row: &Row) -> rusqlite::Result<TransactionRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+        row.get(6)?,
+    ))
+}
+
+fn transaction_from_row(row: TransactionRow) -> Result<TransactionTransfer, Error> {
+    let (id, account_name, action, created_at, memo, uuid, currency) = row;
+    Ok(TransactionTransfer {
+        id: id as usize,
+        action: serde_json::from_str(&action).map_err(storage_error)?,
+        account_name,
+        created_at: created_at as u64,
+        memo,
+        uuid: uuid
+            .map(|uuid| uuid.parse().map_err(storage_error))
+            .transpose()?,
+        currency,
+    })
+}
+
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), Error> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+            params![table, column],
+            |row| row.get(0),
+        )
+        .map_err(storage_error)?;
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            [],
+        )
+        .map_err(storage_error)?;
+    }
+    Ok(())
+}
+
+impl SqliteAccountStorage {
+    // opens (or creates) a database at path (":memory:" for an in-memory one),
+    // creates the table and the fee account if absent
+    // Errors: StorageError
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::with_fee_account_name(path, FEE_ACCOUNT_NAME.to_string())
+    }
+
+    // same as new, the fee account is stored under `fee_acc_name`
+    // Errors: StorageError
+    pub fn with_fee_account_name<P: AsRef<Path>>(
+        path: P,
+        fee_acc_name: String,
+    ) -> Result<Self, Error> {
+        let conn = Connection::open(path).map_err(storage_error)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS accounts (
+                name TEXT PRIMARY KEY,
+                balance INTEGER NOT NULL,
+                min_balance INTEGER NOT NULL DEFAULT 0,
+                frozen INTEGER NOT NULL DEFAULT 0,
+                transfer_limit INTEGER,
+                transferred INTEGER NOT NULL DEFAULT 0,
+                held INTEGER NOT NULL DEFAULT 0,
+                balances TEXT NOT NULL DEFAULT '{}',
+                holds TEXT NOT NULL DEFAULT '{}'
+            )",
+            [],
+        )
+        .map_err(storage_error)?;
+        // databases created before these columns were added
+        add_column_if_missing(
+            &conn,
+            "accounts",
+            "min_balance",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        add_column_if_missing(&conn, "accounts", "frozen", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&conn, "accounts", "transfer_limit", "INTEGER")?;
+        add_column_if_missing(
+            &conn,
+            "accounts",
+            "transferred",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        add_column_if_missing(&conn, "accounts", "held", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&conn, "accounts", "balances", "TEXT NOT NULL DEFAULT '{}'")?;
+        add_column_if_missing(&conn, "accounts", "holds", "TEXT NOT NULL DEFAULT '{}'")?;
+
+        let mut s = SqliteAccountStorage { conn, fee_acc_name };
+        match s.create_account(AccountTransfer::new(s.fee_acc_name.clone(), None)) {
+            Ok(_) | Err(Error::AccountAlreadyExists) => Ok(s),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl SqliteTransactionStorage {
+    // opens (or creates) a database at path (":memory:" for an in-memory one)
+    // and creates the table if absent
+    // Errors: StorageError
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::with_id_mode(path, TransactionIdMode::Sequential)
+    }
+
+    // same as new, new transactions are given ids of the mode
+    // Errors: StorageError
+    pub fn with_id_mode<P: AsRef<Path>>(
+        path: P,
+        id_mode: TransactionIdMode,
+    ) -> Result<Self, Error> {
+        let conn = Connection::open(path).map_err(storage_error)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transactions (
+                id INTEGER PRIMARY KEY,
+                account_name TEXT NOT NULL,
+                action TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )
+        .map_err(storage_error)?;
+        add_column_if_missing(&conn, "transactions", "memo", "TEXT")?;
+        // set in the uuid id mode (and for transactions restored from such a storage)
+        add_column_if_missing(&conn, "transactions", "uuid", "TEXT")?;
+        // NULL for the default currency
+        add_column_if_missing(&conn, "transactions", "currency", "TEXT")?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS transactions_account_name ON transactions (account_name)",
+            [],
+        )
+        .map_err(storage_error)?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS transactions_uuid ON transactions (uuid)",
+            [],
+        )
+        .map_err(storage_error)?;
+        Ok(SqliteTransactionStorage { conn, id_mode })
+    }
+
+    fn query(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        let mut stmt = self.conn.prepare(sql).map_err(storage_error)?;
+        let rows = stmt
+            .query_map(params, transaction_row)
+            .map_err(storage_error)?;
+        let mut transactions = Vec::new();
+        for row in rows {
+            transactions.push(transaction_from_row(row.map_err(storage_error)?)?);
+        }
+        Ok(transactions)
+    }
+}
+
+impl AccountStorage for SqliteAccountStorage {
+    fn create_account(&mut self, raw_data: AccountTransfer) -> Result<AccountTransfer, Error> {
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO accounts
+                (name, balance, min_balance, frozen, transfer_limit, transferred, held, balances,
+                holds)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    raw_data.name,
+                    raw_data.balance() as i64,
+                    raw_data.min_balance as i64,
+                    raw_data.frozen,
+                    raw_data.transfer_limit.map(|x| x as i64),
+                    raw_data.transferred as i64,
+                    raw_data.held() as i64,
+                    other_currencies_json(&raw_data.balances)?,
+                    other_currencies_json(&raw_data.held)?
+                ],
+            )
+            .map_err(storage_error)?;
+        if inserted == 0 {
+            return Err(Error::AccountAlreadyExists);
+        }
+        Ok(raw_data)
+    }
+
+    fn get_account(&self, name: String) -> Result<AccountTransfer, Error> {
+        self.conn
+            .query_row(
+                "SELECT name, balance, min_balance, frozen, transfer_limit, transferred, held, balances, holds FROM accounts WHERE name = ?1",
+                params![name],
+                account_from_row,
+            )
+            .optional()
+            .map_err(storage_error)?
+            .ok_or(Error::AccountNotExists)
+    }
+
+    fn update_account(&mut self, transfer_data: AccountTransfer) -> Result<AccountTransfer, Error> {
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE accounts SET balance = ?2, min_balance = ?3, frozen = ?4,
+                transfer_limit = ?5, transferred = ?6, held = ?7, balances = ?8, holds = ?9
+                WHERE name = ?1",
+                params![
+                    transfer_data.name,
+                    transfer_data.balance() as i64,
+                    transfer_data.min_balance as i64,
+                    transfer_data.frozen,
+                    transfer_data.transfer_limit.map(|x| x as i64),
+                    transfer_data.transferred as i64,
+                    transfer_data.held() as i64,
+                    other_currencies_json(&transfer_data.balances)?,
+                    other_currencies_json(&transfer_data.held)?
+                ],
+            )
+            .map_err(storage_error)?;
+        if updated == 0 {
+            return Err(Error::AccountNotExists);
+        }
+        self.get_account(transfer_data.name)
+    }
+
+    fn fee_account(&self) -> Result<AccountTransfer, Error> {
+        self.get_account(self.fee_acc_name.clone())
+    }
+
+    fn accounts(&self) -> Result<Vec<AccountTransfer>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, balance, min_balance, frozen, transfer_limit, transferred, held, balances, holds FROM accounts ORDER BY name")
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map([], account_from_row)
+            .map_err(storage_error)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(storage_error)
+    }
+
+    fn for_each_account(
+        &self,
+        f: &mut dyn FnMut(&AccountTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, balance, min_balance, frozen, transfer_limit, transferred, held,
+                balances, holds FROM accounts ORDER BY name",
+            )
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map([], account_from_row)
+            .map_err(storage_error)?;
+        for row in rows {
+            f(&row.map_err(storage_error)?)?;
+        }
+        Ok(())
+    }
+
+    fn count(&self) -> Result<usize, Error> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM accounts", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as usize)
+            .map_err(storage_error)
+    }
+
+    fn rename_account(&mut self, old: String, new: String) -> Result<AccountTransfer, Error> {
+        if self.get_account(new.clone()).is_ok() {
+            return Err(Error::AccountAlreadyExists);
+        }
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE accounts SET name = ?2 WHERE name = ?1",
+                params![old, new],
+            )
+            .map_err(storage_error)?;
+        if updated == 0 {
+            return Err(Error::AccountNotExists);
+        }
+        self.get_account(new)
+    }
+
+    fn delete_account(&mut self, name: String) -> Result<AccountTransfer, Error> {
+        let acc = self.get_account(name.clone())?;
+        self.conn
+            .execute("DELETE FROM accounts WHERE name = ?1", params![name])
+            .map_err(storage_error)?;
+        Ok(acc)
+    }
+}
+
+impl TransactionStorage for SqliteTransactionStorage {
+    fn create_transaction_in(
+        &mut self,
+        account_name: String,
+        action: TransactionAction,
+        memo: Option<String>,
+        currency: Option<String>,
+    ) -> Result<TransactionTransfer, Error> {
+        let created_at = system_now();
+        let uuid = self.id_mode.new_uuid();
+        self.conn
+            .execute(
+                "INSERT INTO transactions (account_name, action, created_at, memo, uuid, currency)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    account_name,
+                    serde_json::to_string(&action).map_err(storage_error)?,
+                    created_at as i64,
+                    memo,
+                    uuid.map(|uuid| uuid.to_string()),
+                    currency
+                ],
+            )
+            .map_err(storage_error)?;
+        Ok(TransactionTransfer {
+            id: self.conn.last_insert_rowid() as usize,
+            action,
+            account_name,
+            created_at,
+            memo,
+            uuid,
+            currency,
+        })
+    }
+
+    fn transactions(&self) -> Result<Vec<TransactionTransfer>, Error> {
+        self.query(
+            "SELECT id, account_name, action, created_at, memo, uuid, currency FROM transactions ORDER BY id",
+            [],
+        )
+    }
+
+    fn transactions_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.query(
+            "SELECT id, account_name, action, created_at, memo, uuid, currency FROM transactions
+            ORDER BY id LIMIT ?1 OFFSET ?2",
+            params![
+                i64::try_from(limit).unwrap_or(i64::MAX),
+                i64::try_from(offset).unwrap_or(i64::MAX)
+            ],
+        )
+    }
+
+    fn transactions_between(
+        &self,
+        from_id: usize,
+        to_id: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        self.query(
+            "SELECT id, account_name, action, created_at, memo, uuid, currency FROM transactions
+            WHERE id BETWEEN ?1 AND ?2 ORDER BY id",
+            params![
+                i64::try_from(from_id).unwrap_or(i64::MAX),
+                i64::try_from(to_id).unwrap_or(i64::MAX)
+            ],
+        )
+    }
+
+    fn account_transactions(
+        &self,
+        account_name: String,
+    ) -> Result<Vec<TransactionTransfer>, Error> {
+        let transactions = self.query(
+            "SELECT id, account_name, action, created_at, memo, uuid, currency FROM transactions
+            WHERE account_name = ?1 ORDER BY id",
+            params![account_name],
+        )?;
+        if transactions.is_empty() {
+            return Err(Error::AccountNotExists);
+        }
+        Ok(transactions)
+    }
+
+    fn for_each_account_transaction(
+        &self,
+        account_name: String,
+        f: &mut dyn FnMut(TransactionTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, account_name, action, created_at, memo, uuid, currency FROM transactions
+                WHERE account_name = ?1 ORDER BY id",
+            )
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map(params![account_name], transaction_row)
+            .map_err(storage_error)?;
+
+        let mut found = false;
+        for row in rows {
+            found = true;
+            f(transaction_from_row(row.map_err(storage_error)?)?)?;
+        }
+        if !found {
+            return Err(Error::AccountNotExists);
+        }
+        Ok(())
+    }
+
+    fn transaction_by_id(
+        &self,
+        id: impl Into<TransactionId>,
+    ) -> Result<TransactionTransfer, Error> {
+        let (sql, param) = match id.into() {
+            TransactionId::Seq(id) => ("id = ?1", Value::Integer(id as i64)),
+            TransactionId::Uuid(uuid) => ("uuid = ?1", Value::Text(uuid.to_string())),
+        };
+        let row = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT id, account_name, action, created_at, memo, uuid, currency FROM transactions
+                    WHERE {sql}"
+                ),
+                params![param],
+                transaction_row,
+            )
+            .optional()
+            .map_err(storage_error)?
+            .ok_or(Error::TransactionNotExists)?;
+        transaction_from_row(row)
+    }
+
+    fn first_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        Ok(self
+            .query(
+                "SELECT id, account_name, action, created_at, memo, uuid, currency FROM transactions
+                ORDER BY id ASC LIMIT 1",
+                [],
+            )?
+            .pop())
+    }
+
+    fn last_transaction(&self) -> Result<Option<TransactionTransfer>, Error> {
+        Ok(self
+            .query(
+                "SELECT id, account_name, action, created_at, memo, uuid, currency FROM transactions
+                ORDER BY id DESC LIMIT 1",
+                [],
+            )?
+            .pop())
+    }
+
+    fn transaction_count(&self) -> Result<usize, Error> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM transactions", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as usize)
+            .map_err(storage_error)
+    }
+
+    fn account_transaction_count(&self, account_name: String) -> Result<usize, Error> {
+        self.conn
+            .query_row(
+                "SELECT COUNT(*) FROM transactions WHERE account_name = ?1",
+                params![account_name],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count as usize)
+            .map_err(storage_error)
+    }
+
+    fn restore_transaction(&mut self, tr: TransactionTransfer) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT INTO transactions
+                (id, account_name, action, created_at, memo, uuid, currency)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    tr.id as i64,
+                    tr.account_name,
+                    serde_json::to_string(&tr.action).map_err(storage_error)?,
+                    tr.created_at as i64,
+                    tr.memo,
+                    tr.uuid.map(|uuid| uuid.to_string()),
+                    tr.currency
+                ],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn rename_account_references(&mut self, old: String, new: String) -> Result<(), Error> {
+        let transactions = self.transactions()?;
+        let tx = self.conn.transaction().map_err(storage_error)?;
+        tx.execute(
+            "UPDATE transactions SET account_name = ?2 WHERE account_name = ?1",
+            params![old, new],
+        )
+        .map_err(storage_error)?;
+        for mut tr in transactions {
+            if tr.action.rename_counterparty(&old, &new) {
+                tx.execute(
+                    "UPDATE transactions SET action = ?2 WHERE id = ?1",
+                    params![
+                        tr.id as i64,
+                        serde_json::to_string(&tr.action).map_err(storage_error)?
+                    ],
+                )
+                .map_err(storage_error)?;
+            }
+        }
+        tx.commit().map_err(storage_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::account::{Account, TransferOptions};
+    use crate::bank::storage::Error as StorageError;
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn test_sqlite_storage_accounts() {
+        let mut storage = SqliteAccountStorage::new(":memory:").unwrap();
+        assert_eq!(storage.fee_account().unwrap().balance(), 0);
+        assert_eq!(
+            storage.get_account("test".to_owned()).err().unwrap(),
+            StorageError::AccountNotExists
+        );
+
+        storage
+            .create_account(AccountTransfer::new("test".to_owned(), Some(5)))
+            .unwrap();
+        assert_eq!(
+            storage
+                .create_account(AccountTransfer::new("test".to_owned(), None))
+                .err()
+                .unwrap(),
+            StorageError::AccountAlreadyExists
+        );
+        assert_eq!(storage.get_account("test".to_owned()).unwrap().balance(), 5);
+
+        let acc = storage
+            .update_account(AccountTransfer::new("test".to_owned(), Some(42)))
+            .unwrap();
+        assert_eq!(acc.balance(), 42);
+        assert_eq!(
+            storage
+                .update_account(AccountTransfer::new("not_exists".to_owned(), None))
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+        assert_eq!(storage.accounts().unwrap().len(), 2);
+        assert_eq!(storage.count().unwrap(), 2);
+
+        let mut acc = storage.get_account("test".to_owned()).unwrap();
+        acc.set_held_in(DEFAULT_CURRENCY, 7);
+        acc.set_held_in("EUR", 3);
+        let updated = storage.update_account(acc).unwrap();
+        assert_eq!((updated.held(), updated.held_in("EUR")), (7, 3));
+        let stored = storage.get_account("test".to_owned()).unwrap();
+        assert_eq!(stored.held, updated.held);
+    }
+
+    #[test]
+    fn test_sqlite_storage_custom_fee_account_name() {
+        let storage =
+            SqliteAccountStorage::with_fee_account_name(":memory:", "bank_fees".to_owned())
+                .unwrap();
+        assert_eq!(storage.fee_account().unwrap().name, "bank_fees");
+        assert_eq!(storage.accounts().unwrap().len(), 1);
+        assert_eq!(
+            storage
+                .get_account(FEE_ACCOUNT_NAME.to_owned())
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+    }
+
+    #[test]
+    fn test_sqlite_storage_transactions_order() {
+        let mut storage = SqliteTransactionStorage::new(":memory:").unwrap();
+        assert!(storage.first_transaction().unwrap().is_none());
+        assert!(storage.last_transaction().unwrap().is_none());
+        for name in ["test_2", "test_1", "test_2", "test_3"] {
+            storage
+                .create_transaction(name.to_owned(), TransactionAction::Registration)
+                .unwrap();
+        }
+        let tr = storage
+            .create_transaction(
+                "test_1".to_owned(),
+                TransactionAction::TransferOut {
+                    to: "test_2".to_owned(),
+                    value: 10,
+                    fee: 1,
+                    memo: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(tr.id, 5);
+
+        let trs = storage.transactions().unwrap();
+        assert_eq!(
+            trs.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            trs.iter()
+                .map(|x| x.account_name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["test_2", "test_1", "test_2", "test_3", "test_1"]
+        );
+
+        let tr = storage.transaction_by_id(5).unwrap();
+        assert_eq!(tr.account_name, "test_1");
+        assert_eq!(
+            tr.action,
+            TransactionAction::TransferOut {
+                to: "test_2".to_owned(),
+                value: 10,
+                fee: 1,
+                memo: None,
+            }
+        );
+        assert_eq!(
+            storage.transaction_by_id(6).err().unwrap(),
+            StorageError::TransactionNotExists
+        );
+
+        let page = storage.transactions_paged(1, 2).unwrap();
+        assert_eq!(page.iter().map(|x| x.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(storage.transactions_paged(10, 2).unwrap().is_empty());
+        let range = storage.transactions_between(2, 4).unwrap();
+        assert_eq!(
+            range.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        assert_eq!(
+            storage.transactions_between(4, usize::MAX).unwrap().len(),
+            2
+        );
+        assert!(storage.transactions_between(4, 3).unwrap().is_empty());
+
+        assert_eq!(
+            storage
+                .account_transactions("test_2".to_owned())
+                .unwrap()
+                .iter()
+                .map(|x| x.id)
+                .collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(
+            storage
+                .account_transactions("not_exists".to_owned())
+                .err()
+                .unwrap(),
+            StorageError::AccountNotExists
+        );
+
+        // restored transactions keep their ids, new ones continue after them
+        let uuid = Uuid::new_v4();
+        storage
+            .restore_transaction(TransactionTransfer {
+                id: 10,
+                action: TransactionAction::Increment(3),
+                account_name: "test_3".to_owned(),
+                created_at: 7,
+                memo: Some("cash".to_owned()),
+                uuid: Some(uuid),
+                currency: None,
+            })
+            .unwrap();
+        let restored = storage.transaction_by_id(10).unwrap();
+        assert_eq!(restored.created_at, 7);
+        assert_eq!(restored.memo.as_deref(), Some("cash"));
+        assert_eq!(restored.uuid, Some(uuid));
+        assert_eq!(storage.transaction_by_id(uuid).unwrap().id, 10);
+        assert_eq!(
+            storage.transaction_by_id(Uuid::new_v4()).err().unwrap(),
+            StorageError::TransactionNotExists
+        );
+        let tr = storage
+            .create_transaction("test_3".to_owned(), TransactionAction::Increment(1))
+            .unwrap();
+        assert_eq!(tr.id, 11);
+        assert_eq!(storage.first_transaction().unwrap().unwrap().id, 1);
+        assert_eq!(storage.last_transaction().unwrap().unwrap().id, 11);
+    }
+
+    #[test]
+    fn test_sqlite_storage_uuid_id_mode() {
+        let mut storage =
+            SqliteTransactionStorage::with_id_mode(":memory:", TransactionIdMode::Uuid).unwrap();
+        let first = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Registration)
+            .unwrap();
+        let second = storage
+            .create_transaction("test_1".to_owned(), TransactionAction::Increment(10))
+            .unwrap();
+        let (first_uuid, second_uuid) = (first.uuid.unwrap(), second.uuid.unwrap());
+        assert_ne!(first_uuid, second_uuid);
+        assert_eq!(second.transaction_id(), TransactionId::Uuid(second_uuid));
+
+        assert_eq!(storage.transaction_by_id(first_uuid).unwrap().id, first.id);
+        assert_eq!(
+            storage.transaction_by_id(second.id).unwrap().uuid,
+            Some(second_uuid)
+        );
+        assert_eq!(
+            storage.last_transaction().unwrap().unwrap().uuid,
+            Some(second_uuid)
+        );
+    }
+
+    #[test]
+    fn test_sqlite_storage_account_operations() {
+        let mut acc_storage = SqliteAccountStorage::new(":memory:").unwrap();
+        let mut tr_storage = SqliteTransactionStorage::new(":memory:").unwrap();
+        let mut acc_f =
+            Account::new("person_1".to_owned(), &mut acc_storage, &mut tr_storage).unwrap();
+        let mut acc_s =
+            Account::new("person_2".to_owned(), &mut acc_storage, &mut tr_storage).unwrap();
+        acc_f
+            .inc_balance(100, &mut acc_storage, &mut tr_storage)
+            .unwrap();
+        acc_f
+            .make_transaction(10, &mut acc_s, Some(1), &mut acc_storage, &mut tr_storage)
+            .unwrap();
+
+        assert_eq!(
+            acc_storage
+                .get_account("person_1".to_owned())
+                .unwrap()
+                .balance(),
+            89
+        );
+        assert_eq!(
+            acc_storage
+                .get_account("person_2".to_owned())
+                .unwrap()
+                .balance(),
+            10
+        );
+        assert_eq!(acc_storage.fee_account().unwrap().balance(), 1);
+
+        let restored = Account::restore_account_from_transactions(
+            "person_1".to_owned(),
+            &mut acc_storage,
+            &tr_storage,
+        )
+        .unwrap();
+        assert_eq!(restored.balance(), 89);
+    }
+
+    #[test]
+    fn test_sqlite_storage_currency_balances() {
+        let mut acc_storage = SqliteAccountStorage::new(":memory:").unwrap();
+        let mut tr_storage = SqliteTransactionStorage::new(":memory:").unwrap();
+        let mut acc_f =
+            Account::new("person_1".to_owned(), &mut acc_storage, &mut tr_storage).unwrap();
+        let mut acc_s =
+            Account::new("person_2".to_owned(), &mut acc_storage, &mut tr_storage).unwrap();
+        acc_f
+            .inc_balance(100, &mut acc_storage, &mut tr_storage)
+            .unwrap();
+        acc_f
+            .inc_balance_in(50, "EUR", None, &mut acc_storage, &mut tr_storage)
+            .unwrap();
+        let tr_id = acc_f
+            .make_transaction_with_options(
+                10,
+                &mut acc_s,
+                TransferOptions {
+                    fee: Some(1),
+                    memo: Some("eur".to_owned()),
+                    currency: Some("EUR".to_owned()),
+                },
+                &mut acc_storage,
+                &mut tr_storage,
+            )
+            .unwrap();
+
+        let stored = acc_storage.get_account("person_1".to_owned()).unwrap();
+        assert_eq!((stored.balance(), stored.balance_in("EUR")), (100, 39));
+        let stored = acc_storage.get_account("person_2".to_owned()).unwrap();
+        assert_eq!((stored.balance(), stored.balance_in("EUR")), (0, 10));
+        let fee_acc = acc_storage.fee_account().unwrap();
+        assert_eq!((fee_acc.balance(), fee_acc.balance_in("EUR")), (0, 1));
+        let tr = tr_storage.transaction_by_id(tr_id).unwrap();
+        assert_eq!((tr.currency(), tr.memo()), ("EUR", Some("eur")));
+        assert_eq!(tr_storage.transaction_by_id(3).unwrap().currency, None);
+
+        let restored = Account::restore_account_from_transactions(
+            "person_1".to_owned(),
+            &mut acc_storage,
+            &tr_storage,
+        )
+        .unwrap();
+        assert_eq!(restored.balances, acc_f.balances);
+    }
+
+    #[test]
+    fn test_sqlite_storage_rename_account() {
+        let mut acc_storage = SqliteAccountStorage::new(":memory:").unwrap();
+        let mut tr_storage = SqliteTransactionStorage::new(":memory:").unwrap();
+        let mut acc_f = Account::new("old".to_owned(), &mut acc_storage, &mut tr_storage).unwrap();
+        let mut acc_s =
+            Account::new("other".to_owned(), &mut acc_storage, &mut tr_storage).unwrap();
+        acc_f
+            .inc_balance(10, &mut acc_storage, &mut tr_storage)
+            .unwrap();
+        acc_f
+            .make_transaction(4, &mut acc_s, None, &mut acc_storage, &mut tr_storage)
+            .unwrap();
+
+        assert_eq!(
+            acc_storage
+                .rename_account("old".to_owned(), "other".to_owned())
+                .err()
+                .unwrap(),
+            StorageError::AccountAlreadyExists
+        );
+        let acc = acc_storage
+            .rename_account("old".to_owned(), "new".to_owned())
+            .unwrap();
+        assert_eq!((acc.name.as_str(), acc.balance()), ("new", 6));
+        assert_eq!(
+            acc_storage.get_account("old".to_owned()).err().unwrap(),
+            StorageError::AccountNotExists
+        );
+
+        tr_storage
+            .rename_account_references("old".to_owned(), "new".to_owned())
+            .unwrap();
+        assert!(tr_storage.account_transactions("old".to_owned()).is_err());
+        let trs = tr_storage.account_transactions("new".to_owned()).unwrap();
+        assert_eq!(trs.len(), 3);
+        assert_eq!(
+            tr_storage.account_transactions("other".to_owned()).unwrap()[1].action,
+            TransactionAction::TransferIn {
+                from: "new".to_owned(),
+                value: 4,
+                memo: None,
+            }
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::account::Account;
+use super::storage::{AccountTransfer, Error, TransactionTransfer};
+use super::transactions::Transaction;
+
+// leading bytes of a binary snapshot
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"RBSN";
+// bumped on every incompatible change of the snapshot layout
+pub const SNAPSHOT_VERSION: u8 = 12;
+
+// full bank state: accounts (including the fee account) and transactions with their ids
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BankState {
+    pub accounts: Vec<AccountTransfer>,
+    pub transactions: Vec<TransactionTransfer>,
+}
+
+// consistent read-only view of the bank (accounts sorted by name, transactions by id)
+#[derive(Debug)]
+pub struct BankSnapshot {
+    pub accounts: Vec<Account>,
+    pub transactions: Vec<Transaction>,
+    pub fee_balance: usize,
+}
+
+fn storage_error<E: ToString>(err: E) -> Error {
+    Error::StorageError(err.to_string())
+}
+
+impl BankState {
+    // writes header (magic + version) followed by bincode encoded state
+    // Errors: StorageError
+    pub fn write_binary<W: Write>(&self, w: &mut W) -> Result<(), Error> {
+        w.write_all(SNAPSHOT_MAGIC).map_err(storage_error)?;
+        w.write_all(&[SNAPSHOT_VERSION]).map_err(storage_error)?;
+        bincode::serialize_into(&mut *w, self).map_err(storage_error)?;
+        w.flush().map_err(storage_error)
+    }
+
+    // reads a state written by write_binary
+    // Errors: StorageError (bad header, unsupported version, malformed data)
+    pub fn read_binary<R: Read>(r: &mut R) -> Result<Self, Error> {
+        let mut header = [0u8; 5];
+        r.read_exact(&mut header).map_err(storage_error)?;
+        if &header[..4] != SNAPSHOT_MAGIC {
+            return Err(Error::StorageError("invalid snapshot header".to_string()));
+        }
+        if header[4] != SNAPSHOT_VERSION {
+            return Err(Error::StorageError(format!(
+                "unsupported snapshot version {}",
+                header[4]
+            )));
+        }
+        bincode::deserialize_from(r).map_err(storage_error)
+    }
+
+    // human readable form of the state (for backups)
+    // Errors: StorageError
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(storage_error)
+    }
+
+    // reads a state written by to_json
+    // Errors: StorageError (malformed data)
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(storage_error)
+    }
+}
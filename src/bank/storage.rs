@@ -1,32 +1,386 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+
+// name reserved for the bank fees account
+pub const FEE_ACCOUNT_NAME: &str = "fee_acc";
+
+// currency of balances and transactions that don't name one
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+// max length (in chars) of a transfer memo
+pub const MAX_MEMO_LEN: usize = 256;
+
 // data between database and Model
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AccountTransfer {
     pub name: String,
-    pub balance: usize,
+    // balance per currency code (currencies with a zero balance are left out)
+    #[serde(default, alias = "balance", deserialize_with = "deserialize_balances")]
+    pub balances: HashMap<String, usize>,
+    // default currency balance can't go below it by withdrawals and transfers
+    #[serde(default)]
+    pub min_balance: usize,
+    // no money can enter or leave a frozen account
+    #[serde(default)]
+    pub frozen: bool,
+    // cap on the total default currency value sent by transfers (None - no cap)
+    #[serde(default)]
+    pub transfer_limit: Option<usize>,
+    // value sent by transfers since the last usage reset
+    #[serde(default)]
+    pub transferred: usize,
+    // part of the balance per currency reserved by open holds (not available for
+    // spending; currencies without holds are left out)
+    #[serde(default, deserialize_with = "deserialize_balances")]
+    pub held: HashMap<String, usize>,
+}
+
+// direction of money moved between an account and an external system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExternalDirection {
+    Deposit,
+    Withdrawal,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TransactionAction {
     Registration,
     Increment(usize),
     Decrement(usize),
+    // sender side of a transfer (debits value + fee)
+    TransferOut {
+        to: String,
+        value: usize,
+        fee: usize,
+        // free text note shown on statements
+        #[serde(default)]
+        memo: Option<String>,
+    },
+    // recipient side of a transfer (credits value)
+    TransferIn {
+        from: String,
+        value: usize,
+        #[serde(default)]
+        memo: Option<String>,
+    },
+    // money entered or left the bank via an external rail (no counterparty account)
+    External {
+        direction: ExternalDirection,
+        amount: usize,
+        reference: String,
+    },
+    // part of the balance reserved until the hold is settled or released
+    Hold(usize),
+    // closes the hold created by transaction `hold_id`
+    Release {
+        hold_id: usize,
+        amount: usize,
+    },
+    // marks transaction `tr_id` as reversed (the compensation is logged separately)
+    Reversal {
+        tr_id: usize,
+    },
+    // the account was renamed from `from` (its history was moved to the new name)
+    Renamed {
+        from: String,
+    },
+    // account `from` was merged into this one and removed
+    Merged {
+        from: String,
+    },
+    // the account was closed and removed, its history stays under the name
+    Closed,
+    // fee of the transfer `transfer_id` (its sender side) credited to the fee account
+    Fee {
+        transfer_id: usize,
+        amount: usize,
+    },
+}
+
+// kind of transaction action without its data (used for filtering)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionKind {
+    Registration,
+    Increment,
+    Decrement,
+    TransferOut,
+    TransferIn,
+    External,
+    Hold,
+    Release,
+    Reversal,
+    Renamed,
+    Merged,
+    Closed,
+    Fee,
+}
+
+// id a transaction can be looked up by: its sequence number (every storage) or its
+// uuid (set only by storages generating uuids)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransactionId {
+    Seq(usize),
+    Uuid(Uuid),
 }
 
-#[derive(Debug)]
+impl From<usize> for TransactionId {
+    fn from(value: usize) -> Self {
+        TransactionId::Seq(value)
+    }
+}
+
+impl From<Uuid> for TransactionId {
+    fn from(value: Uuid) -> Self {
+        TransactionId::Uuid(value)
+    }
+}
+
+impl Display for TransactionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransactionId::Seq(id) => write!(f, "{id}"),
+            TransactionId::Uuid(uuid) => write!(f, "{uuid}"),
+        }
+    }
+}
+
+// ids given to new transactions; sequence numbers are kept in both modes (they
+// order the log), uuids are public ids that don't reveal the transaction volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransactionIdMode {
+    #[default]
+    Sequential,
+    Uuid,
+}
+
+impl TransactionIdMode {
+    // uuid for a new transaction (None in the sequential mode)
+    pub fn new_uuid(self) -> Option<Uuid> {
+        match self {
+            TransactionIdMode::Sequential => None,
+            TransactionIdMode::Uuid => Some(Uuid::new_v4()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionTransfer {
     pub id: usize,
     pub action: TransactionAction,
     pub account_name: String,
+    // creation time (unix millis)
+    pub created_at: u64,
+    // note attached to an increment or a decrement (transfers keep it in the action)
+    #[serde(default)]
+    pub memo: Option<String>,
+    // public id of the transaction (None unless the storage generates uuids)
+    #[serde(default)]
+    pub uuid: Option<Uuid>,
+    // currency of the amounts of the action (None - DEFAULT_CURRENCY)
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+impl TransactionAction {
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            TransactionAction::Registration => ActionKind::Registration,
+            TransactionAction::Increment(_) => ActionKind::Increment,
+            TransactionAction::Decrement(_) => ActionKind::Decrement,
+            TransactionAction::TransferOut { .. } => ActionKind::TransferOut,
+            TransactionAction::TransferIn { .. } => ActionKind::TransferIn,
+            TransactionAction::External { .. } => ActionKind::External,
+            TransactionAction::Hold(_) => ActionKind::Hold,
+            TransactionAction::Release { .. } => ActionKind::Release,
+            TransactionAction::Reversal { .. } => ActionKind::Reversal,
+            TransactionAction::Renamed { .. } => ActionKind::Renamed,
+            TransactionAction::Merged { .. } => ActionKind::Merged,
+            TransactionAction::Closed => ActionKind::Closed,
+            TransactionAction::Fee { .. } => ActionKind::Fee,
+        }
+    }
+
+    // note attached to a transfer
+    pub fn memo(&self) -> Option<&str> {
+        match self {
+            TransactionAction::TransferOut { memo, .. }
+            | TransactionAction::TransferIn { memo, .. } => memo.as_deref(),
+            _ => None,
+        }
+    }
+
+    // replaces references to the counterparty account `old` with `new`
+    // returns true if the action was changed
+    pub fn rename_counterparty(&mut self, old: &str, new: &str) -> bool {
+        match self {
+            TransactionAction::TransferOut { to: name, .. }
+            | TransactionAction::TransferIn { from: name, .. }
+                if name == old =>
+            {
+                *name = new.to_string();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // signed effect of the action on the account balance
+    pub fn balance_delta(&self) -> i128 {
+        match self {
+            TransactionAction::Registration => 0,
+            TransactionAction::Increment(amount) => *amount as i128,
+            TransactionAction::Decrement(amount) => -(*amount as i128),
+            TransactionAction::TransferOut { value, fee, .. } => -(*value as i128 + *fee as i128),
+            TransactionAction::TransferIn { value, .. } => *value as i128,
+            TransactionAction::Fee { amount, .. } => *amount as i128,
+            TransactionAction::External {
+                direction: ExternalDirection::Deposit,
+                amount,
+                ..
+            } => *amount as i128,
+            TransactionAction::External {
+                direction: ExternalDirection::Withdrawal,
+                amount,
+                ..
+            } => -(*amount as i128),
+            // holds only reserve money, the balance stays
+            TransactionAction::Hold(_) | TransactionAction::Release { .. } => 0,
+            // markers of reversals and account changes, the money moved by them is logged
+            // as separate transactions
+            TransactionAction::Reversal { .. }
+            | TransactionAction::Renamed { .. }
+            | TransactionAction::Merged { .. }
+            | TransactionAction::Closed => 0,
+        }
+    }
+
+    // signed effect of the action on the held part of the balance
+    pub fn held_delta(&self) -> i128 {
+        match self {
+            TransactionAction::Hold(amount) => *amount as i128,
+            TransactionAction::Release { amount, .. } => -(*amount as i128),
+            _ => 0,
+        }
+    }
+}
+
+impl TransactionTransfer {
+    // the uuid if the transaction has one, the sequence number otherwise
+    pub fn transaction_id(&self) -> TransactionId {
+        self.uuid
+            .map_or(TransactionId::Seq(self.id), TransactionId::Uuid)
+    }
+
+    // note attached to the transaction
+    pub fn memo(&self) -> Option<&str> {
+        self.action.memo().or(self.memo.as_deref())
+    }
+
+    pub fn currency(&self) -> &str {
+        self.currency.as_deref().unwrap_or(DEFAULT_CURRENCY)
+    }
+
+    // effect on the balance of the transaction account; a transfer to itself
+    // (left e.g. by a merge) moves no value, only its fee is charged
+    pub fn balance_delta(&self) -> i128 {
+        match &self.action {
+            TransactionAction::TransferOut { to, fee, .. } if *to == self.account_name => {
+                -(*fee as i128)
+            }
+            TransactionAction::TransferIn { from, .. } if *from == self.account_name => 0,
+            action => action.balance_delta(),
+        }
+    }
 }
 
 impl AccountTransfer {
+    // `balance` is in the default currency
     pub fn new(name: String, balance: Option<usize>) -> Self {
-        Self {
+        let mut acc = Self {
             name,
-            balance: balance.unwrap_or_default(),
+            balances: HashMap::new(),
+            min_balance: 0,
+            frozen: false,
+            transfer_limit: None,
+            transferred: 0,
+            held: HashMap::new(),
+        };
+        acc.set_balance(balance.unwrap_or_default());
+        acc
+    }
+
+    // balance in the default currency
+    pub fn balance(&self) -> usize {
+        self.balance_in(DEFAULT_CURRENCY)
+    }
+
+    pub fn balance_in(&self, currency: &str) -> usize {
+        self.balances.get(currency).copied().unwrap_or_default()
+    }
+
+    pub fn set_balance(&mut self, value: usize) {
+        self.set_balance_in(DEFAULT_CURRENCY, value)
+    }
+
+    // a zero balance removes the currency
+    pub fn set_balance_in(&mut self, currency: &str, value: usize) {
+        set_currency_balance(&mut self.balances, currency, value)
+    }
+
+    // held amount in the default currency
+    pub fn held(&self) -> usize {
+        self.held_in(DEFAULT_CURRENCY)
+    }
+
+    pub fn held_in(&self, currency: &str) -> usize {
+        self.held.get(currency).copied().unwrap_or_default()
+    }
+
+    // a zero amount removes the currency
+    pub fn set_held_in(&mut self, currency: &str, value: usize) {
+        set_currency_balance(&mut self.held, currency, value)
+    }
+}
+
+// json written before amounts were kept per currency has a single number
+// (`balance` or `held`), it's read as the default currency amount
+fn deserialize_balances<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Balances {
+        Single(usize),
+        PerCurrency(HashMap<String, usize>),
+    }
+
+    // binary formats can't tell the two apart and never had the single balance
+    if !deserializer.is_human_readable() {
+        return HashMap::deserialize(deserializer);
+    }
+    Ok(match Balances::deserialize(deserializer)? {
+        Balances::Single(balance) => {
+            let mut balances = HashMap::new();
+            set_currency_balance(&mut balances, DEFAULT_CURRENCY, balance);
+            balances
         }
+        Balances::PerCurrency(balances) => balances,
+    })
+}
+
+// sets the balance of a currency in a balance map, a zero balance removes the currency
+pub(crate) fn set_currency_balance(
+    balances: &mut HashMap<String, usize>,
+    currency: &str,
+    value: usize,
+) {
+    if value == 0 {
+        balances.remove(currency);
+    } else {
+        balances.insert(currency.to_string(), value);
     }
 }
 
@@ -34,7 +388,12 @@ impl Clone for AccountTransfer {
     fn clone(&self) -> Self {
         Self {
             name: self.name.clone(),
-            balance: self.balance,
+            balances: self.balances.clone(),
+            min_balance: self.min_balance,
+            frozen: self.frozen,
+            transfer_limit: self.transfer_limit,
+            transferred: self.transferred,
+            held: self.held.clone(),
         }
     }
 }
@@ -62,6 +421,30 @@ pub trait AccountStorage {
     fn fee_account(&self) -> Result<AccountTransfer, Error>;
 
     fn accounts(&self) -> Result<Vec<AccountTransfer>, Error>;
+
+    // calls `f` for every account (including the fee account) without collecting them
+    // Errors: StorageError, any error returned by `f`
+    fn for_each_account(
+        &self,
+        f: &mut dyn FnMut(&AccountTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error>;
+
+    // number of stored accounts (including the fee account)
+    fn count(&self) -> Result<usize, Error>;
+
+    // moves the account stored under `old` to `new` keeping its balance
+    // Errors: AccountNotExists, AccountAlreadyExists, StorageError
+    fn rename_account(&mut self, old: String, new: String) -> Result<AccountTransfer, Error>;
+
+    // removes the account, returns its last data
+    // Errors: AccountNotExists, StorageError
+    fn delete_account(&mut self, name: String) -> Result<AccountTransfer, Error>;
+
+    // persists buffered changes (no-op for storages that write through)
+    // Errors: StorageError
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 pub trait TransactionStorage {
@@ -69,16 +452,86 @@ pub trait TransactionStorage {
         &mut self,
         account_name: String,
         action: TransactionAction,
+    ) -> Result<TransactionTransfer, Error> {
+        self.create_transaction_with_memo(account_name, action, None)
+    }
+    // same as create_transaction, `memo` is stored in the transaction memo field
+    fn create_transaction_with_memo(
+        &mut self,
+        account_name: String,
+        action: TransactionAction,
+        memo: Option<String>,
+    ) -> Result<TransactionTransfer, Error> {
+        self.create_transaction_in(account_name, action, memo, None)
+    }
+    // same as create_transaction_with_memo, the amounts of the action are in
+    // `currency` (None - DEFAULT_CURRENCY)
+    fn create_transaction_in(
+        &mut self,
+        account_name: String,
+        action: TransactionAction,
+        memo: Option<String>,
+        currency: Option<String>,
     ) -> Result<TransactionTransfer, Error>;
+    // all transactions in no particular order
     fn transactions(&self) -> Result<Vec<TransactionTransfer>, Error>;
+    // returns a page of transactions ordered by id
+    // (an offset past the end returns an empty vec)
+    fn transactions_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error>;
+    // transactions with from_id <= id <= to_id ordered by id
+    // (an empty vec for an inverted range)
+    fn transactions_between(
+        &self,
+        from_id: usize,
+        to_id: usize,
+    ) -> Result<Vec<TransactionTransfer>, Error>;
     fn account_transactions(&self, account_name: String)
         -> Result<Vec<TransactionTransfer>, Error>;
-    fn transaction_by_id(&self, id: usize) -> Result<TransactionTransfer, Error>;
+    // calls `f` for every account transaction in id order without collecting them
+    // Errors: AccountNotExists, any error returned by `f`
+    fn for_each_account_transaction(
+        &self,
+        account_name: String,
+        f: &mut dyn FnMut(TransactionTransfer) -> Result<(), Error>,
+    ) -> Result<(), Error>;
+    // Errors: TransactionNotExists, StorageError
+    fn transaction_by_id(&self, id: impl Into<TransactionId>)
+        -> Result<TransactionTransfer, Error>;
+    // transaction with the smallest id (None for an empty storage)
+    fn first_transaction(&self) -> Result<Option<TransactionTransfer>, Error>;
+    // transaction with the largest id (None for an empty storage)
+    fn last_transaction(&self) -> Result<Option<TransactionTransfer>, Error>;
+    fn transaction_count(&self) -> Result<usize, Error>;
+    // number of the account transactions (0 for an unknown account)
+    fn account_transaction_count(&self, account_name: String) -> Result<usize, Error>;
+    // stores an already existing transaction keeping its id and creation time
+    // (used to restore a storage from a snapshot)
+    fn restore_transaction(&mut self, tr: TransactionTransfer) -> Result<(), Error>;
+    // rewrites `account_name` of the account transactions and transfer
+    // counterparties from `old` to `new`
+    // Errors: StorageError
+    fn rename_account_references(&mut self, old: String, new: String) -> Result<(), Error>;
+    // persists buffered changes (no-op for storages that write through)
+    // Errors: StorageError
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+fn write_memo(f: &mut std::fmt::Formatter<'_>, memo: Option<&str>) -> std::fmt::Result {
+    match memo {
+        Some(memo) => write!(f, ", Memo: {memo}"),
+        None => Ok(()),
+    }
 }
 
 impl Display for TransactionTransfer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.action {
+        match &self.action {
             TransactionAction::Registration => {
                 write!(f, "ID: {}, Action: {:?}", self.id, self.action)
             }
@@ -87,13 +540,72 @@ impl Display for TransactionTransfer {
                     f,
                     "ID: {}, Action: {:?}, Amount: {}",
                     self.id, self.action, amount
-                )
+                )?;
+                write_memo(f, self.memo())
             }
             TransactionAction::Decrement(amount) => {
                 write!(
                     f,
                     "ID: {}, Action: {:?}, Amount: {}",
                     self.id, self.action, amount
+                )?;
+                write_memo(f, self.memo())
+            }
+            TransactionAction::TransferOut { to, value, fee, .. } => {
+                write!(
+                    f,
+                    "ID: {}, Action: Transfer to {}, Amount: {}, Fee: {}",
+                    self.id, to, value, fee
+                )?;
+                write_memo(f, self.action.memo())
+            }
+            TransactionAction::TransferIn { from, value, .. } => {
+                write!(
+                    f,
+                    "ID: {}, Action: Transfer from {}, Amount: {}",
+                    self.id, from, value
+                )?;
+                write_memo(f, self.action.memo())
+            }
+            TransactionAction::External {
+                direction,
+                amount,
+                reference,
+            } => {
+                write!(
+                    f,
+                    "ID: {}, Action: External {:?}, Amount: {}, Reference: {}",
+                    self.id, direction, amount, reference
+                )
+            }
+            TransactionAction::Hold(amount) => {
+                write!(f, "ID: {}, Action: Hold, Amount: {}", self.id, amount)
+            }
+            TransactionAction::Release { hold_id, amount } => {
+                write!(
+                    f,
+                    "ID: {}, Action: Release of {}, Amount: {}",
+                    self.id, hold_id, amount
+                )
+            }
+            TransactionAction::Reversal { tr_id } => {
+                write!(f, "ID: {}, Action: Reversal of {}", self.id, tr_id)
+            }
+            TransactionAction::Renamed { from } => {
+                write!(f, "ID: {}, Action: Renamed from {}", self.id, from)
+            }
+            TransactionAction::Merged { from } => {
+                write!(f, "ID: {}, Action: Merged {}", self.id, from)
+            }
+            TransactionAction::Closed => write!(f, "ID: {}, Action: Closed", self.id),
+            TransactionAction::Fee {
+                transfer_id,
+                amount,
+            } => {
+                write!(
+                    f,
+                    "ID: {}, Action: Fee of {}, Amount: {}",
+                    self.id, transfer_id, amount
                 )
             }
         }
@@ -1,12 +1,98 @@
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
+use uuid::Uuid;
+
 use super::storage::{TransactionAction, TransactionTransfer};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Transaction {
     pub id: usize,
     pub action: TransactionAction,
     pub account_name: String,
+    pub created_at: u64,
+    pub memo: Option<String>,
+    pub uuid: Option<Uuid>,
+    // None - DEFAULT_CURRENCY
+    pub currency: Option<String>,
+}
+
+// net effect of a single event on an account balance
+#[derive(Debug, PartialEq, Eq)]
+pub struct NetTransaction {
+    pub id: usize,
+    pub delta: i128,
+}
+
+// statement row: a transaction and the account balance in its currency right after it
+#[derive(Debug, PartialEq)]
+pub struct StatementLine {
+    pub id: usize,
+    pub action: TransactionAction,
+    pub currency: String,
+    pub balance_after: usize,
+}
+
+// totals over all transactions in one currency; each transfer is counted once (by its
+// sender side) and its fee leg only as a fee
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Aggregates {
+    // deposits (including external ones) into client accounts
+    pub total_added: usize,
+    // withdrawals (including external ones) from client accounts
+    pub total_withdrawn: usize,
+    // values moved between accounts (without fees)
+    pub total_transferred: usize,
+    // fees charged for transfers
+    pub total_fees: usize,
+}
+
+// stored account balance that differs from the balance replayed from its log
+#[derive(Debug, PartialEq, Eq)]
+pub struct BalanceDiscrepancy {
+    pub account_name: String,
+    pub currency: String,
+    pub stored: usize,
+    pub computed: i128,
+}
+
+// result of Bank::audit_integrity
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    // accounts sorted by name
+    pub discrepancies: Vec<BalanceDiscrepancy>,
+    // sum of the fees of all recorded transfers per currency (currencies without
+    // fees are left out)
+    pub recorded_fees: BTreeMap<String, usize>,
+    // fee account balance per currency (zero balances are left out)
+    pub fee_balances: BTreeMap<String, usize>,
+}
+
+impl BalanceDiscrepancy {
+    // stored - computed
+    pub fn delta(&self) -> i128 {
+        self.stored as i128 - self.computed
+    }
+}
+
+impl IntegrityReport {
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancies.is_empty() && self.recorded_fees == self.fee_balances
+    }
+}
+
+impl From<Transaction> for TransactionTransfer {
+    fn from(value: Transaction) -> Self {
+        TransactionTransfer {
+            id: value.id,
+            action: value.action,
+            account_name: value.account_name,
+            created_at: value.created_at,
+            memo: value.memo,
+            uuid: value.uuid,
+            currency: value.currency,
+        }
+    }
 }
 
 impl From<TransactionTransfer> for Transaction {
@@ -15,6 +101,10 @@ impl From<TransactionTransfer> for Transaction {
             id: value.id,
             action: value.action,
             account_name: value.account_name,
+            created_at: value.created_at,
+            memo: value.memo,
+            uuid: value.uuid,
+            currency: value.currency,
         }
     }
 }
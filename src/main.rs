@@ -1,4 +1,5 @@
 use rust_bank::bank::{
+    fee::FlatFee,
     implements::memory::storage::{MemAccountStorage, MemTransactionStorage},
     Bank,
 };
@@ -10,7 +11,7 @@ fn main() {
     let mut bank = Bank::new(
         MemAccountStorage::new().unwrap(),
         MemTransactionStorage::new(),
-        Some(tr_fee),
+        Some(Box::new(FlatFee(tr_fee))),
     );
 
     // create acc
@@ -32,7 +33,7 @@ fn main() {
     println!(
         "Before transaction. Fee: {tr_fee}. Amount: {tr_amount} Account from: {acc}, to {to_acc}"
     );
-    let _ = bank.make_transaction(&mut acc, &mut to_acc, tr_amount);
+    let _ = bank.make_transaction(&mut acc, &mut to_acc, tr_amount, None, None);
     println!(
         "After transaction. Fee: {tr_fee}. Amount: {tr_amount} Account from: {acc}, to {to_acc}"
     );
@@ -54,7 +55,7 @@ fn main() {
     let mut bank_sec = Bank::new(
         MemAccountStorage::new().unwrap(),
         MemTransactionStorage::new(),
-        Some(tr_fee),
+        Some(Box::new(FlatFee(tr_fee))),
     );
 
     println!("Show accs in first bank:");
@@ -80,4 +81,7 @@ fn main() {
         .into_iter()
         .for_each(|acc| println!("Acc: {acc}"));
     println!("----------------------------");
+
+    bank.flush().unwrap();
+    bank_sec.flush().unwrap();
 }